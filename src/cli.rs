@@ -0,0 +1,708 @@
+//! Module containing the headless command line interface of image_sieve
+
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::item_sort_list::{
+    DeletionPolicy, DirectoryNames, HashAlgorithm, ItemList, MotionPhotos, PathTemplate,
+    SieveEvent, SieveJournal, SieveMethod, HASH_SIZES, MAX_SCORE,
+};
+use crate::persistence::json::{
+    get_archive_index_filename, get_journal_filename, get_project_filename, get_settings_filename,
+    load_archive_index, load_latest_journal, JsonPersistence,
+};
+use crate::persistence::settings::Settings;
+use crate::synchronize;
+
+const USAGE: &str = "Usage: image_sieve [<command> <source directory> [options]]
+
+Without a command, the graphical user interface is started.
+
+Commands:
+  scan       Scan the source directory and update its project file
+  list       Scan the source directory and list all items with their state
+  similar    Scan the source directory and list groups of similar items
+  sieve      Scan the source directory and sieve the items to the target directory
+  undo       Undo the last sieve of the source directory or to the target directory
+  help       Show this help
+
+Options (default values are taken from the settings of the graphical user interface):
+  --target <directory>               Target directory of the sieve command
+  --method <method>                  Sieve method: Copy, Move, MoveAndDelete or Delete, Copy if not given
+  --directory-names <names>          Target directory names: YearAndMonth, Year, YearMonthAndDay,
+                                     YearAndQuarter, YearAndMonthInSubdirectory or Custom
+  --template <template>              Custom template of the target paths, for example
+                                     \"{year}/{month:02}-{event}/{camera_model}/{filename}\"
+  --rename <pattern>                 Rename the sieved files, for example \"{date}_{time}_{seq}.{ext}\",
+                                     an empty pattern keeps the file names
+  --timestamp-difference <seconds>   Maximum timestamp difference of similar items
+  --similarity / --no-similarity     Enable or disable the image similarity calculation
+  --hash-algorithm <algorithm>       Image similarity hash: Mean, Gradient, DoubleGradient, Dct or Blockhash
+  --hash-size <size>                 Image similarity hash size: 8, 16 or 32
+  --similarity-threshold <score>     Minimum similarity score of similar items from 0 to 100
+  --keep-best                        Keep the best shot of each group of similar items and discard the others
+  --deletion-policy <policy>         How discarded items are deleted: Delete, Trash (Linux only) or Quarantine
+  --quarantine <directory>           Quarantine directory of the Quarantine deletion policy
+  --retention-days <days>            Days to keep items in the quarantine directory, 0 keeps them forever
+  --motion-photos <mode>             How motion photos and live photos are sieved: Keep, ExtractVideo or
+                                     StillOnly
+  --dry-run                          Only print the operations the sieve command would perform
+  --export <file>                    Write the planned operations of a dry run to a JSON file";
+
+/// Commands supported on the command line
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Help,
+    Scan,
+    List,
+    Similar,
+    Sieve,
+    Undo,
+}
+
+/// Parsed command line arguments, options that are not given are taken from the settings
+#[derive(Debug, PartialEq, Eq)]
+struct Arguments {
+    command: Command,
+    source: PathBuf,
+    target: Option<String>,
+    method: Option<SieveMethod>,
+    directory_names: Option<DirectoryNames>,
+    template: Option<String>,
+    rename_pattern: Option<String>,
+    timestamp_difference: Option<i64>,
+    use_hash: Option<bool>,
+    hash_algorithm: Option<HashAlgorithm>,
+    hash_size: Option<u32>,
+    similarity_threshold: Option<u32>,
+    deletion_policy: Option<DeletionPolicy>,
+    quarantine_directory: Option<String>,
+    retention_days: Option<u32>,
+    motion_photos: Option<MotionPhotos>,
+    dry_run: bool,
+    export: Option<PathBuf>,
+    keep_best: bool,
+}
+
+/// Runs image_sieve without GUI using the given command line arguments (excluding the program name) and returns
+/// the exit code of the process
+pub fn run(args: &[String]) -> i32 {
+    let arguments = match parse_arguments(args) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return 2;
+        }
+    };
+    if arguments.command == Command::Help {
+        println!("{}", USAGE);
+        return 0;
+    }
+
+    let mut settings: Settings =
+        JsonPersistence::load(&get_settings_filename()).unwrap_or_else(Settings::new);
+    arguments.apply_to(&mut settings);
+
+    // Undo before scanning, otherwise the items moved away by the last sieve would be dropped from the project
+    if arguments.command == Command::Undo {
+        return undo(&arguments.source, &settings);
+    }
+
+    let mut item_list = ItemList::new();
+    if let Err(exit_code) = scan(&arguments.source, &mut item_list) {
+        return exit_code;
+    }
+
+    let exit_code = match arguments.command {
+        Command::Scan => {
+            println!(
+                "{} items found in {}",
+                item_list.items.len(),
+                item_list.path.display()
+            );
+            0
+        }
+        Command::List => {
+            for item in &item_list.items {
+                println!("{}", item);
+            }
+            0
+        }
+        Command::Similar => similar(&mut item_list, &settings, arguments.keep_best),
+        Command::Sieve if arguments.dry_run => plan_sieve(&item_list, &settings, &arguments),
+        Command::Sieve => sieve(&item_list, &settings),
+        Command::Help | Command::Undo => unreachable!(),
+    };
+
+    // Listing and planning must not change anything, not even the project file
+    let changed = match arguments.command {
+        Command::Scan | Command::Similar => true,
+        Command::Sieve => !arguments.dry_run,
+        _ => false,
+    };
+    if changed {
+        JsonPersistence::save(&get_project_filename(&item_list.path), &item_list);
+    }
+    exit_code
+}
+
+impl Arguments {
+    /// Overwrite the settings with the options given on the command line
+    fn apply_to(&self, settings: &mut Settings) {
+        if let Some(target) = &self.target {
+            settings.target_directory = target.clone();
+        }
+        // Moving and deleting files must be requested explicitly, so the method of the settings is not used
+        settings.sieve_method = self.method.clone().unwrap_or(SieveMethod::Copy);
+        if let Some(directory_names) = &self.directory_names {
+            settings.sieve_directory_names = Some(directory_names.clone());
+        }
+        if let Some(template) = &self.template {
+            settings.sieve_directory_names = Some(DirectoryNames::Custom);
+            settings.sieve_template = template.clone();
+        }
+        if let Some(rename_pattern) = &self.rename_pattern {
+            settings.rename_pattern = rename_pattern.clone();
+        }
+        if let Some(timestamp_difference) = self.timestamp_difference {
+            settings.use_timestamps = true;
+            settings.timestamp_max_diff = timestamp_difference;
+        }
+        if let Some(use_hash) = self.use_hash {
+            settings.use_hash = use_hash;
+        }
+        if let Some(hash_algorithm) = self.hash_algorithm {
+            settings.hash_algorithm = hash_algorithm;
+        }
+        if let Some(hash_size) = self.hash_size {
+            settings.hash_size = hash_size;
+        }
+        if let Some(similarity_threshold) = self.similarity_threshold {
+            settings.similarity_threshold = similarity_threshold;
+        }
+        if let Some(deletion_policy) = &self.deletion_policy {
+            settings.deletion_policy = deletion_policy.clone();
+        }
+        if let Some(quarantine_directory) = &self.quarantine_directory {
+            settings.quarantine_directory = quarantine_directory.clone();
+        }
+        if let Some(retention_days) = self.retention_days {
+            settings.quarantine_retention_days = retention_days;
+        }
+        if let Some(motion_photos) = self.motion_photos {
+            settings.motion_photos = motion_photos;
+        }
+    }
+}
+
+/// Parse the command line arguments
+fn parse_arguments(args: &[String]) -> Result<Arguments, String> {
+    let mut args = args.iter();
+    let command = match args.next().map(String::as_str) {
+        Some("scan") => Command::Scan,
+        Some("list") => Command::List,
+        Some("similar") => Command::Similar,
+        Some("sieve") => Command::Sieve,
+        Some("undo") => Command::Undo,
+        Some("help") | Some("--help") | Some("-h") => Command::Help,
+        Some(command) => return Err(format!("Unknown command {}", command)),
+        None => return Err(String::from("No command given")),
+    };
+    let mut arguments = Arguments {
+        command,
+        source: PathBuf::new(),
+        target: None,
+        method: None,
+        directory_names: None,
+        template: None,
+        rename_pattern: None,
+        timestamp_difference: None,
+        use_hash: None,
+        hash_algorithm: None,
+        hash_size: None,
+        similarity_threshold: None,
+        deletion_policy: None,
+        quarantine_directory: None,
+        retention_days: None,
+        motion_photos: None,
+        dry_run: false,
+        export: None,
+        keep_best: false,
+    };
+    if arguments.command == Command::Help {
+        return Ok(arguments);
+    }
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => arguments.target = Some(option_value(arg, args.next())?.to_string()),
+            "--method" => {
+                let value = option_value(arg, args.next())?;
+                arguments.method = Some(
+                    SieveMethod::from_str(value)
+                        .map_err(|_| format!("Invalid sieve method {}", value))?,
+                );
+            }
+            "--directory-names" => {
+                let value = option_value(arg, args.next())?;
+                arguments.directory_names = Some(
+                    DirectoryNames::from_str(value)
+                        .map_err(|_| format!("Invalid directory names {}", value))?,
+                );
+            }
+            "--template" => {
+                let value = option_value(arg, args.next())?;
+                PathTemplate::from_str(value)
+                    .map_err(|error| format!("Invalid template {}: {}", value, error))?;
+                arguments.template = Some(value.to_string());
+            }
+            "--rename" => {
+                let value = option_value(arg, args.next())?;
+                PathTemplate::check_rename_pattern(value)
+                    .map_err(|error| format!("Invalid rename pattern {}: {}", value, error))?;
+                arguments.rename_pattern = Some(value.to_string());
+            }
+            "--timestamp-difference" => {
+                let value = option_value(arg, args.next())?;
+                arguments.timestamp_difference = Some(
+                    value
+                        .parse::<i64>()
+                        .map_err(|_| format!("Invalid timestamp difference {}", value))?,
+                );
+            }
+            "--similarity" => arguments.use_hash = Some(true),
+            "--no-similarity" => arguments.use_hash = Some(false),
+            "--hash-algorithm" => {
+                let value = option_value(arg, args.next())?;
+                arguments.hash_algorithm = Some(
+                    HashAlgorithm::from_str(value)
+                        .map_err(|_| format!("Invalid hash algorithm {}", value))?,
+                );
+            }
+            "--hash-size" => {
+                let value = option_value(arg, args.next())?;
+                arguments.hash_size = Some(
+                    value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|size| HASH_SIZES.contains(size))
+                        .ok_or_else(|| format!("Invalid hash size {}", value))?,
+                );
+            }
+            "--similarity-threshold" => {
+                let value = option_value(arg, args.next())?;
+                arguments.similarity_threshold = Some(
+                    value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|threshold| *threshold <= MAX_SCORE)
+                        .ok_or_else(|| format!("Invalid similarity threshold {}", value))?,
+                );
+            }
+            "--deletion-policy" => {
+                let value = option_value(arg, args.next())?;
+                arguments.deletion_policy = Some(
+                    DeletionPolicy::from_str(value)
+                        .map_err(|_| format!("Invalid deletion policy {}", value))?,
+                );
+            }
+            "--quarantine" => {
+                arguments.quarantine_directory = Some(option_value(arg, args.next())?.to_string())
+            }
+            "--retention-days" => {
+                let value = option_value(arg, args.next())?;
+                arguments.retention_days = Some(
+                    value
+                        .parse::<u32>()
+                        .map_err(|_| format!("Invalid retention days {}", value))?,
+                );
+            }
+            "--motion-photos" => {
+                let value = option_value(arg, args.next())?;
+                arguments.motion_photos = Some(
+                    MotionPhotos::from_str(value)
+                        .map_err(|_| format!("Invalid motion photo mode {}", value))?,
+                );
+            }
+            "--dry-run" => arguments.dry_run = true,
+            "--keep-best" => arguments.keep_best = true,
+            "--export" => {
+                arguments.export = Some(PathBuf::from(option_value(arg, args.next())?));
+                arguments.dry_run = true;
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if arguments.source.as_os_str().is_empty() => arguments.source = PathBuf::from(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+
+    if arguments.source.as_os_str().is_empty() {
+        Err(String::from("No source directory given"))
+    } else {
+        Ok(arguments)
+    }
+}
+
+/// Get the value of an option or an error if it is missing
+fn option_value<'a>(option: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    value
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing value for option {}", option))
+}
+
+/// Synchronize the item list with the source directory, reporting the progress on stderr. If the source is no
+/// directory, the error is reported and the exit code is returned.
+fn scan(source: &Path, item_list: &mut ItemList) -> Result<(), i32> {
+    if !source.is_dir() {
+        eprintln!("Source directory {} not found", source.display());
+        return Err(2);
+    }
+    synchronize::scan_files(
+        source,
+        item_list,
+        |progress| eprintln!("{}", progress),
+        || Ok(()),
+    )
+    .map_err(|_| 1)
+}
+
+/// Find similar items and print each item with a list of its similar items and their similarity score. Optionally,
+/// the best shot of each group of similar items is kept and the others are discarded.
+fn similar(item_list: &mut ItemList, settings: &Settings, keep_best: bool) -> i32 {
+    if settings.use_hash {
+        eprintln!("Calculating image similarities...");
+        let shared_item_list = Arc::new(Mutex::new(item_list.clone()));
+        synchronize::calculate_similar_hashes(
+            shared_item_list.clone(),
+            settings,
+            |_| {},
+            || Ok(()),
+        )
+        .ok();
+        item_list.clone_from(&shared_item_list.lock().unwrap());
+    } else {
+        item_list.find_similar(&settings.similarity_config());
+    }
+    if keep_best {
+        eprintln!("Selecting best shots...");
+        let shared_item_list = Arc::new(Mutex::new(item_list.clone()));
+        let groups = synchronize::keep_best_shots(shared_item_list.clone(), |_| {}, || Ok(()));
+        item_list.clone_from(&shared_item_list.lock().unwrap());
+        eprintln!("Best shots selected in {} groups", groups.unwrap_or(0));
+    }
+
+    let mut printed = vec![false; item_list.items.len()];
+    let mut groups = 0;
+    for (index, item) in item_list.items.iter().enumerate() {
+        if printed[index] || item.get_similars().is_empty() {
+            continue;
+        }
+        println!("{}", item);
+        printed[index] = true;
+        for similar in item.get_similars() {
+            println!("  {}% {}", similar.score, item_list.items[similar.index]);
+            printed[similar.index] = true;
+        }
+        println!();
+        groups += 1;
+    }
+    println!("{} groups of similar items found", groups);
+    0
+}
+
+/// Check that a target directory is given if the sieve method needs one
+fn check_target_directory(settings: &Settings) -> Result<(), i32> {
+    if settings.sieve_method != SieveMethod::Delete && settings.target_directory.is_empty() {
+        eprintln!("No target directory given\n\n{}", USAGE);
+        Err(2)
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that the deletion policy can be used on this platform, like the trash which is only supported on Linux
+fn check_deletion_policy(settings: &Settings) -> Result<(), i32> {
+    settings.check_deletion_policy().map_err(|error| {
+        eprintln!("Invalid deletion policy {:?}: {}", settings.deletion_policy, error);
+        2
+    })
+}
+
+/// Get the template of the target paths, an invalid custom template from the settings is reported
+fn get_path_template(settings: &Settings) -> Result<PathTemplate, i32> {
+    settings.path_template().map_err(|error| {
+        eprintln!("Invalid template {}: {}", settings.sieve_template, error);
+        2
+    })
+}
+
+/// Print the operations a sieve would perform without changing any file and optionally export them
+fn plan_sieve(item_list: &ItemList, settings: &Settings, arguments: &Arguments) -> i32 {
+    let path_template = match check_target_directory(settings)
+        .and(check_deletion_policy(settings))
+        .and(get_path_template(settings))
+    {
+        Ok(path_template) => path_template,
+        Err(exit_code) => return exit_code,
+    };
+    let plan = item_list.plan_sieve(
+        Path::new(&settings.target_directory),
+        settings.sieve_method.clone(),
+        &path_template,
+        settings.motion_photos,
+        &load_archive_index(Path::new(&settings.target_directory)),
+    );
+    for operation in &plan.operations {
+        println!("{}", operation);
+    }
+    println!(
+        "{} operations planned, no files were changed",
+        plan.operations.len()
+    );
+    if let Some(export) = &arguments.export {
+        JsonPersistence::save(export, &plan);
+        println!("Planned operations written to {}", export.display());
+    }
+    0
+}
+
+/// Sieve the item list to the target directory and return an error exit code if any file failed
+fn sieve(item_list: &ItemList, settings: &Settings) -> i32 {
+    let path_template = match check_target_directory(settings)
+        .and(check_deletion_policy(settings))
+        .and(get_path_template(settings))
+    {
+        Ok(path_template) => path_template,
+        Err(exit_code) => return exit_code,
+    };
+    let journal_filename = get_journal_filename(&item_list.journal_directory(
+        Path::new(&settings.target_directory),
+        &settings.sieve_method,
+    ));
+    // Only the last sieve can be undone, so finally delete the files staged by the previous one
+    let previous_journal: Option<SieveJournal> = JsonPersistence::load(&journal_filename);
+    if let Some(previous_journal) = previous_journal {
+        previous_journal.purge();
+    }
+    let target_directory = Path::new(&settings.target_directory);
+    let mut archive_index = load_archive_index(target_directory);
+    let failed = Cell::new(0);
+    let journal = item_list.sieve(
+        target_directory,
+        settings.sieve_method.clone(),
+        &path_template,
+        settings.motion_photos,
+        &settings.deletion(),
+        &mut archive_index,
+        |event: SieveEvent| print_event(event, &failed),
+    );
+    JsonPersistence::save(&journal_filename, &journal);
+    if !settings.target_directory.is_empty() {
+        JsonPersistence::save(
+            &get_archive_index_filename(target_directory),
+            &archive_index,
+        );
+    }
+    if failed.get() > 0 { 1 } else { 0 }
+}
+
+/// Undo the last sieve to the target directory or in the source directory
+fn undo(source: &Path, settings: &Settings) -> i32 {
+    let directories = [
+        PathBuf::from(&settings.target_directory),
+        source.to_path_buf(),
+    ];
+    let Some((journal_filename, journal)) = load_latest_journal(&directories) else {
+        eprintln!("No sieve to undo found");
+        return 1;
+    };
+    let failed = Cell::new(0);
+    let remaining = journal.undo(|event: SieveEvent| print_event(event, &failed));
+    if remaining.is_empty() {
+        std::fs::remove_file(journal_filename).ok();
+    } else {
+        JsonPersistence::save(&journal_filename, &remaining);
+    }
+    if failed.get() > 0 { 1 } else { 0 }
+}
+
+/// Print an event of a sieve process, errors are printed to stderr and the number of failed operations is stored
+/// when the process finished
+fn print_event(event: SieveEvent, failed: &Cell<usize>) {
+    match &event {
+        SieveEvent::Failed { .. } => eprintln!("{}", event),
+        SieveEvent::Finished { statistics } => {
+            failed.set(statistics.failed);
+            println!("{}", event);
+        }
+        _ => println!("{}", event),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_sort_list::Deletion;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| String::from(*arg)).collect()
+    }
+
+    #[test]
+    fn test_parse_arguments() {
+        let arguments = parse_arguments(&to_args(&["scan", "source"])).unwrap();
+        assert_eq!(arguments.command, Command::Scan);
+        assert_eq!(arguments.source, PathBuf::from("source"));
+        assert_eq!(arguments.target, None);
+        assert_eq!(arguments.method, None);
+
+        let arguments = parse_arguments(&to_args(&[
+            "sieve",
+            "--method",
+            "moveanddelete",
+            "source",
+            "--target",
+            "target",
+            "--directory-names",
+            "YearAndQuarter",
+            "--timestamp-difference",
+            "10",
+            "--no-similarity",
+        ]))
+        .unwrap();
+        assert_eq!(arguments.command, Command::Sieve);
+        assert_eq!(arguments.source, PathBuf::from("source"));
+        assert_eq!(arguments.target, Some(String::from("target")));
+        assert_eq!(arguments.method, Some(SieveMethod::MoveAndDelete));
+        assert_eq!(
+            arguments.directory_names,
+            Some(DirectoryNames::YearAndQuarter)
+        );
+        assert_eq!(arguments.timestamp_difference, Some(10));
+        assert_eq!(arguments.use_hash, Some(false));
+        assert!(!arguments.dry_run);
+
+        let arguments =
+            parse_arguments(&to_args(&["sieve", "source", "--export", "plan.json"])).unwrap();
+        assert!(arguments.dry_run);
+        assert_eq!(arguments.export, Some(PathBuf::from("plan.json")));
+
+        let arguments = parse_arguments(&to_args(&["similar", "source", "--keep-best"])).unwrap();
+        assert_eq!(arguments.command, Command::Similar);
+        assert!(arguments.keep_best);
+
+        assert_eq!(
+            parse_arguments(&to_args(&["--help"])).unwrap().command,
+            Command::Help
+        );
+        assert_eq!(
+            parse_arguments(&to_args(&["undo", "source"]))
+                .unwrap()
+                .command,
+            Command::Undo
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_arguments() {
+        assert!(parse_arguments(&to_args(&[])).is_err());
+        assert!(parse_arguments(&to_args(&["unknown", "source"])).is_err());
+        assert!(parse_arguments(&to_args(&["scan"])).is_err());
+        assert!(parse_arguments(&to_args(&["scan", "source", "other"])).is_err());
+        assert!(parse_arguments(&to_args(&["scan", "source", "--unknown"])).is_err());
+        assert!(parse_arguments(&to_args(&["sieve", "source", "--method"])).is_err());
+        assert!(parse_arguments(&to_args(&["sieve", "source", "--method", "zip"])).is_err());
+        assert!(
+            parse_arguments(&to_args(&["sieve", "source", "--template", "{unknown}"])).is_err()
+        );
+        assert!(
+            parse_arguments(&to_args(&["sieve", "source", "--rename", "{year}/{seq}"])).is_err()
+        );
+        assert!(
+            parse_arguments(&to_args(&["similar", "source", "--hash-algorithm", "md5"])).is_err()
+        );
+        assert!(parse_arguments(&to_args(&["similar", "source", "--hash-size", "12"])).is_err());
+        assert!(
+            parse_arguments(&to_args(&["similar", "source", "--similarity-threshold", "101"]))
+                .is_err()
+        );
+        assert!(
+            parse_arguments(&to_args(&[
+                "similar",
+                "source",
+                "--timestamp-difference",
+                "x"
+            ]))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_missing_source() {
+        let mut item_list = ItemList::new();
+        assert_eq!(scan(Path::new("tests/missing"), &mut item_list), Err(2));
+        assert_eq!(scan(Path::new("tests/test.jpg"), &mut item_list), Err(2));
+        assert_eq!(run(&to_args(&["list", "tests/missing"])), 2);
+    }
+
+    #[test]
+    fn test_apply_to_settings() {
+        let arguments = parse_arguments(&to_args(&[
+            "sieve",
+            "source",
+            "--target",
+            "target",
+            "--method",
+            "Move",
+            "--similarity",
+            "--deletion-policy",
+            "quarantine",
+            "--quarantine",
+            "quarantine",
+            "--retention-days",
+            "7",
+            "--template",
+            "{year}/{camera_model}",
+            "--rename",
+            "{date}_{seq}",
+            "--hash-algorithm",
+            "dct",
+            "--hash-size",
+            "32",
+            "--similarity-threshold",
+            "50",
+            "--motion-photos",
+            "stillonly",
+        ]))
+        .unwrap();
+        let mut settings = Settings::new();
+        arguments.apply_to(&mut settings);
+        assert_eq!(settings.target_directory, "target");
+        assert_eq!(settings.sieve_method, SieveMethod::Move);
+        assert!(settings.use_hash);
+        assert_eq!(
+            settings.deletion(),
+            Deletion {
+                policy: DeletionPolicy::Quarantine,
+                quarantine_directory: PathBuf::from("quarantine"),
+                retention_days: 7,
+            }
+        );
+        assert_eq!(settings.sieve_directory_names, Some(DirectoryNames::Custom));
+        assert_eq!(settings.sieve_template, "{year}/{camera_model}");
+        assert_eq!(settings.rename_pattern, "{date}_{seq}");
+        assert_eq!(settings.hash_algorithm, HashAlgorithm::Dct);
+        assert_eq!(settings.hash_size, 32);
+        assert_eq!(settings.similarity_threshold, 50);
+        assert_eq!(settings.motion_photos, MotionPhotos::StillOnly);
+        assert!(settings.path_template().is_ok());
+
+        // The method of the settings is never used
+        let arguments = parse_arguments(&to_args(&["sieve", "source"])).unwrap();
+        settings.sieve_method = SieveMethod::Delete;
+        arguments.apply_to(&mut settings);
+        assert_eq!(settings.sieve_method, SieveMethod::Copy);
+    }
+}
@@ -6,7 +6,7 @@ use std::{
 use slint::{Model, SharedString};
 
 use crate::{
-    item_sort_list::{self, parse_date, ItemList},
+    item_sort_list::{self, clock_offset_to_string, parse_clock_offset, parse_date, ItemList},
     main_window,
 };
 
@@ -15,6 +15,7 @@ use super::helper;
 pub struct EventsController {
     item_list: Arc<Mutex<ItemList>>,
     events_model: Rc<slint::VecModel<main_window::Event>>,
+    camera_clocks_model: Rc<slint::VecModel<main_window::CameraClock>>,
 }
 
 impl EventsController {
@@ -22,6 +23,7 @@ impl EventsController {
         Self {
             item_list,
             events_model: Rc::new(slint::VecModel::<main_window::Event>::default()),
+            camera_clocks_model: Rc::new(slint::VecModel::<main_window::CameraClock>::default()),
         }
     }
 
@@ -42,6 +44,18 @@ impl EventsController {
                 self.events_model.set_row_data(index, _event);
             }
         }
+        // Camera clocks model
+        let camera_clocks: Vec<main_window::CameraClock> = item_list
+            .cameras()
+            .into_iter()
+            .map(|camera| main_window::CameraClock {
+                offset: SharedString::from(clock_offset_to_string(
+                    item_list.get_clock_offset(&camera),
+                )),
+                camera: SharedString::from(camera),
+            })
+            .collect();
+        self.camera_clocks_model.set_vec(camera_clocks);
     }
 
     /// Add an event to the item list and to the events model and sorts the lists
@@ -89,14 +103,51 @@ impl EventsController {
         self.events_model.remove(index as usize);
     }
 
+    /// Sets the clock offset of a camera from the camera clocks model. If the offset has an invalid format, an
+    /// error message is returned.
+    pub fn set_clock_offset(&mut self, index: i32, offset: &str) -> SharedString {
+        let Some(mut camera_clock) = self.camera_clocks_model.row_data(index as usize) else {
+            return SharedString::new();
+        };
+        match parse_clock_offset(offset) {
+            Ok(seconds) => {
+                self.item_list
+                    .lock()
+                    .unwrap()
+                    .set_clock_offset(&camera_clock.camera, seconds);
+                camera_clock.offset = SharedString::from(clock_offset_to_string(seconds));
+                self.camera_clocks_model
+                    .set_row_data(index as usize, camera_clock);
+                SharedString::new()
+            }
+            Err(error) => SharedString::from(error),
+        }
+    }
+
+    /// Sets the flag if corrected dates and orientations are written to sieved images
+    pub fn set_write_metadata(&mut self, write_metadata: bool) {
+        self.item_list.lock().unwrap().write_metadata = write_metadata;
+    }
+
+    /// Gets the flag if corrected dates and orientations are written to sieved images
+    pub fn get_write_metadata(&self) -> bool {
+        self.item_list.lock().unwrap().write_metadata
+    }
+
     /// Returns the contained slint VecModel
     pub fn get_model(&self) -> Rc<slint::VecModel<main_window::Event>> {
         self.events_model.clone()
     }
 
-    /// Clear the events model
+    /// Returns the slint VecModel of the camera clocks
+    pub fn get_camera_clocks_model(&self) -> Rc<slint::VecModel<main_window::CameraClock>> {
+        self.camera_clocks_model.clone()
+    }
+
+    /// Clear the events and camera clocks model
     pub fn clear(&mut self) {
         helper::clear_model(self.events_model.clone());
+        helper::clear_model(self.camera_clocks_model.clone());
     }
 
     /// Check the validity of an event
@@ -335,4 +386,46 @@ mod tests {
         events_controller.clear();
         assert_eq!(events_controller.get_model().row_count(), 0);
     }
+    #[test]
+    fn test_clock_offsets() {
+        let item_list = Arc::new(Mutex::new(ItemList::new()));
+        let mut events_controller = EventsController::new(item_list.clone());
+        {
+            let mut item_list = item_list.lock().unwrap();
+            let mut item = item_sort_list::FileItem::dummy("test.jpg", 0, true);
+            item.set_metadata(item_sort_list::Metadata {
+                camera_model: Some(String::from("Camera")),
+                ..Default::default()
+            });
+            item_list.items.push(item);
+        }
+        events_controller.synchronize();
+        let camera_clocks_model = events_controller.get_camera_clocks_model();
+        assert_eq!(camera_clocks_model.row_count(), 1);
+        assert_eq!(
+            camera_clocks_model.row_data(0).unwrap().camera.as_str(),
+            "Camera"
+        );
+        assert_eq!(
+            camera_clocks_model.row_data(0).unwrap().offset.as_str(),
+            "+00:00:00"
+        );
+
+        assert_eq!(
+            events_controller.set_clock_offset(0, "1:75").as_str(),
+            "Invalid clock offset 1:75, expected [+|-]HH:MM[:SS]"
+        );
+        assert_eq!(events_controller.set_clock_offset(0, "-01:30").as_str(), "");
+        assert_eq!(
+            camera_clocks_model.row_data(0).unwrap().offset.as_str(),
+            "-01:30:00"
+        );
+        assert_eq!(item_list.lock().unwrap().items[0].get_timestamp(), -5400);
+
+        events_controller.set_write_metadata(true);
+        assert!(events_controller.get_write_metadata());
+
+        events_controller.clear();
+        assert_eq!(events_controller.get_camera_clocks_model().row_count(), 0);
+    }
 }
@@ -1,8 +1,10 @@
 use std::{
     rc::Rc,
     sync::{Arc, Mutex},
+    thread,
 };
 
+use chrono::NaiveDateTime;
 use slint::Model;
 
 use crate::{
@@ -17,6 +19,7 @@ pub struct ItemsController {
     item_list: Arc<Mutex<ItemList>>,
     list_model: Rc<slint::VecModel<main_window::ListItem>>,
     similar_items_model: Rc<slint::VecModel<main_window::SortItem>>,
+    details_model: Rc<slint::VecModel<main_window::DetailRow>>,
     image_cache: image_cache::ImageCache,
 }
 
@@ -30,6 +33,7 @@ impl ItemsController {
             item_list,
             list_model: Rc::new(slint::VecModel::<main_window::ListItem>::default()),
             similar_items_model: Rc::new(slint::VecModel::<main_window::SortItem>::default()),
+            details_model: Rc::new(slint::VecModel::<main_window::DetailRow>::default()),
             image_cache,
         }
     }
@@ -44,14 +48,20 @@ impl ItemsController {
         self.similar_items_model.clone()
     }
 
+    /// Gets the slint vec model for the metadata details of the similar items
+    pub fn get_details_model(&self) -> Rc<slint::VecModel<main_window::DetailRow>> {
+        self.details_model.clone()
+    }
+
     /// Clear the list model
     pub fn clear_list(&mut self) {
         helper::clear_model(self.list_model.clone());
     }
 
-    /// Clear the similar items model
+    /// Clear the similar items model and their details
     pub fn clear_similar_items(&mut self) {
         helper::clear_model(self.similar_items_model.clone());
+        helper::clear_model(self.details_model.clone());
     }
 
     /// Gets the index of an item by its path
@@ -80,7 +90,17 @@ impl ItemsController {
                 .unwrap()
                 .local_index as usize;
             let item_list = self.item_list.lock().unwrap();
-            let similars = item_list.items[items_index].get_similars();
+            // Other items of the stack come first, followed by the similar items
+            let mut other_indices: Vec<usize> = item_list.items[items_index]
+                .get_stack()
+                .map(|stack| stack.items.clone())
+                .unwrap_or_default();
+            other_indices.retain(|index| *index != items_index);
+            for similar in item_list.items[items_index].get_similars() {
+                if !other_indices.contains(&similar.index) {
+                    other_indices.push(similar.index);
+                }
+            }
 
             // Clear pending commands in the image cache
             self.image_cache.purge();
@@ -92,22 +112,22 @@ impl ItemsController {
                 0,
                 items_index as i32,
                 true,
-                !similars.is_empty(),
+                !other_indices.is_empty(),
                 window.clone(),
             );
             let sort_image = sort_item_from_file_item(item, &item_list, image);
             self.similar_items_model.push(sort_image);
 
-            // Now add all similar images
+            // Now add all stacked and similar images
             let mut model_index = 1;
-            for image_index in similars {
+            for image_index in &other_indices {
                 let item = &item_list.items[*image_index];
                 let image = self.get_item_image(
                     item,
                     model_index,
                     items_index as i32,
                     false,
-                    !similars.is_empty(),
+                    !other_indices.is_empty(),
                     window.clone(),
                 );
                 let sort_image = sort_item_from_file_item(item, &item_list, image);
@@ -126,27 +146,41 @@ impl ItemsController {
         self.prefetch_images(list_model_index);
     }
 
-    /// Sets the take over state of an item
+    /// Sets the take over state of an item and of all other items in its stack
     pub fn set_take_over(&mut self, local_index: i32, take_over: bool) -> slint::SharedString {
-        let description = {
+        let descriptions: Vec<(i32, slint::SharedString)> = {
             // Change the item_list state
             let mut item_list = self.item_list.lock().unwrap();
-            item_list.items[local_index as usize].set_take_over(take_over);
-            sort_item_description(&item_list.items[local_index as usize], &item_list)
+            item_list
+                .set_take_over(local_index as usize, take_over)
+                .into_iter()
+                .map(|index| {
+                    (
+                        index as i32,
+                        sort_item_description(&item_list.items[index], &item_list),
+                    )
+                })
+                .collect()
         };
         // Update item list model to reflect change in icons in list
         self.update_list_model();
         // And update the take over state in the similar items model
         for count in 0..self.similar_items_model.row_count() {
             let mut item: main_window::SortItem = self.similar_items_model.row_data(count).unwrap();
-            if item.local_index == local_index {
+            if let Some((_, description)) = descriptions
+                .iter()
+                .find(|(index, _)| *index == item.local_index)
+            {
                 item.take_over = take_over;
                 item.text = description.clone();
                 self.similar_items_model.set_row_data(count, item);
-                break;
             }
         }
-        description
+        descriptions
+            .into_iter()
+            .find(|(index, _)| *index == local_index)
+            .map(|(_, description)| description)
+            .unwrap_or_default()
     }
 
     /// Update the texts for all entries in the list model and returns true if the list contains more than one item
@@ -167,9 +201,14 @@ impl ItemsController {
         self.clear_list();
 
         let item_list = self.item_list.lock().unwrap();
+        // Stacks are represented by their first item and companions by their primary item
         let mut filtered_list: Vec<&FileItem> = item_list
             .items
             .iter()
+            .enumerate()
+            .filter(|(index, item)| item.get_stack().is_none_or(|stack| stack.head() == *index))
+            .map(|(_, item)| item)
+            .filter(|item| item.get_primary().is_none())
             .filter(|item| filter_file_items(item, filters))
             .collect();
         filtered_list.sort_unstable_by(|a, b| compare_file_items(a, b, filters));
@@ -184,6 +223,70 @@ impl ItemsController {
         list_len
     }
 
+    /// Corrects the capture time of an item manually. An empty string removes the correction. The items are sorted
+    /// again, so the list has to be filled again and the similarities have to be calculated again. Returns an error
+    /// message if the date cannot be parsed.
+    pub fn set_date(&mut self, local_index: i32, date: &str) -> slint::SharedString {
+        let mut item_list = self.item_list.lock().unwrap();
+        if local_index < 0 || local_index as usize >= item_list.items.len() {
+            return slint::SharedString::from("No image selected");
+        }
+        let manual_timestamp = if date.trim().is_empty() {
+            None
+        } else {
+            match NaiveDateTime::parse_from_str(date.trim(), &Format::DateTime.to_string()) {
+                Ok(date_time) => Some(date_time.and_utc().timestamp()),
+                Err(_) => {
+                    return slint::SharedString::from(format!(
+                        "Invalid date {}, expected YYYY-MM-DD HH:MM:SS",
+                        date.trim()
+                    ));
+                }
+            }
+        };
+        item_list.set_manual_timestamp(local_index as usize, manual_timestamp);
+        slint::SharedString::new()
+    }
+
+    /// Fills the details model with the metadata of all items in the similar items model, one column per item.
+    /// The files are read in a background thread and the details are only shown if the current image is still the
+    /// same when they are loaded.
+    pub fn fill_details(&mut self, window: slint::Weak<main_window::ImageSieve>) {
+        helper::clear_model(self.details_model.clone());
+        let Some(current_image) = self.similar_items_model.row_data(0) else {
+            return;
+        };
+        let items: Vec<(String, FileItem)> = {
+            let item_list = self.item_list.lock().unwrap();
+            self.similar_items_model
+                .iter()
+                .map(|sort_item| {
+                    let item = &item_list.items[sort_item.local_index as usize];
+                    (item.get_item_string(&item_list.path), item.clone())
+                })
+                .collect()
+        };
+        thread::spawn(move || {
+            let details: Vec<(String, Vec<(String, String)>)> = items
+                .into_iter()
+                .map(|(name, item)| (name, item.read_details()))
+                .collect();
+            window
+                .upgrade_in_event_loop(move |handle| {
+                    if handle.get_current_image().local_index != current_image.local_index {
+                        return;
+                    }
+                    let details_model = handle.get_details_model();
+                    let details_model = details_model
+                        .as_any()
+                        .downcast_ref::<slint::VecModel<main_window::DetailRow>>()
+                        .unwrap();
+                    details_model.set_vec(details_rows(&details));
+                })
+                .ok();
+        });
+    }
+
     /// Gets the date string for an image
     pub fn get_date_string(&self, local_index: i32) -> slint::SharedString {
         let item_list = self.item_list.lock().unwrap();
@@ -303,10 +406,58 @@ fn compare_file_items(
             }
         }
         "Size" => a.get_size().cmp(&b.get_size()),
+        "Camera" => a
+            .get_metadata()
+            .camera()
+            .cmp(&b.get_metadata().camera())
+            .then_with(|| a.cmp(b)),
         _ => panic!("Unknown sort by type"),
     }
 }
 
+/// Arranges the metadata details of several items in rows with one value per item. The first row contains the
+/// item names, the other rows are ordered by the first appearance of the name. Values which are not the same for all
+/// items are marked.
+fn details_rows(details: &[(String, Vec<(String, String)>)]) -> Vec<main_window::DetailRow> {
+    let mut names: Vec<&String> = vec![];
+    for (_, item_details) in details {
+        for (name, _) in item_details {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    let mut rows = vec![main_window::DetailRow {
+        name: slint::SharedString::from("File"),
+        values: slint::ModelRc::from(Rc::new(slint::VecModel::from(
+            details
+                .iter()
+                .map(|(item_name, _)| slint::SharedString::from(item_name.as_str()))
+                .collect::<Vec<slint::SharedString>>(),
+        ))),
+        differs: false,
+    }];
+    for name in names {
+        let values: Vec<slint::SharedString> = details
+            .iter()
+            .map(|(_, item_details)| {
+                item_details
+                    .iter()
+                    .find(|(other, _)| other == name)
+                    .map(|(_, value)| slint::SharedString::from(value.as_str()))
+                    .unwrap_or_default()
+            })
+            .collect();
+        let differs = values.iter().any(|value| *value != values[0]);
+        rows.push(main_window::DetailRow {
+            name: slint::SharedString::from(name.as_str()),
+            values: slint::ModelRc::from(Rc::new(slint::VecModel::from(values))),
+            differs,
+        });
+    }
+    rows
+}
+
 /// Create a sort item for the GUI from a file item
 fn sort_item_from_file_item(
     file_item: &FileItem,
@@ -327,6 +478,10 @@ fn sort_item_description(file_item: &FileItem, item_list: &ItemList) -> slint::S
     if let Some(event) = item_list.get_event(file_item) {
         description = description + ", 📅 " + &event.name;
     }
+    let summary = file_item.get_metadata().summary();
+    if !summary.is_empty() {
+        description = description + "\n" + &summary;
+    }
     slint::SharedString::from(description)
 }
 
@@ -349,6 +504,7 @@ fn list_item_from_file_item(file_item: &FileItem, item_list: &ItemList) -> main_
 
 #[cfg(test)]
 mod tests {
+    use crate::item_sort_list::{MAX_SCORE, Similar, Stack, StackKind};
     use crate::main_window::ImageSieve;
     use rusty_fork::rusty_fork_test;
     use slint::{ComponentHandle, SharedString};
@@ -375,7 +531,10 @@ mod tests {
             let mut item_list = item_list.lock().unwrap();
             item_list.items.push(FileItem::dummy("test2.mov", 1, true));
             let mut file_item = FileItem::dummy("test1.jpg", 0, false);
-            file_item.add_similar_range(&(1..2));
+            file_item.set_similars(vec![Similar {
+                index: 1,
+                score: MAX_SCORE,
+            }]);
             item_list.items.push(file_item);
         }
         items_controller.populate_list_model(&filters);
@@ -438,7 +597,10 @@ mod tests {
                 let mut item_list = item_list.lock().unwrap();
                 item_list.items.push(FileItem::dummy("test2.mov", 1, true));
                 let mut file_item = FileItem::dummy("test1.jpg", 0, false);
-                file_item.add_similar_range(&(0..1));
+                file_item.set_similars(vec![Similar {
+                    index: 0,
+                    score: MAX_SCORE,
+                }]);
                 item_list.items.push(file_item);
             }
             items_controller.populate_list_model(&filters);
@@ -465,6 +627,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_details_rows() {
+        let detail = |name: &str, value: &str| (String::from(name), String::from(value));
+        let rows = details_rows(&[
+            (
+                String::from("a.jpg"),
+                vec![detail("Model", "D90"), detail("ISO", "200")],
+            ),
+            (
+                String::from("b.jpg"),
+                vec![detail("Model", "D90"), detail("FNumber", "f/1.8")],
+            ),
+        ]);
+        let values = |row: &main_window::DetailRow| row.values.iter().collect::<Vec<SharedString>>();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].name, "File");
+        assert_eq!(values(&rows[0]), vec!["a.jpg", "b.jpg"]);
+        assert_eq!(rows[1].name, "Model");
+        assert!(!rows[1].differs);
+        assert_eq!(rows[2].name, "ISO");
+        assert_eq!(values(&rows[2]), vec!["200", ""]);
+        assert!(rows[2].differs);
+        assert_eq!(rows[3].name, "FNumber");
+        assert_eq!(values(&rows[3]), vec!["", "f/1.8"]);
+    }
+
+    #[test]
+    fn test_set_date() {
+        let item_list = Arc::new(Mutex::new(ItemList::new()));
+        let mut items_controller = ItemsController::new(item_list.clone());
+        item_list
+            .lock()
+            .unwrap()
+            .items
+            .push(FileItem::dummy("test1.jpg", 0, true));
+
+        assert_eq!(items_controller.set_date(0, "2024-05-06 07:08:09"), "");
+        assert_eq!(
+            item_list.lock().unwrap().items[0].get_timestamp(),
+            1714979289
+        );
+        assert!(!items_controller.set_date(0, "2024-05-06").is_empty());
+        assert!(!items_controller.set_date(1, "").is_empty());
+        assert!(!items_controller.set_date(-1, "").is_empty());
+        assert_eq!(items_controller.set_date(0, ""), "");
+        assert_eq!(item_list.lock().unwrap().items[0].get_timestamp(), 0);
+    }
+
     #[test]
     fn test_select_item() {
         let item_list = Arc::new(Mutex::new(ItemList::new()));
@@ -476,7 +686,10 @@ mod tests {
             let mut item_list = item_list.lock().unwrap();
             item_list.items.push(FileItem::dummy("test2.mov", 1, true));
             let mut file_item = FileItem::dummy("test1.jpg", 0, false);
-            file_item.add_similar_range(&(0..1));
+            file_item.set_similars(vec![Similar {
+                index: 0,
+                score: MAX_SCORE,
+            }]);
             item_list.items.push(file_item);
         }
         items_controller.populate_list_model(&filters);
@@ -505,6 +718,35 @@ mod tests {
         assert_eq!(window.get_current_image().local_index, 0);
     }
 
+    #[test]
+    fn test_stack() {
+        let item_list = Arc::new(Mutex::new(ItemList::new()));
+        let mut items_controller = ItemsController::new(item_list.clone());
+        let filters = build_filters();
+        {
+            let mut item_list = item_list.lock().unwrap();
+            for (name, timestamp) in [("test1.jpg", 0), ("test2.jpg", 0), ("test3.jpg", 1)] {
+                item_list.items.push(FileItem::dummy(name, timestamp, true));
+            }
+            let stack = Stack {
+                kind: StackKind::Burst,
+                items: vec![0, 1],
+            };
+            item_list.items[0].set_stack(Some(stack.clone()));
+            item_list.items[1].set_stack(Some(stack));
+        }
+        items_controller.populate_list_model(&filters);
+        let list_model = items_controller.get_list_model();
+        assert_eq!(list_model.row_count(), 2);
+        assert_eq!(list_model.row_data(0).unwrap().text, "📚 📷 test1.jpg");
+        assert_eq!(list_model.row_data(1).unwrap().text, "📷 test3.jpg");
+
+        items_controller.set_take_over(0, false);
+        let item_list = item_list.lock().unwrap();
+        assert!(!item_list.items[1].get_take_over());
+        assert!(item_list.items[2].get_take_over());
+    }
+
     #[test]
     fn test_update_list() {
         let item_list = Arc::new(Mutex::new(ItemList::new()));
@@ -515,7 +757,10 @@ mod tests {
             let mut item_list = item_list.lock().unwrap();
             item_list.items.push(FileItem::dummy("test2.mov", 1, true));
             let mut file_item = FileItem::dummy("test1.jpg", 0, false);
-            file_item.add_similar_range(&(1..2));
+            file_item.set_similars(vec![Similar {
+                index: 1,
+                score: MAX_SCORE,
+            }]);
             item_list.items.push(file_item);
         }
         items_controller.populate_list_model(&filters);
@@ -0,0 +1,228 @@
+use std::{
+    collections::HashMap,
+    fs::{File, Metadata, metadata},
+    io::{Error, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use super::file_types::is_any;
+use super::journal::STAGING_DIRECTORY;
+
+/// A file of the archive with its content hash, which is only calculated when needed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexedFile {
+    size: u64,
+    modified: u128,
+    hash: Option<String>,
+}
+
+impl IndexedFile {
+    fn new(metadata: &Metadata) -> Self {
+        IndexedFile {
+            size: metadata.len(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|modified| modified.as_nanos())
+                .unwrap_or_default(),
+            hash: None,
+        }
+    }
+}
+
+/// Index of the content hashes of all media files in an archive directory. It is used to detect files that are
+/// already present anywhere in the archive. Since files with different sizes cannot be identical, hashes are only
+/// calculated for files with the same size as a file to look up and cached in the index.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ArchiveIndex {
+    /// Indexed files by their path relative to the archive root
+    files: HashMap<PathBuf, IndexedFile>,
+    #[serde(skip)]
+    root: PathBuf,
+    #[serde(skip)]
+    by_size: HashMap<u64, Vec<PathBuf>>,
+}
+
+impl ArchiveIndex {
+    /// Synchronizes the index with the media files found in the archive root directory. Cached hashes are kept for
+    /// all files whose size and modification time did not change.
+    pub fn update(&mut self, root: &Path) {
+        let mut files = HashMap::new();
+        for entry in WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != STAGING_DIRECTORY)
+            .flatten()
+        {
+            if !entry.file_type().is_file() || !is_any(entry.path()) {
+                continue;
+            }
+            if let (Ok(relative_path), Ok(metadata)) =
+                (entry.path().strip_prefix(root), entry.metadata())
+            {
+                let mut indexed_file = IndexedFile::new(&metadata);
+                if let Some(cached) = self.files.get_mut(relative_path)
+                    && cached.size == indexed_file.size
+                    && cached.modified == indexed_file.modified
+                {
+                    indexed_file.hash = cached.hash.take();
+                }
+                files.insert(relative_path.to_path_buf(), indexed_file);
+            }
+        }
+        self.files = files;
+        self.root = root.to_path_buf();
+        self.by_size.clear();
+        for (relative_path, indexed_file) in &self.files {
+            self.by_size
+                .entry(indexed_file.size)
+                .or_default()
+                .push(relative_path.clone());
+        }
+    }
+
+    /// Returns the number of indexed files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Checks if the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Finds a file in the archive which has the same content as the given file
+    pub fn find(&mut self, path: &Path) -> Result<Option<PathBuf>, Error> {
+        let size = metadata(path)?.len();
+        self.find_content(size, || hash_file(path))
+    }
+
+    /// Finds a file in the archive which has the same content as a part of the given file, like the video embedded
+    /// in a motion photo. The end of the range may exceed the file size.
+    pub fn find_part(&mut self, path: &Path, range: Range<u64>) -> Result<Option<PathBuf>, Error> {
+        let size = metadata(path)?.len().min(range.end).saturating_sub(range.start);
+        self.find_content(size, || hash_part(path, range))
+    }
+
+    /// Finds a file in the archive with the given size and hash, the hash is only calculated if there are files
+    /// with the same size
+    fn find_content(
+        &mut self,
+        size: u64,
+        hash: impl FnOnce() -> Result<String, Error>,
+    ) -> Result<Option<PathBuf>, Error> {
+        let Some(candidates) = self.by_size.get(&size) else {
+            return Ok(None);
+        };
+        let hash = hash()?;
+        for relative_path in candidates {
+            let indexed_file = self.files.get_mut(relative_path).unwrap();
+            if indexed_file.hash.is_none() {
+                indexed_file.hash = hash_file(&self.root.join(relative_path)).ok();
+            }
+            if indexed_file.hash.as_ref() == Some(&hash) {
+                return Ok(Some(self.root.join(relative_path)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Adds a file of the archive to the index, files outside of the archive root are ignored
+    pub fn add(&mut self, path: &Path) -> Result<(), Error> {
+        if let Ok(relative_path) = path.strip_prefix(&self.root) {
+            let indexed_file = IndexedFile::new(&metadata(path)?);
+            let candidates = self.by_size.entry(indexed_file.size).or_default();
+            if !candidates.iter().any(|candidate| candidate == relative_path) {
+                candidates.push(relative_path.to_path_buf());
+            }
+            self.files.insert(relative_path.to_path_buf(), indexed_file);
+        }
+        Ok(())
+    }
+}
+
+/// Calculates the BLAKE3 hash of a file without reading it into memory at once
+pub fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Calculates the BLAKE3 hash of a part of a file
+fn hash_part(path: &Path, range: Range<u64>) -> Result<String, Error> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(file.take(range.end - range.start))?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let mut archive_index = ArchiveIndex::default();
+        archive_index.update(Path::new("tests/subdir"));
+        assert_eq!(archive_index.len(), 3);
+        assert!(archive_index.files.values().all(|file| file.hash.is_none()));
+
+        assert_eq!(
+            archive_index.find(Path::new("tests/test.jpg")).unwrap(),
+            Some(PathBuf::from("tests/subdir/test.jpg"))
+        );
+        assert_eq!(
+            archive_index.find(Path::new("tests/test2.JPG")).unwrap(),
+            None
+        );
+        assert_eq!(
+            archive_index.find(Path::new("tests/test3.jpg")).unwrap(),
+            None
+        );
+        assert_eq!(
+            archive_index
+                .find(Path::new("tests/test_no_exif.jpg"))
+                .unwrap(),
+            None
+        );
+        assert!(archive_index.find(Path::new("tests/missing.jpg")).is_err());
+
+        // Parts of files are found by their content as well
+        let size = metadata("tests/subdir/test.jpg").unwrap().len();
+        assert_eq!(
+            archive_index
+                .find_part(Path::new("tests/test.jpg"), 0..u64::MAX)
+                .unwrap(),
+            Some(PathBuf::from("tests/subdir/test.jpg"))
+        );
+        assert_eq!(
+            archive_index
+                .find_part(Path::new("tests/test.jpg"), 1..size)
+                .unwrap(),
+            None
+        );
+
+        // Hashes are kept when updating the index again
+        let hashed = archive_index
+            .files
+            .values()
+            .filter(|file| file.hash.is_some())
+            .count();
+        assert!(hashed > 0);
+        archive_index.update(Path::new("tests/subdir"));
+        assert_eq!(
+            archive_index
+                .files
+                .values()
+                .filter(|file| file.hash.is_some())
+                .count(),
+            hashed
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+/// Correction of a camera clock which was not set correctly
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockOffset {
+    /// Camera identified by make, model and serial number
+    pub camera: String,
+    /// Seconds to add to the capture time of all items taken with the camera
+    pub seconds: i64,
+}
+
+/// Parses a clock offset in the format [+|-]HH:MM[:SS] and returns the offset in seconds
+pub fn parse_clock_offset(offset: &str) -> Result<i64, String> {
+    let offset = offset.trim();
+    let (sign, value) = match offset.strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, offset.strip_prefix('+').unwrap_or(offset)),
+    };
+    let error = || format!("Invalid clock offset {}, expected [+|-]HH:MM[:SS]", offset);
+    let parts: Vec<&str> = value.split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return Err(error());
+    }
+    let mut seconds = 0;
+    for (index, part) in parts.iter().enumerate() {
+        let value: i64 = part.parse().map_err(|_| error())?;
+        if value < 0 || (index > 0 && value >= 60) {
+            return Err(error());
+        }
+        seconds = seconds * 60 + value;
+    }
+    if parts.len() == 2 {
+        seconds *= 60;
+    }
+    Ok(sign * seconds)
+}
+
+/// Formats a clock offset in seconds as [+|-]HH:MM:SS
+pub fn clock_offset_to_string(seconds: i64) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    format!(
+        "{}{:02}:{:02}:{:02}",
+        sign,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_clock_offset() {
+        assert_eq!(parse_clock_offset("01:30"), Ok(5400));
+        assert_eq!(parse_clock_offset("+01:30:15"), Ok(5415));
+        assert_eq!(parse_clock_offset(" -00:00:30 "), Ok(-30));
+        assert_eq!(parse_clock_offset("-25:00"), Ok(-90000));
+        assert!(parse_clock_offset("").is_err());
+        assert!(parse_clock_offset("1").is_err());
+        assert!(parse_clock_offset("01:60").is_err());
+        assert!(parse_clock_offset("01:-5").is_err());
+        assert!(parse_clock_offset("a:00").is_err());
+    }
+
+    #[test]
+    fn test_clock_offset_to_string() {
+        assert_eq!(clock_offset_to_string(0), "+00:00:00");
+        assert_eq!(clock_offset_to_string(5415), "+01:30:15");
+        assert_eq!(clock_offset_to_string(-90000), "-25:00:00");
+        assert_eq!(parse_clock_offset(&clock_offset_to_string(-3723)), Ok(-3723));
+    }
+}
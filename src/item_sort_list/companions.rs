@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::file_item::{FileItem, ItemType};
+
+/// Gets the rank of an item type for choosing the primary item of a group, the item with the lowest rank is shown
+fn rank(item_type: &ItemType) -> u8 {
+    match item_type {
+        ItemType::Image => 0,
+        ItemType::HeifImage => 1,
+        ItemType::RawImage => 2,
+        ItemType::Video => 3,
+    }
+}
+
+/// Finds files in the same directory with the same name but a different type, like DSC_0001.NEF and DSC_0001.JPG
+/// taken in RAW+JPEG mode. Each returned group starts with the primary item, which is the one that is displayed,
+/// followed by its companions. Items of the same type as the primary item are not grouped.
+pub fn find_companions(items: &[FileItem]) -> Vec<Vec<usize>> {
+    let mut names: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (index, item) in items.iter().enumerate() {
+        if let Some(stem) = item.path.file_stem() {
+            let directory = item.path.parent().map(PathBuf::from).unwrap_or_default();
+            names
+                .entry((directory, stem.to_string_lossy().to_lowercase()))
+                .or_default()
+                .push(index);
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = names
+        .into_values()
+        .filter_map(|mut indices| {
+            indices.sort_by_key(|index| (rank(items[*index].get_item_type()), *index));
+            let primary_type = items[indices[0]].get_item_type();
+            let mut group = vec![indices[0]];
+            group.extend(
+                indices[1..]
+                    .iter()
+                    .filter(|index| items[**index].get_item_type() != primary_type),
+            );
+            (group.len() > 1).then_some(group)
+        })
+        .collect();
+    groups.sort_unstable();
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_companions() {
+        let items = vec![
+            FileItem::dummy("dir/DSC_0001.NEF", 0, true),
+            FileItem::dummy("dir/DSC_0001.JPG", 0, true),
+            FileItem::dummy("dir/DSC_0002.jpg", 1, true),
+            FileItem::dummy("other/DSC_0002.nef", 1, true),
+            FileItem::dummy("dir/IMG_0003.mov", 2, true),
+            FileItem::dummy("dir/IMG_0003.heic", 2, true),
+            FileItem::dummy("dir/IMG_0003.dng", 2, true),
+            FileItem::dummy("dir/IMG_0004.jpg", 3, true),
+            FileItem::dummy("dir/IMG_0004.jpeg", 3, true),
+        ];
+        assert_eq!(find_companions(&items), vec![vec![1, 0], vec![5, 6, 4]]);
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    fs::{OpenOptions, create_dir_all, read_dir, remove_dir_all, remove_file},
+    io::{Error, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta};
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumString;
+
+/// Format of the directory names in the quarantine directory
+const QUARANTINE_FORMAT: &str = "%Y-%m-%d %H-%M-%S";
+/// The trash is only supported on platforms following the freedesktop.org trash specification
+const TRASH_SUPPORTED: bool = !cfg!(any(target_os = "windows", target_os = "macos"));
+
+/// Policy how discarded files are deleted during the sieve process
+#[derive(
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    Clone,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    EnumString,
+)]
+#[strum(ascii_case_insensitive)]
+#[repr(i32)]
+pub enum DeletionPolicy {
+    /// Delete the files, they are kept in a staging directory until the next sieve process
+    #[default]
+    Delete = 0,
+    /// Move the files to the trash of the desktop environment
+    Trash,
+    /// Move the files to a quarantine directory where they are kept for a retention period
+    Quarantine,
+}
+
+impl DeletionPolicy {
+    /// Returns an Unsupported error if the policy cannot be used on this platform
+    pub fn check_supported(&self) -> Result<(), Error> {
+        if *self == DeletionPolicy::Trash && !TRASH_SUPPORTED {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "The trash is not supported on this platform",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Deletion policy and the settings required to apply it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Deletion {
+    /// How discarded files are deleted
+    pub policy: DeletionPolicy,
+    /// Directory where files are moved to with the quarantine policy
+    pub quarantine_directory: PathBuf,
+    /// Number of days files are kept in the quarantine directory, 0 keeps them forever
+    pub retention_days: u32,
+}
+
+impl Deletion {
+    /// Gets the path where a discarded file is moved to instead of deleting it. With the delete policy, this is in
+    /// the staging directory. The index and the timestamp of the sieve process are used to create unique names.
+    pub fn discard_target(
+        &self,
+        path: &Path,
+        staging_directory: &Path,
+        index: usize,
+        timestamp: i64,
+    ) -> Result<PathBuf, Error> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        match self.policy {
+            DeletionPolicy::Delete => {
+                create_dir_all(staging_directory)?;
+                Ok(staging_directory.join(format!("{}_{}", index, file_name)))
+            }
+            DeletionPolicy::Trash => trash_target(path),
+            DeletionPolicy::Quarantine => {
+                if self.quarantine_directory.as_os_str().is_empty() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "No quarantine directory set",
+                    ));
+                }
+                let sieve_time = DateTime::from_timestamp(timestamp, 0)
+                    .unwrap_or_default()
+                    .with_timezone(&Local);
+                let directory = self
+                    .quarantine_directory
+                    .join(sieve_time.format(QUARANTINE_FORMAT).to_string());
+                create_dir_all(&directory)?;
+                Ok(directory.join(format!("{}_{}", index, file_name)))
+            }
+        }
+    }
+
+    /// Removes all sieve directories from the quarantine directory which are older than the retention period
+    pub fn expire_quarantine(&self) {
+        if self.retention_days == 0 || self.quarantine_directory.as_os_str().is_empty() {
+            return;
+        }
+        let oldest = Local::now().naive_local() - TimeDelta::days(self.retention_days as i64);
+        if let Ok(entries) = read_dir(&self.quarantine_directory) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let sieve_time =
+                    NaiveDateTime::parse_from_str(&name.to_string_lossy(), QUARANTINE_FORMAT);
+                if matches!(sieve_time, Ok(sieve_time) if sieve_time < oldest) {
+                    remove_dir_all(entry.path()).ok();
+                }
+            }
+        }
+    }
+}
+
+/// Removes the trash information of a discarded file after it was restored or could not be moved to the trash
+pub fn forget_discarded(discarded: &Path) {
+    if let (Some(trash_files), Some(file_name)) = (discarded.parent(), discarded.file_name())
+        && trash_files.file_name().is_some_and(|name| name == "files")
+    {
+        let mut info_name = file_name.to_os_string();
+        info_name.push(".trashinfo");
+        let info = trash_files.with_file_name("info").join(info_name);
+        if info.exists() {
+            remove_file(info).ok();
+        }
+    }
+}
+
+/// Gets the home trash directory as defined by the freedesktop.org trash specification
+fn trash_directory() -> Result<PathBuf, Error> {
+    DeletionPolicy::Trash.check_supported()?;
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|data_home| !data_home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home::home_dir().map(|home| home.join(".local/share")))
+        .map(|data_home| data_home.join("Trash"))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "No home directory found"))
+}
+
+/// Reserves a file name in the trash by writing its trash information and returns the path to move the file to
+fn trash_target(path: &Path) -> Result<PathBuf, Error> {
+    let trash = trash_directory()?;
+    create_dir_all(trash.join("files"))?;
+    create_dir_all(trash.join("info"))?;
+    let original_path = std::path::absolute(path)?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    for counter in 1.. {
+        let name = if counter == 1 {
+            format!("{}{}", stem, extension)
+        } else {
+            format!("{}.{}{}", stem, counter, extension)
+        };
+        if trash.join("files").join(&name).exists() {
+            continue;
+        }
+        let info = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(trash.join("info").join(format!("{}.trashinfo", name)));
+        match info {
+            Ok(mut info) => {
+                write!(
+                    info,
+                    "[Trash Info]\nPath={}\nDeletionDate={}\n",
+                    encode_trash_path(&original_path),
+                    Local::now().format("%Y-%m-%dT%H:%M:%S")
+                )?;
+                return Ok(trash.join("files").join(name));
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// Percent-encodes a path for the trash information file
+fn encode_trash_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_trash_path() {
+        assert_eq!(
+            encode_trash_path(Path::new("/home/user/My Pictures/bild_ä.jpg")),
+            "/home/user/My%20Pictures/bild_%C3%A4.jpg"
+        );
+    }
+
+    #[test]
+    fn test_check_supported() {
+        assert!(DeletionPolicy::Delete.check_supported().is_ok());
+        assert!(DeletionPolicy::Quarantine.check_supported().is_ok());
+        assert_eq!(
+            DeletionPolicy::Trash.check_supported().is_ok(),
+            TRASH_SUPPORTED
+        );
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let quarantine_directory = PathBuf::from("tests/quarantine");
+        remove_dir_all(&quarantine_directory).ok();
+        let deletion = Deletion {
+            policy: DeletionPolicy::Quarantine,
+            quarantine_directory: quarantine_directory.clone(),
+            retention_days: 10,
+        };
+
+        let old_timestamp = (Local::now() - TimeDelta::days(11)).timestamp();
+        let old_target = deletion.discard_target(Path::new("a/test.jpg"), Path::new(""), 3, old_timestamp);
+        let new_target = deletion.discard_target(
+            Path::new("a/test.jpg"),
+            Path::new(""),
+            3,
+            Local::now().timestamp(),
+        );
+        let exists = |target: &Result<PathBuf, Error>| {
+            target
+                .as_ref()
+                .is_ok_and(|target| target.parent().unwrap().exists())
+        };
+        let created = (exists(&old_target), exists(&new_target));
+        deletion.expire_quarantine();
+        let expired = (exists(&old_target), exists(&new_target));
+        remove_dir_all(&quarantine_directory).ok();
+
+        let (old_target, new_target) = (old_target.unwrap(), new_target.unwrap());
+        assert!(old_target.ends_with("3_test.jpg"));
+        assert_ne!(old_target, new_target);
+        assert_eq!(created, (true, true));
+        assert_eq!(expired, (false, true));
+
+        let deletion = Deletion {
+            policy: DeletionPolicy::Quarantine,
+            ..Default::default()
+        };
+        assert!(
+            deletion
+                .discard_target(Path::new("test.jpg"), Path::new(""), 0, 0)
+                .is_err()
+        );
+    }
+}
@@ -2,7 +2,6 @@ use std::cmp::Ordering;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
-use std::ops::Range;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -16,8 +15,14 @@ use super::file_types::is_image;
 use super::file_types::is_raw_image;
 use super::file_types::is_heif_image;
 use super::file_types::is_video;
+use super::hash_config::HashConfig;
 use super::item_traits::Orientation;
+use super::metadata::Metadata;
 use super::item_traits::PropertyResolver;
+use super::resolvers::get_resolver;
+use super::similarity::Similar;
+use super::stack::Stack;
+use super::timestamp::{Timestamp, deserialize_timestamp};
 use super::timestamp_to_string;
 
 pub type HashType = ImageHash<Vec<u8>>;
@@ -36,19 +41,51 @@ pub struct FileItem {
     /// Actual file path
     pub path: PathBuf,
     /// Time stamp of file creation (either from EXIF or from file system)
-    timestamp: i64,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    timestamp: Timestamp,
     /// Flag indicating if the file shall be taken over during sieving (true) or be discarded (false)
     take_over: bool,
-    /// List of similar items as indices in the list of file items
-    similar: Vec<usize>,
+    /// Flag indicating that the take over flag was set by the user and is not changed automatically
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    decided: bool,
+    /// List of similar items ordered by their similarity score
+    #[serde(skip)]
+    similar: Vec<Similar>,
+    /// Burst or bracket sequence the item belongs to
+    #[serde(skip)]
+    stack: Option<Stack>,
+    /// Indices of the files with the same name but a different type, like the raw file of a JPEG image
+    #[serde(skip)]
+    companions: Vec<usize>,
+    /// Index of the item this file is a companion of
+    #[serde(skip)]
+    primary: Option<usize>,
+    /// Flag indicating that a companion of this image is the video of a live photo
+    #[serde(skip)]
+    live_photo: bool,
+    /// Sidecar files which belong to this item, like XMP files
+    #[serde(skip)]
+    sidecars: Vec<PathBuf>,
+    /// Seconds to add to the timestamp to correct the clock of the camera
+    #[serde(skip)]
+    clock_offset: i64,
+    /// Capture time in seconds of local time that was corrected manually
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manual_timestamp: Option<i64>,
     /// Orientation of the image
     orientation: Option<Orientation>,
     /// Hash of the image
     #[serde(serialize_with = "serialize_hash")]
     #[serde(deserialize_with = "deserialize_hash")]
     hash: Option<HashType>,
+    /// Configuration the hash was calculated with
+    #[serde(default)]
+    hash_config: HashConfig,
     /// File item type
     item_type: Option<ItemType>,
+    /// Metadata read from the file
+    #[serde(default)]
+    metadata: Metadata,
 }
 
 pub fn serialize_hash<S>(hash: &Option<HashType>, s: S) -> Result<S::Ok, S::Error>
@@ -93,6 +130,7 @@ impl FileItem {
     ) -> Self {
         let timestamp = property_resolver.get_timestamp();
         let orientation = property_resolver.get_orientation();
+        let metadata = property_resolver.get_metadata();
         let hash = process_encoded_hash(encoded_hash);
         let item_type = get_item_type(&path);
 
@@ -100,10 +138,20 @@ impl FileItem {
             path,
             timestamp,
             take_over,
+            decided: false,
             similar: Vec::new(),
+            stack: None,
+            companions: Vec::new(),
+            primary: None,
+            live_photo: false,
+            sidecars: Vec::new(),
+            clock_offset: 0,
+            manual_timestamp: None,
             orientation,
             hash,
+            hash_config: HashConfig::default(),
             item_type: Some(item_type),
+            metadata,
         }
     }
 
@@ -114,12 +162,22 @@ impl FileItem {
         let item_type = get_item_type(&path);
         Self {
             path,
-            timestamp,
+            timestamp: Timestamp::from_seconds(timestamp),
             orientation: Some(Orientation::Landscape),
             take_over,
+            decided: false,
             similar: Vec::new(),
+            stack: None,
+            companions: Vec::new(),
+            primary: None,
+            live_photo: false,
+            sidecars: Vec::new(),
+            clock_offset: 0,
+            manual_timestamp: None,
             hash: None,
+            hash_config: HashConfig::default(),
             item_type: Some(item_type),
+            metadata: Metadata::default(),
         }
     }
 
@@ -140,14 +198,61 @@ impl FileItem {
         self.take_over
     }
 
-    /// Get the time stamp of the file item
+    /// Sets if the take over flag was set by the user
+    pub fn set_decided(&mut self, decided: bool) {
+        self.decided = decided;
+    }
+
+    /// Checks if the take over flag was set by the user
+    pub fn is_decided(&self) -> bool {
+        self.decided
+    }
+
+    /// Get the time stamp of the file item in seconds of local time
     pub fn get_timestamp(&self) -> i64 {
-        self.timestamp
+        self.get_capture_time().seconds()
+    }
+
+    /// Get the capture time of the file item with sub second precision and offset to UTC, corrected manually or
+    /// by the clock offset of the camera
+    pub fn get_capture_time(&self) -> Timestamp {
+        match self.manual_timestamp {
+            Some(seconds) => Timestamp::new(seconds * 1000, self.timestamp.offset),
+            None => Timestamp::new(
+                self.timestamp.millis + self.clock_offset * 1000,
+                self.timestamp.offset,
+            ),
+        }
+    }
+
+    /// Set the manually corrected capture time in seconds of local time, None removes the correction
+    pub fn set_manual_timestamp(&mut self, manual_timestamp: Option<i64>) {
+        self.manual_timestamp = manual_timestamp;
+    }
+
+    /// Check if the capture time differs from the time read from the file
+    pub fn is_time_corrected(&self) -> bool {
+        self.manual_timestamp.is_some() || self.clock_offset != 0
+    }
+
+    /// Set the capture time of the file item as read from the file
+    pub fn set_capture_time(&mut self, timestamp: Timestamp) {
+        self.timestamp = timestamp;
+    }
+
+    /// Get the clock offset of the camera in seconds
+    pub fn get_clock_offset(&self) -> i64 {
+        self.clock_offset
+    }
+
+    /// Set the clock offset of the camera in seconds which corrects the capture time
+    pub fn set_clock_offset(&mut self, clock_offset: i64) {
+        self.clock_offset = clock_offset;
     }
 
     /// Get the time stamp of the file item formatted as string
     fn get_date_str(&self) -> String {
-        timestamp_to_string(self.timestamp, Format::DateTime)
+        timestamp_to_string(self.get_timestamp(), Format::DateTime)
     }
 
     /// Get the size of a file item in bytes
@@ -159,18 +264,14 @@ impl FileItem {
         }
     }
 
-    /// Adds a vector of similars
-    pub fn add_similar_vec(&mut self, similars: &[usize]) {
-        self.similar.extend(similars);
+    /// Sets the list of similar items, the most similar items come first
+    pub fn set_similars(&mut self, mut similars: Vec<Similar>) {
+        similars.sort_unstable_by(|a, b| b.score.cmp(&a.score).then(a.index.cmp(&b.index)));
+        self.similar = similars;
     }
 
-    /// Add a range of similars
-    pub fn add_similar_range(&mut self, similars: &Range<usize>) {
-        self.similar.extend(similars.clone());
-    }
-
-    /// Get the list of similar item indices.
-    pub fn get_similars(&self) -> &Vec<usize> {
+    /// Get the list of similar items ordered by their similarity score
+    pub fn get_similars(&self) -> &Vec<Similar> {
         &self.similar
     }
 
@@ -183,12 +284,59 @@ impl FileItem {
         self.similar.is_empty()
     }
 
-    pub fn clean_similars(&mut self, item_index: usize) {
-        self.similar.sort_unstable();
-        self.similar.dedup();
-        if let Ok(similar_index) = self.similar.binary_search(&item_index) {
-            self.similar.remove(similar_index);
-        }
+    /// Sets the burst or bracket sequence the item belongs to
+    pub fn set_stack(&mut self, stack: Option<Stack>) {
+        self.stack = stack;
+    }
+
+    /// Gets the burst or bracket sequence the item belongs to
+    pub fn get_stack(&self) -> Option<&Stack> {
+        self.stack.as_ref()
+    }
+
+    /// Sets the companion files which are handled together with this item
+    pub fn set_companions(&mut self, companions: Vec<usize>) {
+        self.companions = companions;
+    }
+
+    /// Gets the companion files which are handled together with this item
+    pub fn get_companions(&self) -> &Vec<usize> {
+        &self.companions
+    }
+
+    /// Sets the item this file is a companion of
+    pub fn set_primary(&mut self, primary: Option<usize>) {
+        self.primary = primary;
+    }
+
+    /// Gets the item this file is a companion of, None if the file is handled on its own
+    pub fn get_primary(&self) -> Option<usize> {
+        self.primary
+    }
+
+    /// Sets the flag indicating that a companion of this image is the video of a live photo
+    pub fn set_live_photo(&mut self, live_photo: bool) {
+        self.live_photo = live_photo;
+    }
+
+    /// Checks if a companion of this image is the video of a live photo
+    pub fn is_live_photo(&self) -> bool {
+        self.live_photo
+    }
+
+    /// Checks if the item is a live photo with a separate video or a motion photo with an embedded video
+    pub fn is_motion_photo(&self) -> bool {
+        self.live_photo || self.metadata.embedded_video.is_some()
+    }
+
+    /// Sets the sidecar files which are copied, moved and deleted together with this item
+    pub fn set_sidecars(&mut self, sidecars: Vec<PathBuf>) {
+        self.sidecars = sidecars;
+    }
+
+    /// Gets the sidecar files which are copied, moved and deleted together with this item
+    pub fn get_sidecars(&self) -> &Vec<PathBuf> {
+        &self.sidecars
     }
 
     /// Get the orientation of the image
@@ -196,14 +344,38 @@ impl FileItem {
         self.orientation.as_ref()
     }
 
+    /// Get the metadata read from the file
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Set the metadata of the file
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = metadata;
+    }
+
+    /// Reads all metadata from the file as pairs of name and value for display. In contrast to the metadata, the
+    /// details are not stored, so the file is read on every call.
+    pub fn read_details(&self) -> Vec<(String, String)> {
+        get_resolver(&self.path).get_details()
+    }
+
     /// Gets a string representing the item type and if it has simlar items or not, if it will be discarded and the item path
     pub fn get_item_string(&self, base_path: &Path) -> String {
         let path = self.path.strip_prefix(base_path).unwrap_or(&self.path);
         let similars_str = if !self.has_similars() { "🔀" } else { "" };
+        let stack_str = if self.stack.is_some() { "📚" } else { "" };
+        let companions_str = if self.companions.is_empty() { "" } else { "🔗" };
+        let sidecars_str = if self.sidecars.is_empty() { "" } else { "📎" };
+        let motion_str = if self.is_motion_photo() { "▶" } else { "" };
         let extension_str = self.extension_to_unicode_icon();
         let take_over_str = if self.take_over { "" } else { "🗑" };
         let strings: Vec<&str> = [
             similars_str,
+            stack_str,
+            companions_str,
+            sidecars_str,
+            motion_str,
             extension_str,
             take_over_str,
             path.to_str().unwrap(),
@@ -245,9 +417,18 @@ impl FileItem {
         }
     }
 
-    /// Set the image hash
-    pub fn set_hash(&mut self, hash: ImageHash<Vec<u8>>) {
+    /// Set the image hash together with the configuration it was calculated with
+    pub fn set_hash(&mut self, hash: ImageHash<Vec<u8>>, hash_config: HashConfig) {
         self.hash = Some(hash);
+        self.hash_config = hash_config;
+    }
+
+    /// Remove the image hash if it was calculated with a different configuration
+    pub fn invalidate_hash(&mut self, hash_config: &HashConfig) {
+        if self.hash_config != *hash_config {
+            self.hash = None;
+            self.hash_config = *hash_config;
+        }
     }
 
     /// Set the image hash from an encoded hash
@@ -264,6 +445,11 @@ impl FileItem {
         }
     }
 
+    /// Get the image hash if it was calculated
+    pub fn get_hash(&self) -> Option<&HashType> {
+        self.hash.as_ref()
+    }
+
     /// Check if the file item has a hash
     pub fn has_hash(&self) -> bool {
         self.hash.is_some()
@@ -307,7 +493,7 @@ impl PartialOrd for FileItem {
 
 impl Ord for FileItem {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.timestamp.cmp(&other.timestamp)
+        self.get_capture_time().cmp(&other.get_capture_time())
     }
 }
 
@@ -325,24 +511,25 @@ fn process_encoded_hash(encoded_hash: &str) -> Option<ImageHash<Vec<u8>>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::item_sort_list::HashAlgorithm;
     use crate::item_sort_list::{Orientation, item_traits::PropertyResolver};
 
     struct MockResolver {
-        timestamp: i64,
+        timestamp: Timestamp,
         orientation: Option<Orientation>,
     }
 
     impl MockResolver {
         fn new(timestamp: i64, orientation: Option<Orientation>) -> Self {
             MockResolver {
-                timestamp,
+                timestamp: Timestamp::new(timestamp * 1000 + 250, Some(3600)),
                 orientation,
             }
         }
     }
 
     impl PropertyResolver for MockResolver {
-        fn get_timestamp(&self) -> i64 {
+        fn get_timestamp(&self) -> Timestamp {
             self.timestamp
         }
 
@@ -361,6 +548,10 @@ mod tests {
             file_item.get_orientation()
         );
         assert_eq!(10, file_item.get_timestamp());
+        assert_eq!(
+            Timestamp::new(10_250, Some(3600)),
+            file_item.get_capture_time()
+        );
         assert!(file_item.get_take_over());
         assert_eq!(7383, file_item.get_size());
         assert_eq!("", file_item.get_encoded_hash());
@@ -404,18 +595,32 @@ mod tests {
         assert_eq!(hash, file_item2.get_encoded_hash());
         assert!(file_item2.has_hash());
 
-        file_item2.set_hash(HashType::from_bytes(&[0x64, 0x65, 0x66, 0x67]).unwrap());
+        file_item2.set_hash(
+            HashType::from_bytes(&[0x64, 0x65, 0x66, 0x67]).unwrap(),
+            HashConfig::default(),
+        );
         assert_eq!(hash, file_item2.get_encoded_hash());
         assert!(file_item2.has_hash());
 
         assert_eq!(file_item.get_hash_distance(&file_item2), u32::MAX);
-        file_item.set_hash(HashType::from_bytes(&[0x64, 0x65, 0x66, 0x67]).unwrap());
+        file_item.set_hash(
+            HashType::from_bytes(&[0x64, 0x65, 0x66, 0x67]).unwrap(),
+            HashConfig::default(),
+        );
 
         assert_eq!(
             file_item.get_hash_distance(&file_item2),
             file_item2.get_hash_distance(&file_item)
         );
         assert_eq!(file_item.get_hash_distance(&file_item2), 0);
+
+        file_item.invalidate_hash(&HashConfig::default());
+        assert!(file_item.has_hash());
+        file_item.invalidate_hash(&HashConfig {
+            algorithm: HashAlgorithm::Dct,
+            size: 16,
+        });
+        assert!(!file_item.has_hash());
     }
 
     #[test]
@@ -454,4 +659,40 @@ mod tests {
         let file_item = FileItem::new(PathBuf::from("tests/test.mp4"), resolver, true, "");
         assert_eq!(ItemType::Video, file_item.get_item_type().clone());
     }
+    #[test]
+    fn test_deserialize_timestamp() {
+        let mut file_item = FileItem::dummy("tests/test.jpg", 10, true);
+        file_item.set_capture_time(Timestamp::new(10_250, Some(-3600)));
+        let json = serde_json::to_string(&file_item).unwrap();
+        let loaded: FileItem = serde_json::from_str(&json).unwrap();
+        assert_eq!(file_item.get_capture_time(), loaded.get_capture_time());
+
+        // Older project files stored the timestamp in whole seconds
+        let mut value = serde_json::to_value(&file_item).unwrap();
+        value["timestamp"] = serde_json::json!(20);
+        let loaded: FileItem = serde_json::from_value(value).unwrap();
+        assert_eq!(Timestamp::from_seconds(20), loaded.get_capture_time());
+        assert_eq!(20, loaded.get_timestamp());
+    }
+
+    #[test]
+    fn test_corrected_time() {
+        let mut file_item = FileItem::dummy("tests/test.jpg", 10, true);
+        assert!(!file_item.is_time_corrected());
+        file_item.set_clock_offset(5);
+        assert!(file_item.is_time_corrected());
+        assert_eq!(15, file_item.get_timestamp());
+
+        // A manual correction replaces the clock offset
+        file_item.set_manual_timestamp(Some(100));
+        assert_eq!(100, file_item.get_timestamp());
+        let json = serde_json::to_string(&file_item).unwrap();
+        let loaded: FileItem = serde_json::from_str(&json).unwrap();
+        assert_eq!(100, loaded.get_timestamp());
+
+        file_item.set_manual_timestamp(None);
+        file_item.set_clock_offset(0);
+        assert!(!file_item.is_time_corrected());
+        assert_eq!(10, file_item.get_timestamp());
+    }
 }
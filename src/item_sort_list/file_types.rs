@@ -17,6 +17,12 @@ const HEIF: &[&str] = &[
     "heic", "heif"
 ];
 
+/// Files which belong to an image or video, like XMP files of editing applications or GoPro low resolution videos
+const SIDECAR: &[&str] = &["xmp", "aae", "thm", "lrv"];
+
+/// Formats whose EXIF data can be modified in place
+const METADATA_WRITABLE: &[&str] = &["jpg", "jpeg", "jpe", "tif", "tiff", "dng"];
+
 pub fn is_image(path: &Path) -> bool {
     is_extension_in(path, IMAGE)
 }
@@ -29,6 +35,14 @@ pub fn is_heif_image(path: &Path) -> bool {
     is_extension_in(path, HEIF)
 }
 
+pub fn is_sidecar(path: &Path) -> bool {
+    is_extension_in(path, SIDECAR)
+}
+
+pub fn is_metadata_writable(path: &Path) -> bool {
+    is_extension_in(path, METADATA_WRITABLE)
+}
+
 pub fn is_video(path: &Path) -> bool {
     is_extension_in(path, VIDEO)
 }
@@ -69,6 +83,15 @@ mod test {
         assert!(is_video(Path::new("path/to/video.AVI")));
         assert!(!is_video(Path::new("path/to/video.jpg")));
 
+        assert!(is_sidecar(Path::new("/path/to/image.jpg.xmp")));
+        assert!(is_sidecar(Path::new("/path/to/GL010123.LRV")));
+        assert!(!is_sidecar(Path::new("/path/to/image.jpg")));
+        assert!(!is_any(Path::new("/path/to/image.xmp")));
+
+        assert!(is_metadata_writable(Path::new("/path/to/image.JPG")));
+        assert!(is_metadata_writable(Path::new("/path/to/image.dng")));
+        assert!(!is_metadata_writable(Path::new("/path/to/image.nef")));
+
         assert!(is_heif_image(Path::new("/path/to/image.heic")));
         assert!(is_heif_image(Path::new("/path/to/image.HEIF")));
         assert!(!is_heif_image(Path::new("/path/to/image.png")));
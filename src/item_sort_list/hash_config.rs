@@ -0,0 +1,130 @@
+use img_hash::{HashAlg, Hasher, HasherConfig};
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumString;
+
+/// Hash sizes that can be selected
+pub const HASH_SIZES: [u32; 3] = [8, 16, 32];
+
+/// Algorithm used to calculate the similarity hash of an image
+#[derive(
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    EnumString,
+)]
+#[strum(ascii_case_insensitive)]
+#[repr(i32)]
+pub enum HashAlgorithm {
+    /// Compares the brightness of each pixel to the mean brightness
+    Mean = 0,
+    /// Compares the brightness of horizontally adjacent pixels
+    Gradient,
+    /// Compares the brightness of horizontally and vertically adjacent pixels
+    #[default]
+    DoubleGradient,
+    /// Compares the low frequencies of a discrete cosine transform to their mean
+    Dct,
+    /// Compares the brightness of blocks to the median brightness
+    Blockhash,
+}
+
+/// Configuration of the similarity hash which is stored together with each hash, since only hashes calculated with
+/// the same configuration can be compared. The default is the configuration used before it was selectable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashConfig {
+    /// Hash algorithm
+    pub algorithm: HashAlgorithm,
+    /// Width of the hash of a landscape image, its height is half of the size
+    pub size: u32,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: HashAlgorithm::DoubleGradient,
+            size: 16,
+        }
+    }
+}
+
+impl HashConfig {
+    /// Creates a hasher for an image. The hash size is dependent on the image orientation to increase the result
+    /// quality.
+    pub fn hasher(&self, landscape: bool) -> Hasher<Vec<u8>> {
+        let (width, height) = if landscape {
+            (self.size, self.size / 2)
+        } else {
+            (self.size / 2, self.size)
+        };
+        let config = HasherConfig::with_bytes_type().hash_size(width, height);
+        match self.algorithm {
+            HashAlgorithm::Mean => config.hash_alg(HashAlg::Mean),
+            HashAlgorithm::Gradient => config.hash_alg(HashAlg::Gradient),
+            HashAlgorithm::DoubleGradient => config.hash_alg(HashAlg::DoubleGradient),
+            HashAlgorithm::Dct => config.hash_alg(HashAlg::Mean).preproc_dct(),
+            HashAlgorithm::Blockhash => config.hash_alg(HashAlg::Blockhash),
+        }
+        .to_hasher()
+    }
+
+    /// Scales a maximum hash distance given for the default hash size to the number of bits of this configuration
+    pub fn scale_distance(&self, distance: u32) -> u32 {
+        let default_bits = HashConfig::default().bits();
+        (distance * self.bits()).div_ceil(default_bits)
+    }
+
+    /// Gets the number of bits of a hash, which depends on the algorithm. It is determined by hashing an empty image.
+    fn bits(&self) -> u32 {
+        let image = image_23::DynamicImage::new_luma8(1, 1);
+        self.hasher(true).hash_image(&image).as_bytes().len() as u32 * 8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hasher() {
+        let image = image_23::DynamicImage::new_rgb8(64, 32);
+        for algorithm in [
+            HashAlgorithm::Mean,
+            HashAlgorithm::Gradient,
+            HashAlgorithm::DoubleGradient,
+            HashAlgorithm::Dct,
+            HashAlgorithm::Blockhash,
+        ] {
+            for size in HASH_SIZES {
+                let hash_config = HashConfig { algorithm, size };
+                let landscape = hash_config.hasher(true).hash_image(&image);
+                let portrait = hash_config.hasher(false).hash_image(&image);
+                assert_eq!(landscape.as_bytes().len() as u32 * 8, hash_config.bits());
+                assert_eq!(portrait.as_bytes().len(), landscape.as_bytes().len());
+                assert!(hash_config.bits() >= size * size / 4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scale_distance() {
+        assert_eq!(HashConfig::default().scale_distance(14), 14);
+        let hash_config = HashConfig {
+            algorithm: HashAlgorithm::Mean,
+            size: 8,
+        };
+        assert_eq!(hash_config.scale_distance(14), 6);
+        let hash_config = HashConfig {
+            algorithm: HashAlgorithm::Mean,
+            size: 32,
+        };
+        assert_eq!(hash_config.scale_distance(14), 90);
+    }
+}
@@ -0,0 +1,112 @@
+use super::file_item::HashType;
+
+/// Node of the hash tree holding all items with the same hash
+#[derive(Debug, Clone)]
+struct Node {
+    hash: HashType,
+    items: Vec<usize>,
+    /// Indices of the child nodes together with their distance to this node
+    children: Vec<(u32, usize)>,
+}
+
+/// BK-tree of image hashes to find all items with a similar hash without comparing every pair of items. Since the
+/// Hamming distance of two hashes is a metric, only the subtrees that can contain hashes within the maximum distance
+/// need to be searched. Items can be inserted at any time without rebuilding the tree.
+#[derive(Debug, Clone, Default)]
+pub struct HashTree {
+    nodes: Vec<Node>,
+}
+
+impl HashTree {
+    /// Inserts the hash of an item given by its index in the item list
+    pub fn insert(&mut self, hash: &HashType, item: usize) {
+        let mut current = 0;
+        while current < self.nodes.len() {
+            let distance = self.nodes[current].hash.dist(hash);
+            if distance == 0 {
+                self.nodes[current].items.push(item);
+                return;
+            }
+            match self.nodes[current]
+                .children
+                .iter()
+                .find(|(child_distance, _)| *child_distance == distance)
+            {
+                Some((_, child)) => current = *child,
+                None => {
+                    let index = self.nodes.len();
+                    self.nodes[current].children.push((distance, index));
+                    current = index;
+                }
+            }
+        }
+        self.nodes.push(Node {
+            hash: hash.clone(),
+            items: vec![item],
+            children: vec![],
+        });
+    }
+
+    /// Finds all items whose hash has at most the given distance to a hash
+    pub fn find(&self, hash: &HashType, max_distance: u32) -> Vec<usize> {
+        let mut found = vec![];
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(current) = stack.pop() {
+            let node = &self.nodes[current];
+            let distance = node.hash.dist(hash);
+            if distance <= max_distance {
+                found.extend(&node.items);
+            }
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(child_distance, _)| child_distance.abs_diff(distance) <= max_distance)
+                    .map(|(_, child)| *child),
+            );
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(bytes: &[u8]) -> HashType {
+        HashType::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_find() {
+        let hashes = [
+            hash(&[0b0000_0000, 0]),
+            hash(&[0b0000_0001, 0]),
+            hash(&[0b0000_0011, 0]),
+            hash(&[0b0000_0000, 0]),
+            hash(&[0b1111_0000, 0b1111]),
+            hash(&[0b1111_0001, 0b1111]),
+        ];
+        let mut hash_tree = HashTree::default();
+        assert!(hash_tree.find(&hashes[0], 8).is_empty());
+        for (index, hash) in hashes.iter().enumerate() {
+            hash_tree.insert(hash, index);
+        }
+        assert_eq!(hash_tree.find(&hashes[0], 16).len(), 6);
+
+        // Compare the result of the tree with comparing all pairs
+        for hash in &hashes {
+            for max_distance in 0..10 {
+                let mut found = hash_tree.find(hash, max_distance);
+                found.sort_unstable();
+                let expected: Vec<usize> = (0..hashes.len())
+                    .filter(|index| hashes[*index].dist(hash) <= max_distance)
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+}
@@ -3,18 +3,32 @@ extern crate chrono;
 use chrono::DateTime;
 use num_derive::{FromPrimitive, ToPrimitive};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use strum_macros::EnumString;
 
+use super::archive_index;
+use super::clock_offset::ClockOffset;
+use super::companions;
+use super::deletion;
 use super::event;
 use super::file_item;
 use super::file_types::is_any;
+use super::hash_tree;
+use super::journal;
+use super::motion_photo::MotionPhotos;
+use super::path_template;
 use super::resolvers;
+use super::sidecars;
 use super::sieve;
+use super::stack;
+use super::similarity::{Similar, SimilarityConfig};
 
 /// Method how to perform sieve of sieved images
-#[derive(PartialEq, Eq, FromPrimitive, ToPrimitive, Clone, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, FromPrimitive, ToPrimitive, Clone, Debug, Serialize, Deserialize, EnumString)]
+#[strum(ascii_case_insensitive)]
 #[repr(i32)]
 pub enum SieveMethod {
     /// Copy the images to be taken over to the target directory
@@ -27,7 +41,8 @@ pub enum SieveMethod {
     Delete,
 }
 
-#[derive(PartialEq, Eq, FromPrimitive, ToPrimitive, Clone, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, FromPrimitive, ToPrimitive, Clone, Debug, Serialize, Deserialize, EnumString)]
+#[strum(ascii_case_insensitive)]
 #[repr(i32)]
 pub enum DirectoryNames {
     /// Directories are named by year and month
@@ -40,6 +55,8 @@ pub enum DirectoryNames {
     YearAndQuarter,
     /// Directories are named by year and subdirectory by month
     YearAndMonthInSubdirectory,
+    /// Directories are named by a custom path template
+    Custom,
 }
 
 /// Item list containing all file items and all events
@@ -51,6 +68,12 @@ pub struct ItemList {
     pub events: Vec<event::Event>,
     /// Base path that was used to create the item list
     pub path: PathBuf,
+    /// Clock offsets of cameras whose clock was not set correctly
+    #[serde(default)]
+    pub clock_offsets: Vec<ClockOffset>,
+    /// Flag indicating if corrected capture times and orientations are written to the metadata of sieved images
+    #[serde(default)]
+    pub write_metadata: bool,
 }
 
 impl Default for ItemList {
@@ -66,6 +89,8 @@ impl ItemList {
             items: vec![],
             events: vec![],
             path: PathBuf::new(),
+            clock_offsets: vec![],
+            write_metadata: false,
         }
     }
 
@@ -94,8 +119,145 @@ impl ItemList {
 
     /// Finish the synchronization progress
     pub fn finish_synchronizing(&mut self, base_path: &Path) {
+        self.apply_clock_offsets();
         self.items.sort();
         self.path = base_path.to_path_buf();
+        self.find_companions();
+        self.find_sidecars();
+        self.find_stacks();
+    }
+
+    /// Gets the names of all cameras the items were taken with in alphabetical order
+    pub fn cameras(&self) -> Vec<String> {
+        let mut cameras: Vec<String> = self
+            .items
+            .iter()
+            .filter_map(|item| item.get_metadata().camera())
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+        cameras.sort_unstable();
+        cameras
+    }
+
+    /// Gets the clock offset of a camera in seconds
+    pub fn get_clock_offset(&self, camera: &str) -> i64 {
+        self.clock_offsets
+            .iter()
+            .find(|clock_offset| clock_offset.camera == camera)
+            .map_or(0, |clock_offset| clock_offset.seconds)
+    }
+
+    /// Sets the clock offset of a camera in seconds and corrects the capture time of all items taken with it.
+    /// The items are sorted by their corrected capture time and their companions and stacks are found again. The
+    /// similarities are reset, because they refer to the previous order, and have to be calculated again.
+    pub fn set_clock_offset(&mut self, camera: &str, seconds: i64) {
+        self.clock_offsets
+            .retain(|clock_offset| clock_offset.camera != camera);
+        if seconds != 0 {
+            self.clock_offsets.push(ClockOffset {
+                camera: String::from(camera),
+                seconds,
+            });
+        }
+        self.apply_clock_offsets();
+        self.sort_by_corrected_time();
+    }
+
+    /// Sets the manually corrected capture time of an item, None removes the correction. The items are sorted and
+    /// their similarities are reset like after changing a clock offset.
+    pub fn set_manual_timestamp(&mut self, index: usize, timestamp: Option<i64>) {
+        self.items[index].set_manual_timestamp(timestamp);
+        self.sort_by_corrected_time();
+    }
+
+    /// Sorts the items after their capture time was corrected and finds their companions and stacks again
+    fn sort_by_corrected_time(&mut self) {
+        self.items.sort();
+        for item in &mut self.items {
+            item.reset_similars();
+        }
+        self.find_companions();
+        self.find_stacks();
+    }
+
+    /// Sets the clock offset of each item from the clock offset of its camera
+    fn apply_clock_offsets(&mut self) {
+        for index in 0..self.items.len() {
+            let clock_offset = self.items[index]
+                .get_metadata()
+                .camera()
+                .map_or(0, |camera| self.get_clock_offset(&camera));
+            self.items[index].set_clock_offset(clock_offset);
+        }
+    }
+
+    /// Go through all items and pair files with the same name but a different type, like RAW+JPEG. Only the primary
+    /// item of a pair is shown, its companions are kept or discarded and sieved together with it.
+    pub fn find_companions(&mut self) {
+        for item in &mut self.items {
+            item.set_companions(vec![]);
+            item.set_primary(None);
+            item.set_live_photo(false);
+        }
+        for group in companions::find_companions(&self.items) {
+            for index in &group[1..] {
+                self.items[*index].set_primary(Some(group[0]));
+            }
+            // The video of a live photo has the same name as the image, like IMG_0001.HEIC and IMG_0001.MOV
+            let live_photo = !self.items[group[0]].is_video()
+                && group[1..].iter().any(|index| self.items[*index].is_video());
+            self.items[group[0]].set_live_photo(live_photo);
+            self.items[group[0]].set_companions(group[1..].to_vec());
+        }
+    }
+
+    /// Searches the sidecar files of all items in their directories
+    pub fn find_sidecars(&mut self) {
+        let sidecars = sidecars::find_sidecars(&self.items);
+        for (item, sidecars) in self.items.iter_mut().zip(sidecars) {
+            item.set_sidecars(sidecars);
+        }
+    }
+
+    /// Go through all images and group burst and bracket sequences to stacks, each item of a stack knows its stack
+    pub fn find_stacks(&mut self) {
+        for item in &mut self.items {
+            item.set_stack(None);
+        }
+        for stack in stack::find_stacks(&self.items) {
+            for index in &stack.items {
+                self.items[*index].set_stack(Some(stack.clone()));
+            }
+        }
+    }
+
+    /// Sets the take over flag of an item and of all other items in its stack, so that a stack is kept or discarded
+    /// together. Companions of the items are changed as well and all changed items are marked as decided by the
+    /// user. Returns the indices of all changed items.
+    pub fn set_take_over(&mut self, index: usize, take_over: bool) -> Vec<usize> {
+        let stack = match self.items[index].get_stack() {
+            Some(stack) => stack.items.clone(),
+            None => vec![index],
+        };
+        let indices: Vec<usize> = stack
+            .into_iter()
+            .flat_map(|index| self.set_item_take_over(index, take_over))
+            .collect();
+        for index in &indices {
+            self.items[*index].set_decided(true);
+        }
+        indices
+    }
+
+    /// Sets the take over flag of a single item and of its companions. Returns the indices of all changed items.
+    fn set_item_take_over(&mut self, index: usize, take_over: bool) -> Vec<usize> {
+        let mut indices = vec![index];
+        indices.extend(self.items[index].get_companions().iter().copied());
+        for index in &indices {
+            self.items[*index].set_take_over(take_over);
+        }
+        indices
     }
 
     /// Adds an item to the list
@@ -113,80 +275,208 @@ impl ItemList {
         file_item::FileItem::new(item_path, resolver, take_over, encoded_hash)
     }
 
-    /// Go through all images and find similar ones by comparing the timestamp
-    pub fn find_similar(&mut self, max_diff_seconds: i64) {
-        // Find similars based on the taken time
-        if self.items.is_empty() {
-            return;
+    /// Go through all images and videos and find similar ones. Candidates are items taken within the maximum
+    /// timestamp difference and items with a hash within the maximum distance. Each candidate pair is scored and is
+    /// similar if the score reaches the threshold.
+    pub fn find_similar(&mut self, config: &SimilarityConfig) {
+        let mut candidates: HashSet<(usize, usize)> = HashSet::new();
+        if let Some(max_diff) = config.timestamp_max_diff {
+            self.find_similar_timestamps(max_diff, &mut candidates);
+        }
+        if let Some(max_diff) = config.hash_max_diff {
+            self.find_similar_hashes(max_diff, &mut candidates);
         }
-        let mut timestamp: i64 = self.items[0].get_timestamp();
-        let mut start_similar_index: usize = 0;
-        for index in 0..self.items.len() {
-            if timestamp + max_diff_seconds < self.items[index].get_timestamp() {
-                // The item has a larger diff, so set all items between start_similar_index and index to be similar to each other
-                self.set_similar_range(start_similar_index..index);
 
-                start_similar_index = index;
+        let mut similar_lists: Vec<Vec<Similar>> = vec![vec![]; self.items.len()];
+        // Companions are represented by their primary item
+        candidates.retain(|(index, other_index)| {
+            self.items[*index].get_primary().is_none()
+                && self.items[*other_index].get_primary().is_none()
+        });
+        for (index, other_index) in candidates {
+            let score = config.score(&self.items[index], &self.items[other_index]);
+            if score >= config.threshold {
+                similar_lists[index].push(Similar {
+                    index: other_index,
+                    score,
+                });
+                similar_lists[other_index].push(Similar { index, score });
             }
-            timestamp = self.items[index].get_timestamp();
         }
-        // Set all the remaining indices
-        self.set_similar_range(start_similar_index..self.items.len());
-        // Now remove the own index from all items
-        for index in 0..self.items.len() {
-            self.items[index].clean_similars(index);
+        for (item, similar_list) in self.items.iter_mut().zip(similar_lists) {
+            item.set_similars(similar_list);
         }
     }
 
-    /// Sets a range of similar indices for all items in that range
-    fn set_similar_range(&mut self, index_range: std::ops::Range<usize>) {
-        for similar_index in index_range.clone() {
-            self.items[similar_index].add_similar_range(&index_range);
+    /// Adds all pairs of items with a timestamp difference of at most the maximum difference to the candidates
+    fn find_similar_timestamps(
+        &self,
+        max_diff_seconds: i64,
+        candidates: &mut HashSet<(usize, usize)>,
+    ) {
+        let mut indices: Vec<usize> = (0..self.items.len()).collect();
+        indices.sort_by_key(|index| self.items[*index].get_capture_time().utc_millis());
+        for (position, index) in indices.iter().enumerate() {
+            let timestamp = self.items[*index].get_capture_time().utc_millis();
+            for other_index in indices[position + 1..].iter().take_while(|other_index| {
+                self.items[**other_index].get_capture_time().utc_millis()
+                    <= timestamp + max_diff_seconds * 1000
+            }) {
+                candidates.insert(ordered_pair(*index, *other_index));
+            }
         }
     }
 
-    /// Go through all images and find similar ones by comparing the hash
-    pub fn find_similar_hashes(&mut self, max_diff_hash: u32) {
-        let mut similar_lists: HashMap<usize, Vec<usize>> = HashMap::new();
-        for index in 0..self.items.len() {
-            similar_lists.insert(index, vec![]);
+    /// Adds all pairs of items with a hash distance below the maximum difference to the candidates. The hashes are
+    /// inserted into a hash tree one by one and each hash is looked up among the previously inserted ones before.
+    /// Videos are only compared to videos, since their hash is calculated from several frames.
+    fn find_similar_hashes(&self, max_diff_hash: u32, candidates: &mut HashSet<(usize, usize)>) {
+        let Some(max_distance) = max_diff_hash.checked_sub(1) else {
+            return;
+        };
+        let mut image_hash_tree = hash_tree::HashTree::default();
+        let mut video_hash_tree = hash_tree::HashTree::default();
+        for (index, item) in self.items.iter().enumerate() {
+            if let Some(hash) = item.get_hash() {
+                let hash_tree = if item.is_video() {
+                    &mut video_hash_tree
+                } else {
+                    &mut image_hash_tree
+                };
+                for other_index in hash_tree.find(hash, max_distance) {
+                    candidates.insert(ordered_pair(other_index, index));
+                }
+                hash_tree.insert(hash, index);
+            }
         }
-        for index in 0..self.items.len() {
-            for other_index in index + 1..self.items.len() {
-                if other_index != index {
-                    let distance = self.items[index].get_hash_distance(&self.items[other_index]);
-                    if distance < max_diff_hash {
-                        similar_lists.get_mut(&index).unwrap().push(other_index);
-                        similar_lists.get_mut(&other_index).unwrap().push(index);
-                    }
+    }
+
+    /// Gets the groups of similar items. Every item of a group is directly similar to all other items of the group or
+    /// in the same stack, so that chains of similar items do not join dissimilar items. Each item is part of at most
+    /// one group and only groups with at least two items are returned.
+    pub fn similar_groups(&self) -> Vec<Vec<usize>> {
+        let mut groups = vec![];
+        let mut grouped = vec![false; self.items.len()];
+        for start_index in 0..self.items.len() {
+            if grouped[start_index] {
+                continue;
+            }
+            let mut group = vec![start_index];
+            for neighbour in self.neighbours(start_index) {
+                if !grouped[neighbour]
+                    && !group.contains(&neighbour)
+                    && group.iter().all(|index| self.are_neighbours(*index, neighbour))
+                {
+                    group.push(neighbour);
+                }
+            }
+            if group.len() > 1 {
+                for index in &group {
+                    grouped[*index] = true;
                 }
+                group.sort_unstable();
+                groups.push(group);
             }
         }
-        for index in 0..self.items.len() {
-            let similar_list = similar_lists.get(&index).unwrap();
-            self.items[index].add_similar_vec(similar_list);
-            self.items[index].clean_similars(index);
+        groups
+    }
+
+    /// Gets the items of the same stack and the similar items of an item ordered by their similarity
+    fn neighbours(&self, index: usize) -> Vec<usize> {
+        let item = &self.items[index];
+        item.get_stack()
+            .iter()
+            .flat_map(|stack| stack.items.clone())
+            .chain(item.get_similars().iter().map(|similar| similar.index))
+            .filter(|neighbour| *neighbour != index)
+            .collect()
+    }
+
+    /// Checks if two items are similar or in the same stack
+    fn are_neighbours(&self, first: usize, second: usize) -> bool {
+        self.neighbours(first).contains(&second) || self.neighbours(second).contains(&first)
+    }
+
+    /// Keeps the best item of a group and discards all other items of the group. Companions are kept or discarded
+    /// together with their primary item, while items of a stack are rated individually. Items the user already
+    /// decided to keep or discard are not changed.
+    pub fn keep_best(&mut self, group: &[usize], best: usize) {
+        for index in group {
+            if !self.items[*index].is_decided() {
+                self.set_item_take_over(*index, *index == best);
+            }
         }
     }
 
     /// Sieves an item list taking the take_over flag into account to a new directory.
-    /// The progress is reported by calling a callback function with the file that is currently processed.
+    /// The progress is reported by calling a callback function with an event for every processed file.
+    /// Deleted files are moved according to the deletion policy and the returned journal can be used to undo the
+    /// sieve process. Files found in the archive index of the target directory are skipped and the index is
+    /// updated with all files put to the target directory.
     pub fn sieve(
         &self,
         path: &Path,
         sieve_method: SieveMethod,
-        sieve_directory_names: DirectoryNames,
-        progress_callback: impl Fn(String),
-    ) {
-        let sieve_io = sieve::FileSieveIO {};
+        path_template: &path_template::PathTemplate,
+        motion_photos: MotionPhotos,
+        deletion: &deletion::Deletion,
+        archive_index: &mut archive_index::ArchiveIndex,
+        progress_callback: impl Fn(sieve::SieveEvent),
+    ) -> journal::SieveJournal {
+        deletion.expire_quarantine();
+        let index = RefCell::new(std::mem::take(archive_index));
+        let file_io = sieve::FileSieveIO {
+            archive_index: Some(&index),
+        };
+        let staging_directory = self
+            .journal_directory(path, &sieve_method)
+            .join(journal::STAGING_DIRECTORY);
+        let sieve_io = journal::JournalSieveIO::new(&file_io, &staging_directory, deletion);
         sieve::sieve(
             self,
             path,
             sieve_method,
-            sieve_directory_names,
+            path_template,
+            motion_photos,
             &sieve_io,
             progress_callback,
         );
+        let journal = sieve_io.into_journal();
+        *archive_index = index.into_inner();
+        journal
+    }
+
+    /// Gets the directory where the journal of a sieve process is stored. This is the target directory or the
+    /// source directory if the sieve method does not use a target directory.
+    pub fn journal_directory(&self, path: &Path, sieve_method: &SieveMethod) -> PathBuf {
+        if *sieve_method == SieveMethod::Delete {
+            self.path.clone()
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    /// Performs a dry run of the sieve process and returns the plan of all file operations without touching
+    /// any file. Files found in the archive index are planned to be skipped.
+    pub fn plan_sieve(
+        &self,
+        path: &Path,
+        sieve_method: SieveMethod,
+        path_template: &path_template::PathTemplate,
+        motion_photos: MotionPhotos,
+        archive_index: &archive_index::ArchiveIndex,
+    ) -> sieve::SievePlan {
+        let sieve_io = sieve::DryRunSieveIO::with_archive_index(archive_index.clone());
+        sieve::sieve(
+            self,
+            path,
+            sieve_method,
+            path_template,
+            motion_photos,
+            &sieve_io,
+            |_| {},
+        );
+        sieve_io.into_plan()
     }
 
     /// Gets the event which a file item belongs to
@@ -199,10 +489,18 @@ impl ItemList {
     }
 }
 
+/// Gets a pair of item indices with the smaller index first
+fn ordered_pair(index: usize, other_index: usize) -> (usize, usize) {
+    (index.min(other_index), index.max(other_index))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::item_sort_list::item_traits::PropertyResolver;
+    use crate::item_sort_list::Timestamp;
+    use crate::item_sort_list::metadata::Metadata;
+    use crate::item_sort_list::similarity::MAX_SCORE;
     use std::cell::RefCell;
     use std::rc::Rc;
     extern crate base64;
@@ -219,11 +517,11 @@ mod tests {
     }
 
     impl PropertyResolver for MockResolver {
-        fn get_timestamp(&self) -> i64 {
+        fn get_timestamp(&self) -> Timestamp {
             let return_values: [i64; 6] = [0, 4, 8, 14, 64, 65];
             let call_count = *self.call_count.borrow_mut();
             self.call_count.replace(call_count + 1);
-            return_values[call_count]
+            Timestamp::from_seconds(return_values[call_count])
         }
 
         fn get_orientation(&self) -> Option<crate::item_sort_list::Orientation> {
@@ -248,16 +546,52 @@ mod tests {
             items,
             events: vec![],
             path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
         };
 
-        item_list.find_similar(5);
+        item_list.find_similar(&SimilarityConfig {
+            timestamp_max_diff: Some(5),
+            hash_max_diff: None,
+            threshold: 30,
+        });
 
-        assert_eq!(2, item_list.items[0].get_similars().len());
+        assert_eq!(1, item_list.items[0].get_similars().len());
         assert_eq!(2, item_list.items[1].get_similars().len());
-        assert_eq!(2, item_list.items[2].get_similars().len());
+        assert_eq!(1, item_list.items[2].get_similars().len());
         assert_eq!(0, item_list.items[3].get_similars().len());
         assert_eq!(1, item_list.items[4].get_similars().len());
         assert_eq!(1, item_list.items[5].get_similars().len());
+        // Closer items have a higher score and come first
+        assert_eq!(
+            &vec![Similar {
+                index: 5,
+                score: 60
+            }],
+            item_list.items[4].get_similars()
+        );
+        assert_eq!(
+            &vec![
+                Similar {
+                    index: 0,
+                    score: 43
+                },
+                Similar {
+                    index: 2,
+                    score: 43
+                }
+            ],
+            item_list.items[1].get_similars()
+        );
+
+        // A higher threshold only keeps the closest items
+        item_list.find_similar(&SimilarityConfig {
+            timestamp_max_diff: Some(5),
+            hash_max_diff: None,
+            threshold: 50,
+        });
+        assert_eq!(0, item_list.items[1].get_similars().len());
+        assert_eq!(1, item_list.items[4].get_similars().len());
     }
 
     #[test]
@@ -279,12 +613,158 @@ mod tests {
             items,
             events: vec![],
             path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
         };
 
-        item_list.find_similar_hashes(2);
+        let config = SimilarityConfig {
+            timestamp_max_diff: None,
+            hash_max_diff: Some(2),
+            threshold: 30,
+        };
+        item_list.find_similar(&config);
 
         assert_eq!(2, item_list.items[0].get_similars().len());
         assert_eq!(2, item_list.items[4].get_similars().len());
+
+        // Videos are only compared to videos
+        let encoded = general_purpose::STANDARD.encode("a");
+        let mut video = file_item::FileItem::dummy("test.mp4", 0, true);
+        video.set_encoded_hash(&encoded);
+        item_list.items.push(video.clone());
+        item_list.items.push(video);
+
+        item_list.find_similar(&config);
+
+        assert_eq!(2, item_list.items[0].get_similars().len());
+        assert_eq!(
+            &vec![Similar {
+                index: 7,
+                score: 66
+            }],
+            item_list.items[6].get_similars()
+        );
+    }
+
+    #[test]
+    fn stacks() {
+        let mut item_list = ItemList {
+            items: vec![],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        for (timestamp, exposure_bias) in [(0, -100), (1, 100), (100, 0)] {
+            let mut item = file_item::FileItem::dummy("test.jpg", timestamp, true);
+            item.set_metadata(Metadata {
+                camera_model: Some(String::from("A")),
+                exposure_bias: Some(exposure_bias),
+                ..Metadata::default()
+            });
+            item_list.items.push(item);
+        }
+
+        item_list.find_stacks();
+        assert_eq!(
+            item_list.items[1].get_stack().unwrap().kind,
+            crate::item_sort_list::StackKind::Bracket
+        );
+        assert_eq!(item_list.items[0].get_stack(), item_list.items[1].get_stack());
+        assert!(item_list.items[2].get_stack().is_none());
+
+        // The whole stack is discarded together
+        assert_eq!(item_list.set_take_over(1, false), vec![0, 1]);
+        assert!(!item_list.items[0].get_take_over());
+        assert_eq!(item_list.set_take_over(2, false), vec![2]);
+    }
+
+    #[test]
+    fn companions() {
+        let mut item_list = ItemList {
+            items: vec![
+                file_item::FileItem::dummy("dir/DSC_0001.JPG", 0, true),
+                file_item::FileItem::dummy("dir/DSC_0001.NEF", 0, true),
+                file_item::FileItem::dummy("dir/DSC_0002.JPG", 1, true),
+                file_item::FileItem::dummy("dir/IMG_0003.MOV", 100, true),
+                file_item::FileItem::dummy("dir/IMG_0003.HEIC", 100, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+
+        item_list.find_companions();
+        assert_eq!(item_list.items[0].get_companions(), &vec![1]);
+        assert_eq!(item_list.items[1].get_primary(), Some(0));
+        assert_eq!(item_list.items[2].get_primary(), None);
+        assert_eq!(item_list.items[3].get_primary(), Some(4));
+        assert!(!item_list.items[0].is_motion_photo());
+        assert!(item_list.items[4].is_motion_photo());
+
+        // Companions are never similar to other items
+        item_list.find_similar(&SimilarityConfig {
+            timestamp_max_diff: Some(5),
+            hash_max_diff: None,
+            threshold: 0,
+        });
+        assert_eq!(item_list.items[0].get_similars().len(), 1);
+        assert!(item_list.items[1].get_similars().is_empty());
+
+        // Companions are discarded and kept together with their primary item
+        assert_eq!(item_list.set_take_over(0, false), vec![0, 1]);
+        assert!(!item_list.items[1].get_take_over());
+        item_list.keep_best(&[0, 2], 0);
+        assert!(item_list.items[1].get_take_over());
+        assert!(!item_list.items[2].get_take_over());
+    }
+
+    #[test]
+    fn similar_groups() {
+        let mut item_list = ItemList {
+            items: (0..5)
+                .map(|timestamp| file_item::FileItem::dummy("test.jpg", timestamp * 10, true))
+                .collect(),
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        let similar = |index| Similar {
+            index,
+            score: MAX_SCORE,
+        };
+        item_list.items[0].set_similars(vec![similar(1)]);
+        item_list.items[1].set_similars(vec![similar(0), similar(2)]);
+        item_list.items[2].set_similars(vec![similar(1)]);
+        let stack = stack::Stack {
+            kind: stack::StackKind::Burst,
+            items: vec![3, 4],
+        };
+        item_list.items[3].set_stack(Some(stack.clone()));
+        item_list.items[4].set_stack(Some(stack));
+
+        // Item 2 is only similar to item 0 via item 1, so it is not part of their group
+        let groups = item_list.similar_groups();
+        assert_eq!(groups, vec![vec![0, 1], vec![3, 4]]);
+
+        item_list.keep_best(&groups[0], 1);
+        assert!(!item_list.items[0].get_take_over());
+        assert!(item_list.items[1].get_take_over());
+        assert!(item_list.items[2].get_take_over());
+        assert!(item_list.items[3].get_take_over());
+
+        // Decisions of the user are not changed
+        item_list.set_take_over(0, true);
+        assert!(item_list.items[0].is_decided());
+        item_list.keep_best(&groups[0], 1);
+        assert!(item_list.items[0].get_take_over());
+
+        // The best item of a stack is kept without keeping the whole stack
+        item_list.keep_best(&groups[1], 4);
+        assert!(!item_list.items[3].get_take_over());
+        assert!(item_list.items[4].get_take_over());
     }
 
     #[test]
@@ -293,6 +773,8 @@ mod tests {
             items: vec![],
             events: vec![],
             path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
         };
 
         item_list.check_and_add(Path::new("tests/test_no_date.jpg"));
@@ -312,4 +794,71 @@ mod tests {
         item_list.drain_missing();
         assert_eq!(4, item_list.items.len());
     }
+    #[test]
+    fn clock_offsets() {
+        let mut item_list = ItemList {
+            items: vec![],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        for (timestamp, camera_model) in [(100, "B"), (0, "A"), (50, "A")] {
+            let mut item = file_item::FileItem::dummy("test.jpg", timestamp, true);
+            item.set_metadata(Metadata {
+                camera_model: Some(String::from(camera_model)),
+                ..Metadata::default()
+            });
+            item_list.items.push(item);
+        }
+        item_list.items.push(file_item::FileItem::dummy("test.mp4", 10, true));
+        assert_eq!(item_list.cameras(), vec!["A", "B"]);
+
+        item_list.items[1].set_similars(vec![Similar {
+            index: 2,
+            score: MAX_SCORE,
+        }]);
+
+        // Items are sorted by the corrected capture time and the similarities are reset
+        item_list.set_clock_offset("B", -90);
+        assert_eq!(item_list.get_clock_offset("B"), -90);
+        assert_eq!(item_list.get_clock_offset("A"), 0);
+        assert!(item_list.items.iter().all(|item| item.get_similars().is_empty()));
+        let timestamps: Vec<i64> = item_list
+            .items
+            .iter()
+            .map(|item| item.get_timestamp())
+            .collect();
+        assert_eq!(timestamps, vec![0, 10, 10, 50]);
+
+        item_list.set_clock_offset("B", 0);
+        assert!(item_list.clock_offsets.is_empty());
+        assert!(item_list.items.iter().all(|item| item.get_clock_offset() == 0));
+    }
+
+    #[test]
+    fn manual_timestamp() {
+        let mut item_list = ItemList {
+            items: vec![
+                file_item::FileItem::dummy("test1.jpg", 0, true),
+                file_item::FileItem::dummy("test2.jpg", 10, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        item_list.items[0].set_similars(vec![Similar {
+            index: 1,
+            score: MAX_SCORE,
+        }]);
+
+        item_list.set_manual_timestamp(0, Some(20));
+        assert_eq!(item_list.items[0].path, PathBuf::from("test2.jpg"));
+        assert_eq!(item_list.items[1].get_timestamp(), 20);
+        assert!(item_list.items.iter().all(|item| item.get_similars().is_empty()));
+
+        item_list.set_manual_timestamp(1, None);
+        assert_eq!(item_list.items[0].path, PathBuf::from("test1.jpg"));
+    }
 }
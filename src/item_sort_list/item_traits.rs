@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 
+use super::metadata::Metadata;
+use super::timestamp::Timestamp;
+
 /// Image orientation
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Orientation {
@@ -9,8 +12,15 @@ pub enum Orientation {
     Portrait270,
 }
 
-/// Trait to get a timestamp and an optional orientation from a file
+/// Trait to get a timestamp, an optional orientation and further metadata from a file
 pub trait PropertyResolver {
-    fn get_timestamp(&self) -> i64;
+    fn get_timestamp(&self) -> Timestamp;
     fn get_orientation(&self) -> Option<Orientation>;
+    fn get_metadata(&self) -> Metadata {
+        Metadata::default()
+    }
+    /// Gets all metadata of the file as pairs of name and displayable value
+    fn get_details(&self) -> Vec<(String, String)> {
+        vec![]
+    }
 }
@@ -0,0 +1,465 @@
+use std::{
+    cell::RefCell,
+    fs::{copy, create_dir_all, remove_dir, remove_dir_all, remove_file},
+    io::{Error, ErrorKind},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::deletion::{Deletion, forget_discarded};
+use super::file_types::is_metadata_writable;
+use super::metadata_writer::MetadataUpdate;
+use super::sieve::{FileSieveIO, SieveEvent, SieveIO, SieveStatistics};
+use super::SieveMethod;
+
+/// Name of the directory where deleted files are kept until the next sieve
+pub const STAGING_DIRECTORY: &str = ".image_sieve_undo";
+
+/// A single file operation performed by a sieve process
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalEntry {
+    /// A file was copied from source to target
+    Copied { source: PathBuf, target: PathBuf },
+    /// A file was moved from source to target
+    Moved { source: PathBuf, target: PathBuf },
+    /// A file was deleted by moving it to the staging directory, the trash or the quarantine directory
+    Deleted { source: PathBuf, staged: PathBuf },
+    /// A file was created, like an XMP sidecar file with corrected metadata
+    Created { path: PathBuf },
+    /// A file that was not copied by the sieve process was modified, the original is kept in the staging directory
+    Modified { path: PathBuf, backup: PathBuf },
+}
+
+/// Journal of the last sieve process that allows to undo it
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SieveJournal {
+    /// Time when the sieve process was started
+    pub timestamp: i64,
+    /// Directory which contains the deleted files
+    pub staging_directory: PathBuf,
+    /// Directories created by the sieve process in the order of creation
+    pub created_directories: Vec<PathBuf>,
+    /// Performed operations in the order of execution
+    pub entries: Vec<JournalEntry>,
+}
+
+impl SieveJournal {
+    /// Undoes all operations of the journal in reverse order. Copies and created files are removed, moved files are
+    /// moved back and deleted and modified files are restored from the staging directory. Directories created by the
+    /// sieve process are removed if they are empty. The progress is reported as sieve events, restored files are
+    /// reported as moved. Returns a journal with all entries that could not be undone.
+    pub fn undo(&self, progress_callback: impl Fn(SieveEvent)) -> SieveJournal {
+        let mut remaining = SieveJournal {
+            timestamp: self.timestamp,
+            staging_directory: self.staging_directory.clone(),
+            created_directories: vec![],
+            entries: vec![],
+        };
+        let mut statistics = SieveStatistics::default();
+        progress_callback(SieveEvent::Started {
+            total: self.entries.len(),
+        });
+
+        for entry in self.entries.iter().rev() {
+            let (result, event) = match entry {
+                JournalEntry::Copied { target, .. } => (
+                    remove_file(target),
+                    SieveEvent::Deleted {
+                        path: target.clone(),
+                    },
+                ),
+                JournalEntry::Moved { source, target } => (
+                    restore(target, source),
+                    SieveEvent::Moved {
+                        source: target.clone(),
+                        target: source.clone(),
+                    },
+                ),
+                JournalEntry::Deleted { source, staged } => (
+                    restore(staged, source).map(|_| forget_discarded(staged)),
+                    SieveEvent::Moved {
+                        source: staged.clone(),
+                        target: source.clone(),
+                    },
+                ),
+                JournalEntry::Created { path } => (
+                    remove_file(path),
+                    SieveEvent::Deleted { path: path.clone() },
+                ),
+                JournalEntry::Modified { path, backup } => (
+                    copy(backup, path).and_then(|_| remove_file(backup)),
+                    SieveEvent::Moved {
+                        source: backup.clone(),
+                        target: path.clone(),
+                    },
+                ),
+            };
+            match result {
+                Ok(_) => {
+                    if let SieveEvent::Deleted { .. } = event {
+                        statistics.deleted += 1;
+                    } else {
+                        statistics.moved += 1;
+                    }
+                    progress_callback(event);
+                }
+                Err(e) => {
+                    statistics.failed += 1;
+                    let path = match entry {
+                        JournalEntry::Copied { target, .. } => target,
+                        JournalEntry::Moved { source, .. }
+                        | JournalEntry::Deleted { source, .. } => source,
+                        JournalEntry::Created { path } | JournalEntry::Modified { path, .. } => path,
+                    };
+                    progress_callback(SieveEvent::failed("Undo", path, &e));
+                    remaining.entries.insert(0, entry.clone());
+                }
+            }
+        }
+
+        remove_dir(&self.staging_directory).ok();
+        for directory in self.created_directories.iter().rev() {
+            if remove_dir(directory).is_err() && directory.exists() {
+                remaining.created_directories.insert(0, directory.clone());
+            }
+        }
+
+        progress_callback(SieveEvent::Finished { statistics });
+        remaining
+    }
+
+    /// Checks if the journal does not contain anything to undo
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.created_directories.is_empty()
+    }
+
+    /// Finally removes the files kept in the staging directory, the journal cannot be undone anymore afterwards
+    pub fn purge(&self) {
+        if self.staging_directory.exists() {
+            remove_dir_all(&self.staging_directory).ok();
+        }
+    }
+}
+
+/// Moves a file back to its original location, but never overwrites an existing file
+fn restore(from: &Path, to: &Path) -> Result<(), Error> {
+    if to.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("File already exists: {}", to.display()),
+        ));
+    }
+    let file_io = FileSieveIO::default();
+    if let Some(parent) = to.parent() {
+        file_io.create_dir_all(parent)?;
+    }
+    file_io.r#move(from, &mut to.to_path_buf())
+}
+
+/// Implementation of SieveIO that records all operations of another SieveIO in a journal. Instead of removing files,
+/// they are moved to a staging directory, the trash or the quarantine directory depending on the deletion policy so
+/// that the sieve process can be undone.
+pub struct JournalSieveIO<'a, T: SieveIO> {
+    sieve_io: &'a T,
+    deletion: &'a Deletion,
+    journal: RefCell<SieveJournal>,
+}
+
+impl<'a, T: SieveIO> JournalSieveIO<'a, T> {
+    /// Creates a new journaling IO object which moves deleted files to the given staging directory or according
+    /// to the deletion policy
+    pub fn new(sieve_io: &'a T, staging_directory: &Path, deletion: &'a Deletion) -> Self {
+        Self {
+            sieve_io,
+            deletion,
+            journal: RefCell::new(SieveJournal {
+                timestamp: chrono::Utc::now().timestamp(),
+                staging_directory: staging_directory.to_path_buf(),
+                created_directories: vec![],
+                entries: vec![],
+            }),
+        }
+    }
+
+    /// Consumes the IO object and returns the recorded journal
+    pub fn into_journal(self) -> SieveJournal {
+        self.journal.into_inner()
+    }
+
+    fn push(&self, entry: JournalEntry) {
+        self.journal.borrow_mut().entries.push(entry);
+    }
+}
+
+impl<T: SieveIO> SieveIO for JournalSieveIO<'_, T> {
+    fn copy(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error> {
+        self.sieve_io.copy(src, dest)?;
+        self.push(JournalEntry::Copied {
+            source: src.to_path_buf(),
+            target: dest.clone(),
+        });
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), Error> {
+        let mut staged = {
+            let journal = self.journal.borrow();
+            self.deletion.discard_target(
+                path,
+                &journal.staging_directory,
+                journal.entries.len(),
+                journal.timestamp,
+            )?
+        };
+        // Discarded files are neither checked against nor added to the archive index of the target
+        if let Err(e) = FileSieveIO::default().r#move(path, &mut staged) {
+            forget_discarded(&staged);
+            return Err(e);
+        }
+        self.push(JournalEntry::Deleted {
+            source: path.to_path_buf(),
+            staged,
+        });
+        Ok(())
+    }
+
+    fn r#move(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error> {
+        self.sieve_io.r#move(src, dest)?;
+        self.push(JournalEntry::Moved {
+            source: src.to_path_buf(),
+            target: dest.clone(),
+        });
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        let mut new_directories: Vec<PathBuf> = path
+            .ancestors()
+            .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        self.sieve_io.create_dir_all(path)?;
+        new_directories.reverse();
+        self.journal
+            .borrow_mut()
+            .created_directories
+            .append(&mut new_directories);
+        Ok(())
+    }
+
+    fn write_metadata(&self, path: &Path, update: &MetadataUpdate) -> Result<Option<PathBuf>, Error> {
+        // Files copied by this sieve process are removed on undo, all other files are backed up before they are
+        // modified in place, so that the original content is restored on undo
+        let copied = self.journal.borrow().entries.iter().any(
+            |entry| matches!(entry, JournalEntry::Copied { target, .. } if target == path),
+        );
+        if !copied && is_metadata_writable(path) {
+            let backup = {
+                let journal = self.journal.borrow();
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                journal
+                    .staging_directory
+                    .join(format!("{}_{}", journal.entries.len(), file_name))
+            };
+            create_dir_all(backup.parent().unwrap_or(Path::new("")))?;
+            copy(path, &backup)?;
+            self.push(JournalEntry::Modified {
+                path: path.to_path_buf(),
+                backup,
+            });
+        }
+        let sidecar = self.sieve_io.write_metadata(path, update)?;
+        if let Some(sidecar) = &sidecar {
+            self.push(JournalEntry::Created {
+                path: sidecar.clone(),
+            });
+        }
+        Ok(sidecar)
+    }
+
+    fn extract(&self, src: &Path, range: Range<u64>, dest: &mut PathBuf) -> Result<(), Error> {
+        // Undoing an extraction is the same as undoing a copy
+        self.sieve_io.extract(src, range, dest)?;
+        self.push(JournalEntry::Copied {
+            source: src.to_path_buf(),
+            target: dest.clone(),
+        });
+        Ok(())
+    }
+
+    fn carry_sidecar(&self, src: &Path, dest: &mut PathBuf, sieve_method: SieveMethod) -> Result<(), Error> {
+        self.sieve_io.carry_sidecar(src, dest, sieve_method)?;
+        let (source, target) = (src.to_path_buf(), dest.clone());
+        self.push(if sieve_method == SieveMethod::Copy {
+            JournalEntry::Copied { source, target }
+        } else {
+            JournalEntry::Moved { source, target }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_sort_list::archive_index::ArchiveIndex;
+    use crate::item_sort_list::sieve::sieve;
+    use crate::item_sort_list::{
+        DirectoryNames, FileItem, ItemList, MotionPhotos, PathTemplate, SieveMethod,
+    };
+    use std::fs::{copy, create_dir_all};
+
+    #[test]
+    fn test_undo() {
+        let source = Path::new("tests/journal_source");
+        let target = Path::new("tests/journal_target");
+        remove_dir_all(source).ok();
+        remove_dir_all(target).ok();
+        create_dir_all(source).unwrap();
+        copy("tests/test.jpg", source.join("keep.jpg")).unwrap();
+        copy("tests/test2.JPG", source.join("discard.jpg")).unwrap();
+
+        let item_list = ItemList {
+            items: vec![
+                FileItem::dummy("tests/journal_source/keep.jpg", 0, true),
+                FileItem::dummy("tests/journal_source/discard.jpg", 0, false),
+            ],
+            events: vec![],
+            path: source.to_path_buf(),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        let file_io = FileSieveIO::default();
+        let deletion = Deletion::default();
+        let journal_io = JournalSieveIO::new(&file_io, &target.join(STAGING_DIRECTORY), &deletion);
+
+        sieve(
+            &item_list,
+            target,
+            SieveMethod::MoveAndDelete,
+            &PathTemplate::new(&DirectoryNames::YearAndMonth, "").unwrap(),
+            MotionPhotos::Keep,
+            &journal_io,
+            |_| {},
+        );
+
+        let journal = journal_io.into_journal();
+        let sieved = [
+            source.join("keep.jpg").exists(),
+            source.join("discard.jpg").exists(),
+            target.join("1970-01/keep.jpg").exists(),
+            target
+                .join(STAGING_DIRECTORY)
+                .join("1_discard.jpg")
+                .exists(),
+        ];
+        let remaining = journal.undo(|_| {});
+        let restored = [
+            source.join("keep.jpg").exists(),
+            source.join("discard.jpg").exists(),
+            target.exists(),
+        ];
+        remove_dir_all(source).ok();
+        remove_dir_all(target).ok();
+
+        assert_eq!(journal.entries.len(), 2);
+        assert_eq!(
+            journal.created_directories,
+            vec![target.to_path_buf(), target.join("1970-01")]
+        );
+        assert_eq!(sieved, [false, false, true, true]);
+        assert!(remaining.is_empty());
+        assert_eq!(restored, [true, true, false]);
+    }
+
+    #[test]
+    fn test_discard_archived() {
+        let source = Path::new("tests/journal_archived");
+        remove_dir_all(source).ok();
+        create_dir_all(source).unwrap();
+        let discarded = source.join("discard.jpg");
+        copy("tests/test.jpg", &discarded).unwrap();
+
+        let mut archive_index = ArchiveIndex::default();
+        archive_index.update(Path::new("tests/subdir"));
+        let index = RefCell::new(archive_index);
+        let file_io = FileSieveIO {
+            archive_index: Some(&index),
+        };
+        let deletion = Deletion::default();
+        let staging_directory = source.join(STAGING_DIRECTORY);
+        let journal_io = JournalSieveIO::new(&file_io, &staging_directory, &deletion);
+
+        // The content is already in the archive, but the file is discarded anyway
+        let result = journal_io.remove_file(&discarded);
+        let discarded = (
+            discarded.exists(),
+            staging_directory.join("0_discard.jpg").exists(),
+        );
+        remove_dir_all(source).ok();
+
+        assert!(result.is_ok());
+        assert_eq!(discarded, (false, true));
+        assert_eq!(index.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_undo_metadata() {
+        let source = Path::new("tests/journal_metadata_source");
+        let target = Path::new("tests/journal_metadata_target");
+        remove_dir_all(source).ok();
+        remove_dir_all(target).ok();
+        create_dir_all(source).unwrap();
+        copy("tests/test.jpg", source.join("image.jpg")).unwrap();
+        copy("tests/test.nef", source.join("raw.nef")).unwrap();
+
+        let mut item_list = ItemList {
+            items: vec![
+                FileItem::dummy("tests/journal_metadata_source/image.jpg", 0, true),
+                FileItem::dummy("tests/journal_metadata_source/raw.nef", 0, true),
+            ],
+            events: vec![],
+            path: source.to_path_buf(),
+            clock_offsets: vec![],
+            write_metadata: true,
+        };
+        for item in &mut item_list.items {
+            item.set_clock_offset(3600);
+        }
+        let file_io = FileSieveIO::default();
+        let deletion = Deletion::default();
+        let journal_io = JournalSieveIO::new(&file_io, &target.join(STAGING_DIRECTORY), &deletion);
+
+        sieve(
+            &item_list,
+            target,
+            SieveMethod::Move,
+            &PathTemplate::new(&DirectoryNames::YearAndMonth, "").unwrap(),
+            MotionPhotos::Keep,
+            &journal_io,
+            |_| {},
+        );
+
+        let journal = journal_io.into_journal();
+        let original = std::fs::read("tests/test.jpg").unwrap();
+        let modified = std::fs::read(target.join("1970-01/image.jpg")).ok();
+        let remaining = journal.undo(|_| {});
+        let restored = std::fs::read(source.join("image.jpg")).ok();
+        let raw_restored = source.join("raw.nef").exists();
+        let target_exists = target.exists();
+        remove_dir_all(source).ok();
+        remove_dir_all(target).ok();
+
+        assert!(journal.entries.contains(&JournalEntry::Created {
+            path: target.join("1970-01/raw.xmp")
+        }));
+        assert!(modified.is_some_and(|modified| modified != original));
+        assert!(remaining.is_empty());
+        // The modified image is restored and the sidecar file is removed together with the target directory
+        assert_eq!(restored, Some(original));
+        assert!(raw_restored);
+        assert!(!target_exists);
+    }
+}
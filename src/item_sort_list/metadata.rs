@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+/// Metadata of a file item read by the property resolvers
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    /// Manufacturer of the camera
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_make: Option<String>,
+    /// Model of the camera
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_model: Option<String>,
+    /// Serial number of the camera body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_serial: Option<String>,
+    /// Width and height of the image or video in pixels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<(u32, u32)>,
+    /// Milliseconds to add to the capture time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_second: Option<u32>,
+    /// Exposure bias in hundredths of an EV
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposure_bias: Option<i32>,
+    /// Flag indicating that the camera took the image as part of an exposure bracket
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub auto_bracket: bool,
+    /// Model of the lens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lens: Option<String>,
+    /// Focal length in tenths of a millimetre
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focal_length: Option<u32>,
+    /// Aperture as f-number in tenths
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub f_number: Option<u32>,
+    /// Exposure time in seconds as numerator and denominator
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposure_time: Option<(u32, u32)>,
+    /// ISO speed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iso: Option<u32>,
+    /// Latitude and longitude in millionths of a degree
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gps: Option<(i32, i32)>,
+    /// Duration of a video in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    /// Name of the video codec
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    /// Offset of the video embedded in a motion photo in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedded_video: Option<u64>,
+    /// Flag indicating that the EXIF orientation is mirrored or invalid and is normalized when metadata is written
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub irregular_orientation: bool,
+}
+
+impl Metadata {
+    /// Gets the name identifying the camera by make, model and serial number, None if the model is unknown
+    pub fn camera(&self) -> Option<String> {
+        let model = self.camera_model.as_ref()?;
+        let mut camera = match &self.camera_make {
+            // Many manufacturers repeat the make in the model name
+            Some(make) if !model.starts_with(make.as_str()) => format!("{} {}", make, model),
+            _ => model.clone(),
+        };
+        if let Some(serial) = &self.camera_serial {
+            camera.push_str(&format!(" ({})", serial));
+        }
+        Some(camera)
+    }
+
+    /// Gets the exposure time formatted like 1/250 s or 2.5 s
+    pub fn exposure_time_string(&self) -> Option<String> {
+        let (numerator, denominator) = self.exposure_time?;
+        if numerator == 0 || denominator == 0 {
+            None
+        } else if numerator < denominator {
+            Some(format!("1/{} s", (denominator as f64 / numerator as f64).round()))
+        } else {
+            Some(format!("{} s", numerator as f64 / denominator as f64))
+        }
+    }
+
+    /// Gets a short summary of the camera settings, the dimensions and the location separated by commas
+    pub fn summary(&self) -> String {
+        let mut parts = vec![];
+        parts.extend(self.camera());
+        parts.extend(self.lens.clone());
+        parts.extend(
+            self.focal_length
+                .map(|focal_length| format!("{} mm", focal_length as f64 / 10.0)),
+        );
+        parts.extend(
+            self.f_number
+                .map(|f_number| format!("f/{}", f_number as f64 / 10.0)),
+        );
+        parts.extend(self.exposure_time_string());
+        parts.extend(self.iso.map(|iso| format!("ISO {}", iso)));
+        parts.extend(
+            self.resolution
+                .map(|(width, height)| format!("{}x{}", width, height)),
+        );
+        parts.extend(self.duration.map(|duration| {
+            let seconds = duration / 1000;
+            format!("{}:{:02}", seconds / 60, seconds % 60)
+        }));
+        parts.extend(self.codec.clone());
+        parts.extend(self.gps.map(|(latitude, longitude)| {
+            format!(
+                "{:.5}, {:.5}",
+                latitude as f64 / 1_000_000.0,
+                longitude as f64 / 1_000_000.0
+            )
+        }));
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera() {
+        let mut metadata = Metadata::default();
+        assert_eq!(metadata.camera(), None);
+        metadata.camera_make = Some(String::from("Canon"));
+        assert_eq!(metadata.camera(), None);
+        metadata.camera_model = Some(String::from("Canon EOS 5D"));
+        assert_eq!(metadata.camera(), Some(String::from("Canon EOS 5D")));
+        metadata.camera_model = Some(String::from("D90"));
+        metadata.camera_serial = Some(String::from("1234"));
+        assert_eq!(metadata.camera(), Some(String::from("Canon D90 (1234)")));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut metadata = Metadata::default();
+        assert_eq!(metadata.summary(), "");
+        metadata.camera_model = Some(String::from("D90"));
+        metadata.lens = Some(String::from("18-105 mm"));
+        metadata.focal_length = Some(500);
+        metadata.f_number = Some(18);
+        metadata.exposure_time = Some((10, 2500));
+        metadata.iso = Some(200);
+        metadata.resolution = Some((4288, 2848));
+        metadata.gps = Some((48_858_000, 2_294_500));
+        assert_eq!(
+            metadata.summary(),
+            "D90, 18-105 mm, 50 mm, f/1.8, 1/250 s, ISO 200, 4288x2848, 48.85800, 2.29450"
+        );
+
+        let video = Metadata {
+            duration: Some(125_400),
+            codec: Some(String::from("h264")),
+            exposure_time: Some((5, 2)),
+            ..Default::default()
+        };
+        assert_eq!(video.summary(), "2.5 s, 2:05, h264");
+    }
+}
@@ -0,0 +1,391 @@
+extern crate chrono;
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use self::chrono::{DateTime, NaiveDateTime};
+
+use super::file_item::FileItem;
+use super::file_types::is_metadata_writable;
+use super::item_traits::Orientation;
+
+/// Format of date and time values in EXIF data
+const EXIF_DATE_TIME_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+/// Format of date and time values in XMP data
+const XMP_DATE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// EXIF tag of the orientation
+const TAG_ORIENTATION: u16 = 0x0112;
+/// EXIF tag of the pointer to the EXIF IFD
+const TAG_EXIF_IFD: u16 = 0x8769;
+/// EXIF tag of the original capture time
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+/// EXIF tag of the time the image was digitized
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+/// TIFF field type of ASCII strings
+const TYPE_ASCII: u16 = 2;
+/// TIFF field type of 16 bit unsigned integers
+const TYPE_SHORT: u16 = 3;
+
+/// Corrected metadata that is written to a sieved file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataUpdate {
+    /// Corrected capture time, None if the capture time was not corrected
+    pub date_time: Option<NaiveDateTime>,
+    /// Normalized orientation, None if the orientation of the image is already one of the standard values
+    pub orientation: Option<Orientation>,
+}
+
+impl MetadataUpdate {
+    /// Gets the metadata of an item that is written to its sieved file. Videos are never modified.
+    pub fn from_item(item: &FileItem) -> Self {
+        if item.is_video() {
+            return Self::default();
+        }
+        let date_time = if item.is_time_corrected() {
+            DateTime::from_timestamp_millis(item.get_capture_time().millis)
+                .map(|date_time| date_time.naive_utc())
+        } else {
+            None
+        };
+        let orientation = if item.get_metadata().irregular_orientation {
+            item.get_orientation().cloned()
+        } else {
+            None
+        };
+        Self {
+            date_time,
+            orientation,
+        }
+    }
+
+    /// Checks if there is nothing to write
+    pub fn is_empty(&self) -> bool {
+        self.date_time.is_none() && self.orientation.is_none()
+    }
+}
+
+/// Writes the corrected metadata to a file. The EXIF data of JPEG, TIFF and DNG files is modified in place, for all
+/// other formats or if the EXIF data does not contain the values to correct, an XMP sidecar file is written. An
+/// existing sidecar file, for example of an editing application, is never overwritten, an error is returned instead.
+/// Returns the path of the sidecar file if one was written.
+pub fn write_metadata(path: &Path, update: &MetadataUpdate) -> Result<Option<PathBuf>, Error> {
+    if update.is_empty() {
+        return Ok(None);
+    }
+    if is_metadata_writable(path) {
+        let mut data = fs::read(path)?;
+        let original = data.clone();
+        let complete = patch_exif(&mut data, update);
+        if data != original {
+            fs::write(path, &data)?;
+        }
+        if complete {
+            return Ok(None);
+        }
+    }
+    let sidecar = sidecar_path(path);
+    if sidecar.exists() {
+        return Err(sidecar_exists_error(&sidecar));
+    }
+    fs::write(&sidecar, xmp_sidecar(update))?;
+    Ok(Some(sidecar))
+}
+
+/// Checks if the corrected metadata of a file would be written to an XMP sidecar file instead of the file itself,
+/// without modifying anything
+pub fn needs_sidecar(path: &Path, update: &MetadataUpdate) -> Result<bool, Error> {
+    if update.is_empty() {
+        return Ok(false);
+    }
+    if !is_metadata_writable(path) {
+        return Ok(true);
+    }
+    let mut data = fs::read(path)?;
+    Ok(!patch_exif(&mut data, update))
+}
+
+/// Gets the path of the XMP sidecar file of a file
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    path.with_extension("xmp")
+}
+
+/// Creates the error returned if a sidecar file would overwrite an existing one
+pub fn sidecar_exists_error(sidecar: &Path) -> Error {
+    Error::new(
+        ErrorKind::AlreadyExists,
+        format!("Sidecar file already exists: {}", sidecar.display()),
+    )
+}
+
+/// Gets the EXIF value of an orientation
+fn exif_orientation(orientation: &Orientation) -> u16 {
+    match orientation {
+        Orientation::Landscape => 1,
+        Orientation::Landscape180 => 3,
+        Orientation::Portrait90 => 6,
+        Orientation::Portrait270 => 8,
+    }
+}
+
+/// Creates the content of an XMP sidecar file with the corrected metadata
+fn xmp_sidecar(update: &MetadataUpdate) -> String {
+    let mut properties = String::new();
+    if let Some(date_time) = update.date_time {
+        let date_time = date_time.format(XMP_DATE_TIME_FORMAT);
+        properties.push_str(&format!("\n    exif:DateTimeOriginal=\"{}\"", date_time));
+        properties.push_str(&format!("\n    photoshop:DateCreated=\"{}\"", date_time));
+    }
+    if let Some(orientation) = &update.orientation {
+        properties.push_str(&format!(
+            "\n    tiff:Orientation=\"{}\"",
+            exif_orientation(orientation)
+        ));
+    }
+    format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:exif="http://ns.adobe.com/exif/1.0/"
+    xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/"
+    xmlns:tiff="http://ns.adobe.com/tiff/1.0/"{}/>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>
+"#,
+        properties
+    )
+}
+
+/// Gets the position of the TIFF header containing the EXIF data, either at the start of a TIFF or DNG file or in
+/// the APP1 segment of a JPEG file
+fn find_tiff_header(data: &[u8]) -> Option<usize> {
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Some(0);
+    }
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut position = 2;
+    while position + 4 <= data.len() && data[position] == 0xFF {
+        let marker = data[position + 1];
+        // The image data follows the start of scan marker
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+        if marker == 0xE1 && data.get(position + 4..position + 10) == Some(&b"Exif\0\0"[..]) {
+            return Some(position + 10);
+        }
+        position += 2 + length;
+    }
+    None
+}
+
+/// TIFF structure of EXIF data that is modified in place
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    start: usize,
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self
+            .data
+            .get(self.start + offset..self.start + offset + 2)?;
+        let bytes = [bytes[0], bytes[1]];
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self
+            .data
+            .get(self.start + offset..self.start + offset + 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn write(&mut self, offset: usize, bytes: &[u8]) -> bool {
+        match self
+            .data
+            .get_mut(self.start + offset..self.start + offset + bytes.len())
+        {
+            Some(target) => {
+                target.copy_from_slice(bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Finds an entry in an IFD and returns the offset of the entry
+    fn find_entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.read_u16(ifd)? as usize;
+        (0..count)
+            .map(|index| ifd + 2 + index * 12)
+            .find(|entry| self.read_u16(*entry) == Some(tag))
+    }
+
+    /// Writes an orientation to a SHORT entry, returns false if the entry has a different type
+    fn write_orientation(&mut self, entry: usize, orientation: u16) -> bool {
+        if self.read_u16(entry + 2) != Some(TYPE_SHORT) {
+            return false;
+        }
+        let bytes = if self.little_endian {
+            orientation.to_le_bytes()
+        } else {
+            orientation.to_be_bytes()
+        };
+        self.write(entry + 8, &bytes)
+    }
+
+    /// Writes a date and time to an ASCII entry, returns false if the entry has a different type or length
+    fn write_date_time(&mut self, entry: usize, date_time: &[u8]) -> bool {
+        if self.read_u16(entry + 2) != Some(TYPE_ASCII)
+            || self.read_u32(entry + 4) != Some(date_time.len() as u32 + 1)
+        {
+            return false;
+        }
+        match self.read_u32(entry + 8) {
+            Some(offset) => self.write(offset as usize, date_time),
+            None => false,
+        }
+    }
+}
+
+/// Modifies the EXIF data of a file in place. Returns true if all values of the update could be written.
+fn patch_exif(data: &mut [u8], update: &MetadataUpdate) -> bool {
+    let Some(start) = find_tiff_header(data) else {
+        return false;
+    };
+    let mut tiff = Tiff {
+        little_endian: data.get(start..start + 2) == Some(&b"II"[..]),
+        data,
+        start,
+    };
+    let Some(ifd0) = tiff.read_u32(4).map(|offset| offset as usize) else {
+        return false;
+    };
+
+    let mut complete = true;
+    if let Some(orientation) = &update.orientation {
+        complete &= match tiff.find_entry(ifd0, TAG_ORIENTATION) {
+            Some(entry) => tiff.write_orientation(entry, exif_orientation(orientation)),
+            // A missing orientation is the same as landscape
+            None => *orientation == Orientation::Landscape,
+        };
+    }
+    if let Some(date_time) = update.date_time {
+        let date_time = date_time.format(EXIF_DATE_TIME_FORMAT).to_string();
+        let exif_ifd = tiff
+            .find_entry(ifd0, TAG_EXIF_IFD)
+            .and_then(|entry| tiff.read_u32(entry + 8))
+            .map(|offset| offset as usize);
+        let mut written = false;
+        if let Some(exif_ifd) = exif_ifd {
+            for tag in [TAG_DATE_TIME_ORIGINAL, TAG_DATE_TIME_DIGITIZED] {
+                if let Some(entry) = tiff.find_entry(exif_ifd, tag) {
+                    let result = tiff.write_date_time(entry, date_time.as_bytes());
+                    written |= result && tag == TAG_DATE_TIME_ORIGINAL;
+                }
+            }
+        }
+        complete &= written;
+    }
+    complete
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_sort_list::item_traits::PropertyResolver;
+    use crate::item_sort_list::metadata::Metadata;
+    use crate::item_sort_list::resolvers::get_resolver;
+
+    #[test]
+    fn test_from_item() {
+        let mut item = FileItem::dummy("test.jpg", 10, true);
+        assert!(MetadataUpdate::from_item(&item).is_empty());
+        item.set_clock_offset(3600);
+        assert_eq!(
+            MetadataUpdate::from_item(&item),
+            MetadataUpdate {
+                date_time: DateTime::from_timestamp(3610, 0).map(|date_time| date_time.naive_utc()),
+                orientation: None
+            }
+        );
+        item.set_metadata(Metadata {
+            irregular_orientation: true,
+            ..Metadata::default()
+        });
+        assert_eq!(
+            MetadataUpdate::from_item(&item).orientation,
+            Some(Orientation::Landscape)
+        );
+        assert!(MetadataUpdate::from_item(&FileItem::dummy("test.mp4", 10, true)).is_empty());
+    }
+
+    #[test]
+    fn test_write_metadata() {
+        let path = Path::new("tests/test_write_metadata.jpg");
+        fs::copy("tests/test.jpg", path).unwrap();
+        let timestamp = get_resolver(path).get_timestamp();
+        let date_time = DateTime::from_timestamp(timestamp.seconds() - 3600, 0)
+            .unwrap()
+            .naive_utc();
+        let update = MetadataUpdate {
+            date_time: Some(date_time),
+            orientation: Some(Orientation::Landscape180),
+        };
+        let sidecar = write_metadata(path, &update).unwrap();
+        let resolver = get_resolver(path);
+        let size = fs::metadata(path).unwrap().len();
+        fs::remove_file(path).ok();
+
+        assert_eq!(sidecar, None);
+        assert!(!needs_sidecar(Path::new("tests/test.jpg"), &update).unwrap());
+        assert_eq!(
+            resolver.get_timestamp().seconds(),
+            timestamp.seconds() - 3600
+        );
+        assert_eq!(resolver.get_orientation(), Some(Orientation::Landscape180));
+        assert_eq!(size, fs::metadata("tests/test.jpg").unwrap().len());
+        assert_eq!(
+            write_metadata(path, &MetadataUpdate::default()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_write_sidecar() {
+        let path = Path::new("tests/test_write_sidecar.nef");
+        let update = MetadataUpdate {
+            date_time: NaiveDateTime::parse_from_str("2021-09-12 15:41:51", "%Y-%m-%d %H:%M:%S")
+                .ok(),
+            orientation: Some(Orientation::Portrait90),
+        };
+        let sidecar = write_metadata(path, &update).unwrap().unwrap();
+        let content = fs::read_to_string(&sidecar).unwrap();
+        // An existing sidecar file is kept
+        let error = write_metadata(path, &update).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        fs::remove_file(&sidecar).ok();
+
+        assert_eq!(sidecar, PathBuf::from("tests/test_write_sidecar.xmp"));
+        assert!(needs_sidecar(path, &update).unwrap());
+        assert!(!needs_sidecar(path, &MetadataUpdate::default()).unwrap());
+        assert!(content.contains("exif:DateTimeOriginal=\"2021-09-12T15:41:51\""));
+        assert!(content.contains("tiff:Orientation=\"6\""));
+    }
+}
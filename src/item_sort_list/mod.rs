@@ -1,17 +1,42 @@
+mod archive_index;
+mod clock_offset;
+mod companions;
+mod deletion;
 mod event;
 mod file_item;
 mod file_types;
+mod hash_config;
+mod hash_tree;
 mod item_list;
 mod item_traits;
+mod journal;
+mod metadata;
+mod metadata_writer;
+mod motion_photo;
+mod path_template;
 mod resolvers;
+mod sidecars;
 mod sieve;
+mod similarity;
+mod stack;
 mod timestamp;
 
+pub use archive_index::ArchiveIndex;
+pub use clock_offset::{clock_offset_to_string, parse_clock_offset, ClockOffset};
+pub use deletion::{Deletion, DeletionPolicy};
 pub use event::parse_date;
 pub use event::Event;
 pub use file_item::{FileItem, ItemType};
+pub use hash_config::{HashAlgorithm, HashConfig, HASH_SIZES};
 pub use item_list::DirectoryNames;
 pub use item_list::ItemList;
 pub use item_list::SieveMethod;
 pub use item_traits::Orientation;
-pub use timestamp::{timestamp_to_string, Format};
+pub use journal::{SieveJournal, STAGING_DIRECTORY};
+pub use metadata::Metadata;
+pub use motion_photo::MotionPhotos;
+pub use path_template::PathTemplate;
+pub use sieve::{PlannedOperation, SieveEvent, SievePlan, SieveStatistics};
+pub use similarity::{MAX_SCORE, Similar, SimilarityConfig};
+pub use stack::{Stack, StackKind};
+pub use timestamp::{timestamp_to_string, Format, Timestamp};
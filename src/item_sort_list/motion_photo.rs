@@ -0,0 +1,287 @@
+use std::fs::{File, OpenOptions};
+use std::io::{copy, BufReader, Error, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumString;
+
+/// Major brands of MP4 and QuickTime videos embedded in motion photos
+const VIDEO_BRANDS: &[&[u8; 4]] = &[b"isom", b"iso2", b"mp41", b"mp42", b"avc1", b"qt  "];
+/// Signature of the APP1 segment containing the XMP data of a JPEG file
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Number of bytes after the end of the image that are searched for an appended video
+const TRAILER_SEARCH_SIZE: u64 = 4096;
+/// Flags in the XMP data of motion photos, in the GCamera namespace of Google cameras and in the Camera namespace of
+/// the motion photo format
+const XMP_MOTION_PHOTO_FLAGS: &[&str] = &["Camera:MicroVideo=\"1\"", "Camera:MotionPhoto=\"1\""];
+/// Signature at the end of the trailer of Samsung images, which contains the video of a motion photo
+const SAMSUNG_TRAILER_SIGNATURE: &[u8; 4] = b"SEFT";
+
+/// How motion photos and live photos are handled during the sieve process
+#[derive(
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    EnumString,
+)]
+#[strum(ascii_case_insensitive)]
+#[repr(i32)]
+pub enum MotionPhotos {
+    /// Motion photos and live photos are sieved as they are
+    #[default]
+    Keep = 0,
+    /// The video embedded in a motion photo is additionally saved as separate file next to the image
+    ExtractVideo,
+    /// Only the still image is sieved, embedded videos are removed and the videos of live photos are not sieved
+    StillOnly,
+}
+
+/// Finds the video appended to a JPEG motion photo, like the motion photos of Google and Samsung cameras.
+/// Returns the offset of the video in the file, None if the file is no JPEG or does not contain a video.
+/// The offset is taken from the XMP data if given there. Otherwise the video is searched right after the end of the
+/// image, but only if the file is marked as motion photo by its XMP data, its name or a Samsung trailer, because the
+/// whole image data has to be read to find its end.
+pub fn find_embedded_video(path: &Path) -> Option<u64> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let length = reader.get_ref().metadata().ok()?.len();
+    let mut start_of_image = [0u8; 2];
+    reader.read_exact(&mut start_of_image).ok()?;
+    if start_of_image != [0xFF, 0xD8] {
+        return None;
+    }
+
+    // Go through the segments up to the image data
+    let mut motion_photo = is_motion_photo_name(path);
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).ok()?;
+        if header[0] != 0xFF || header[1] == 0xD9 {
+            return None;
+        }
+        let size = u16::from_be_bytes([header[2], header[3]]).checked_sub(2)? as usize;
+        if header[1] == 0xE1 {
+            let mut segment = vec![0u8; size];
+            reader.read_exact(&mut segment).ok()?;
+            if let Some(xmp) = segment.strip_prefix(XMP_SIGNATURE) {
+                let xmp = String::from_utf8_lossy(xmp);
+                if let Some(video_length) = xmp_video_length(&xmp)
+                    && let Some(offset) = length.checked_sub(video_length)
+                    && is_video_at(&mut reader, offset)
+                {
+                    return Some(offset);
+                }
+                motion_photo |= XMP_MOTION_PHOTO_FLAGS.iter().any(|flag| xmp.contains(flag));
+            }
+        } else {
+            reader.seek_relative(size as i64).ok()?;
+        }
+        if header[1] == 0xDA {
+            break;
+        }
+    }
+
+    if !motion_photo && !has_samsung_trailer(&mut reader, length) {
+        return None;
+    }
+
+    // The end of image marker cannot occur in the entropy coded image data
+    let mut previous = 0u8;
+    for byte in reader.by_ref().bytes() {
+        let byte = byte.ok()?;
+        if previous == 0xFF && byte == 0xD9 {
+            break;
+        }
+        previous = byte;
+    }
+    let end_of_image = reader.stream_position().ok()?;
+    // The video may be preceded by a short marker, like "MotionPhoto_Data" of Samsung cameras
+    let mut trailer = vec![];
+    reader
+        .take(TRAILER_SEARCH_SIZE)
+        .read_to_end(&mut trailer)
+        .ok()?;
+    trailer
+        .windows(12)
+        .position(is_file_type_box)
+        .map(|position| end_of_image + position as u64)
+}
+
+/// Gets the length of the video at the end of a motion photo from its XMP data, either from the micro video offset
+/// of older Google cameras or from the container directory of the motion photo format
+fn xmp_video_length(xmp: &str) -> Option<u64> {
+    if let Some(offset) = xmp_attribute(xmp, "GCamera:MicroVideoOffset") {
+        return Some(offset);
+    }
+    let semantic = xmp.find("Item:Semantic=\"MotionPhoto\"")?;
+    let start = xmp[..semantic].rfind('<')?;
+    let end = semantic + xmp[semantic..].find('>')?;
+    xmp_attribute(&xmp[start..end], "Item:Length")
+}
+
+/// Gets the numeric value of an XMP attribute
+fn xmp_attribute(xmp: &str, name: &str) -> Option<u64> {
+    let start = xmp.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = start + xmp[start..].find('"')?;
+    xmp[start..end].trim().parse().ok()
+}
+
+/// Checks if the file name marks a motion photo, like MVIMG_20200101_120000.jpg of older and
+/// PXL_20200101_120000000.MP.jpg of newer Google cameras
+fn is_motion_photo_name(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+    name.starts_with("MVIMG_") || name.contains(".MP.")
+}
+
+/// Checks if a file ends with the trailer of Samsung cameras
+fn has_samsung_trailer(reader: &mut BufReader<File>, length: u64) -> bool {
+    let mut signature = [0u8; 4];
+    let position = reader.stream_position();
+    let found = length >= 4
+        && reader.seek(SeekFrom::Start(length - 4)).is_ok()
+        && reader.read_exact(&mut signature).is_ok()
+        && signature == *SAMSUNG_TRAILER_SIGNATURE;
+    if let Ok(position) = position {
+        reader.seek(SeekFrom::Start(position)).ok();
+    }
+    found
+}
+
+/// Checks if a video starts at the given offset of a file
+fn is_video_at(reader: &mut BufReader<File>, offset: u64) -> bool {
+    let mut header = [0u8; 12];
+    let position = reader.stream_position();
+    let found = reader.seek(SeekFrom::Start(offset)).is_ok()
+        && reader.read_exact(&mut header).is_ok()
+        && is_file_type_box(&header);
+    if let Ok(position) = position {
+        reader.seek(SeekFrom::Start(position)).ok();
+    }
+    found
+}
+
+/// Checks if data starts with the file type box of a video, which is a 32 bit size followed by "ftyp" and the major
+/// brand
+fn is_file_type_box(data: &[u8]) -> bool {
+    let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    (8..=256).contains(&size)
+        && &data[4..8] == b"ftyp"
+        && VIDEO_BRANDS.iter().any(|video| video[..] == data[8..12])
+}
+
+/// Writes a range of bytes of a file to a new file, the end of the range may exceed the length of the file.
+/// An existing file is never overwritten.
+pub fn extract(src: &Path, range: Range<u64>, dest: &Path) -> Result<(), Error> {
+    let mut file = File::open(src)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    let mut target = OpenOptions::new().write(true).create_new(true).open(dest)?;
+    copy(&mut file.take(range.end - range.start), &mut target)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, remove_file};
+
+    #[test]
+    fn test_motion_photo() {
+        let path = Path::new("tests/test_motion_photo.MP.jpg");
+        let unmarked = Path::new("tests/test_motion_photo_unmarked.jpg");
+        let video = Path::new("tests/test_motion_photo.mp4");
+        let still = Path::new("tests/test_motion_photo_still.jpg");
+        let image = fs::read("tests/test.jpg").unwrap();
+        let mut data = image.clone();
+        data.extend(fs::read("tests/test.mp4").unwrap());
+        fs::write(path, &data).unwrap();
+        fs::write(unmarked, &data).unwrap();
+
+        assert_eq!(find_embedded_video(Path::new("tests/test.jpg")), None);
+        assert_eq!(find_embedded_video(Path::new("tests/test.mp4")), None);
+        let unmarked_offset = find_embedded_video(unmarked);
+        remove_file(unmarked).ok();
+        let offset = find_embedded_video(path);
+        assert_eq!(offset, Some(image.len() as u64));
+
+        extract(path, offset.unwrap()..u64::MAX, video).unwrap();
+        extract(path, 0..offset.unwrap(), still).unwrap();
+        assert!(extract(path, 0..offset.unwrap(), still).is_err());
+        let extracted_video = fs::read(video).unwrap();
+        let extracted_still = fs::read(still).unwrap();
+        remove_file(path).ok();
+        remove_file(video).ok();
+        remove_file(still).ok();
+
+        // The image data is only searched for its end if the file is marked as motion photo
+        assert_eq!(unmarked_offset, None);
+        assert_eq!(extracted_video, fs::read("tests/test.mp4").unwrap());
+        assert_eq!(extracted_still, image);
+    }
+
+    #[test]
+    fn test_motion_photo_formats() {
+        let path = Path::new("tests/test_motion_photo_formats.jpg");
+        let image = fs::read("tests/test.jpg").unwrap();
+        let video = fs::read("tests/test.mp4").unwrap();
+
+        // Samsung cameras put a marker between the image and the video and a trailer at the end of the file
+        let mut data = image.clone();
+        data.extend(b"MotionPhoto_Data");
+        data.extend(&video);
+        data.extend(SAMSUNG_TRAILER_SIGNATURE);
+        fs::write(path, &data).unwrap();
+        let samsung = find_embedded_video(path);
+
+        // Google cameras flag motion photos in the XMP data
+        let xmp = "<rdf:Description GCamera:MotionPhoto=\"1\"/>";
+        let mut segment = XMP_SIGNATURE.to_vec();
+        segment.extend(xmp.as_bytes());
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend(((segment.len() + 2) as u16).to_be_bytes());
+        data.extend(&segment);
+        data.extend(&image[2..]);
+        let image_length = data.len();
+        data.extend(&video);
+        fs::write(path, &data).unwrap();
+        let flagged = find_embedded_video(path);
+
+        // Google cameras give the offset of the video from the end of the file in the XMP data
+        let xmp = format!(
+            "<rdf:Description GCamera:MicroVideo=\"1\" GCamera:MicroVideoOffset=\"{}\"/>",
+            video.len()
+        );
+        let mut segment = XMP_SIGNATURE.to_vec();
+        segment.extend(xmp.as_bytes());
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend(((segment.len() + 2) as u16).to_be_bytes());
+        data.extend(&segment);
+        data.extend(&image[2..]);
+        data.extend(vec![0u8; 8192]);
+        data.extend(&video);
+        fs::write(path, &data).unwrap();
+        let google = find_embedded_video(path);
+        remove_file(path).ok();
+
+        assert_eq!(samsung, Some(image.len() as u64 + 16));
+        assert_eq!(flagged, Some(image_length as u64));
+        assert_eq!(google, Some((data.len() - video.len()) as u64));
+        assert_eq!(
+            xmp_video_length(concat!(
+                "<Container:Item Item:Mime=\"image/jpeg\" Item:Semantic=\"Primary\"/>",
+                "<Container:Item Item:Length=\"1234\" Item:Semantic=\"MotionPhoto\"/>"
+            )),
+            Some(1234)
+        );
+    }
+}
@@ -0,0 +1,532 @@
+use std::{
+    fmt::{Display, Formatter},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use chrono::{DateTime, Datelike, Timelike};
+use strum_macros::EnumString;
+
+use super::event::Event;
+use super::file_item::{FileItem, ItemType};
+use super::item_list::DirectoryNames;
+
+/// Placeholders that can be used in a path template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+enum Placeholder {
+    Year,
+    Quarter,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Date,
+    Time,
+    Event,
+    EventDates,
+    EventDays,
+    EventYear,
+    CameraMake,
+    CameraModel,
+    Lens,
+    FocalLength,
+    Iso,
+    Codec,
+    Type,
+    Filename,
+    Stem,
+    #[strum(serialize = "extension", serialize = "ext")]
+    Extension,
+    Seq,
+}
+
+/// Part of a path template, either literal text or a placeholder padded with zeros to a minimum width
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder {
+        placeholder: Placeholder,
+        width: usize,
+    },
+}
+
+/// One of the alternatives of a path template
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Alternative {
+    parts: Vec<Part>,
+    /// Flag indicating if the last path segment names the file, otherwise the original file name is appended
+    names_file: bool,
+    /// Flag indicating if the alternative contains the extension of the file
+    names_extension: bool,
+}
+
+/// Template text together with its parsed alternatives
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    text: String,
+    alternatives: Vec<Alternative>,
+}
+
+impl Pattern {
+    /// Renders the first alternative for which all placeholders have a value, missing values of the last
+    /// alternative are left empty
+    fn render(
+        &self,
+        item: &FileItem,
+        event: Option<&Event>,
+        sequence: usize,
+    ) -> (String, &Alternative) {
+        let last = self.alternatives.len() - 1;
+        for (index, alternative) in self.alternatives.iter().enumerate() {
+            let mut rendered = String::new();
+            let mut complete = true;
+            for part in &alternative.parts {
+                match part {
+                    Part::Text(text) => rendered.push_str(text),
+                    Part::Placeholder { placeholder, width } => {
+                        match get_value(*placeholder, item, event, sequence) {
+                            Some(value) => {
+                                rendered.push_str(&format!("{:0>width$}", value, width = width))
+                            }
+                            None => complete = false,
+                        }
+                    }
+                }
+            }
+            if complete || index == last {
+                return (rendered, alternative);
+            }
+        }
+        unreachable!()
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let alternatives = text
+            .split('|')
+            .map(parse_alternative)
+            .collect::<Result<Vec<Alternative>, String>>()?;
+        Ok(Self {
+            text: String::from(text),
+            alternatives,
+        })
+    }
+}
+
+/// Template for the path of a file item relative to the sieve target directory, for example
+/// `{year}/{month:02}-{event}/{camera_model}/{filename}`. Directories are separated by `/` or `\` and a
+/// placeholder can be padded with zeros to a minimum width. A template may consist of several alternatives separated
+/// by `|`, the first alternative for which all placeholders have a value is used. Missing values in the last
+/// alternative are left empty. If the last path segment does not contain the filename or stem placeholder, the
+/// original file name is appended.
+///
+/// Optionally, files are renamed using a rename pattern like `{date}_{time}_{seq}.{ext}` which replaces the file
+/// name. If the pattern does not contain the extension, the original extension is appended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    directories: Pattern,
+    file_name: Option<Pattern>,
+}
+
+impl PathTemplate {
+    /// Creates the built-in template for the given directory names or parses the custom template
+    pub fn new(directory_names: &DirectoryNames, custom_template: &str) -> Result<Self, String> {
+        let template = match directory_names {
+            DirectoryNames::YearAndMonth => "{event_dates} {event}|{year}-{month:02}",
+            DirectoryNames::Year => "{event_dates} {event}|{year}",
+            DirectoryNames::YearMonthAndDay => "{event_dates} {event}|{year}-{month:02}-{day:02}",
+            DirectoryNames::YearAndQuarter => "{event_dates} {event}|{year}-Q{quarter}",
+            DirectoryNames::YearAndMonthInSubdirectory => {
+                "{event_year}/{event_days} {event}|{year}/{month:02}"
+            }
+            DirectoryNames::Custom => custom_template,
+        };
+        template.parse()
+    }
+
+    /// Adds a rename pattern for the file names, an empty pattern keeps the original file names
+    pub fn with_rename_pattern(self, rename_pattern: &str) -> Result<Self, String> {
+        Ok(Self {
+            file_name: parse_rename_pattern(rename_pattern)?,
+            ..self
+        })
+    }
+
+    /// Checks a rename pattern, the error describes the first problem found
+    pub fn check_rename_pattern(rename_pattern: &str) -> Result<(), String> {
+        parse_rename_pattern(rename_pattern).map(|_| ())
+    }
+
+    /// Renders the path of a file item relative to the target directory. The event is given if the item is
+    /// part of one, the sequence number is used for the seq placeholder.
+    pub fn render(&self, item: &FileItem, event: Option<&Event>, sequence: usize) -> PathBuf {
+        let (rendered, alternative) = self.directories.render(item, event, sequence);
+        // Segments referring to the directory itself or its parent are dropped, so that the path stays inside the
+        // target directory
+        let mut path: PathBuf = rendered
+            .split(['/', '\\'])
+            .map(str::trim)
+            .filter(|segment| !matches!(*segment, "" | "." | ".."))
+            .collect();
+        match &self.file_name {
+            Some(file_name) => {
+                if alternative.names_file {
+                    path.pop();
+                }
+                let (rendered, alternative) = file_name.render(item, event, sequence);
+                let mut rendered = String::from(rendered.trim());
+                if matches!(rendered.as_str(), "" | "." | "..") {
+                    rendered = item
+                        .path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string();
+                }
+                if !alternative.names_extension
+                    && let Some(extension) = item.path.extension()
+                {
+                    rendered = format!("{}.{}", rendered, extension.to_string_lossy());
+                }
+                path.push(rendered);
+            }
+            None => {
+                if !alternative.names_file {
+                    path.push(item.path.file_name().unwrap_or_default());
+                }
+            }
+        }
+        path
+    }
+}
+
+impl FromStr for PathTemplate {
+    type Err = String;
+
+    /// Parses and validates a path template, the error describes the first problem found
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            directories: template.parse()?,
+            file_name: None,
+        })
+    }
+}
+
+impl Display for PathTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.file_name {
+            Some(file_name) => write!(f, "{} renamed to {}", self.directories.text, file_name.text),
+            None => write!(f, "{}", self.directories.text),
+        }
+    }
+}
+
+/// Parses a rename pattern, which must not contain directories. None is returned for an empty pattern.
+fn parse_rename_pattern(rename_pattern: &str) -> Result<Option<Pattern>, String> {
+    if rename_pattern.trim().is_empty() {
+        return Ok(None);
+    }
+    if rename_pattern.contains(['/', '\\']) {
+        return Err(String::from("Rename pattern must not contain \"/\""));
+    }
+    rename_pattern.parse().map(Some)
+}
+
+/// Parses a single alternative of a path template
+fn parse_alternative(alternative: &str) -> Result<Alternative, String> {
+    if alternative.trim().is_empty() {
+        return Err(String::from("Template must not be empty"));
+    }
+    if alternative
+        .split(['/', '\\'])
+        .any(|segment| segment.trim() == "..")
+    {
+        return Err(String::from("Template must not contain \"..\""));
+    }
+    let mut parts = vec![];
+    let mut names_file = false;
+    let mut names_extension = false;
+    let mut rest = alternative;
+    while !rest.is_empty() {
+        let start = rest.find(['{', '}']).unwrap_or(rest.len());
+        if start > 0 {
+            let text = &rest[..start];
+            if text.contains(['/', '\\']) {
+                names_file = false;
+                names_extension = false;
+            }
+            parts.push(Part::Text(String::from(text)));
+            rest = &rest[start..];
+            continue;
+        }
+        if rest.starts_with('}') {
+            return Err(String::from("Unexpected \"}\""));
+        }
+        let end = rest
+            .find('}')
+            .ok_or_else(|| String::from("Missing \"}\""))?;
+        let (name, width) = match rest[1..end].split_once(':') {
+            Some((name, width)) => (
+                name,
+                width
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid width \"{}\" of {{{}}}", width, name))?,
+            ),
+            None => (&rest[1..end], 0),
+        };
+        let placeholder = Placeholder::from_str(name.trim())
+            .map_err(|_| format!("Unknown placeholder {{{}}}", name))?;
+        if matches!(placeholder, Placeholder::Filename | Placeholder::Stem) {
+            names_file = true;
+        }
+        if matches!(placeholder, Placeholder::Filename | Placeholder::Extension) {
+            names_extension = true;
+        }
+        parts.push(Part::Placeholder { placeholder, width });
+        rest = &rest[end + 1..];
+    }
+    Ok(Alternative {
+        parts,
+        names_file,
+        names_extension,
+    })
+}
+
+/// Gets the value of a placeholder for a file item, path separators are replaced. Empty values and values referring
+/// to a directory itself or its parent are treated as missing.
+fn get_value(
+    placeholder: Placeholder,
+    item: &FileItem,
+    event: Option<&Event>,
+    sequence: usize,
+) -> Option<String> {
+    let date_time = DateTime::from_timestamp(item.get_timestamp(), 0);
+    let value = match placeholder {
+        Placeholder::Date => date_time.map(|date_time| date_time.format("%Y-%m-%d").to_string()),
+        Placeholder::Time => date_time.map(|date_time| date_time.format("%H-%M-%S").to_string()),
+        Placeholder::Year => date_time.map(|date_time| date_time.year().to_string()),
+        Placeholder::Quarter => {
+            date_time.map(|date_time| ((date_time.month() - 1) / 3 + 1).to_string())
+        }
+        Placeholder::Month => date_time.map(|date_time| date_time.month().to_string()),
+        Placeholder::Day => date_time.map(|date_time| date_time.day().to_string()),
+        Placeholder::Hour => date_time.map(|date_time| date_time.hour().to_string()),
+        Placeholder::Minute => date_time.map(|date_time| date_time.minute().to_string()),
+        Placeholder::Second => date_time.map(|date_time| date_time.second().to_string()),
+        Placeholder::Event => event.map(|event| event.name.clone()),
+        Placeholder::EventDates => event.map(|event| {
+            if event.start_date != event.end_date {
+                format!(
+                    "{} - {}",
+                    event.start_date.format("%Y-%m-%d"),
+                    event.end_date.format("%Y-%m-%d")
+                )
+            } else {
+                event.start_date.format("%Y-%m-%d").to_string()
+            }
+        }),
+        Placeholder::EventDays => event.map(|event| {
+            if event.start_date != event.end_date {
+                format!(
+                    "{} - {}",
+                    event.start_date.format("%m-%d"),
+                    event
+                        .end_date
+                        .format(if event.start_date.year() != event.end_date.year() {
+                            "%Y-%m-%d"
+                        } else {
+                            "%m-%d"
+                        })
+                )
+            } else {
+                event.start_date.format("%m-%d").to_string()
+            }
+        }),
+        Placeholder::EventYear => event.map(|event| event.start_date.year().to_string()),
+        Placeholder::CameraMake => item.get_metadata().camera_make.clone(),
+        Placeholder::CameraModel => item.get_metadata().camera_model.clone(),
+        Placeholder::Lens => item.get_metadata().lens.clone(),
+        Placeholder::FocalLength => item
+            .get_metadata()
+            .focal_length
+            .map(|focal_length| (focal_length / 10).to_string()),
+        Placeholder::Iso => item.get_metadata().iso.map(|iso| iso.to_string()),
+        Placeholder::Codec => item.get_metadata().codec.clone(),
+        Placeholder::Type => Some(String::from(match item.get_item_type() {
+            ItemType::Image => "Images",
+            ItemType::Video => "Videos",
+            ItemType::RawImage => "Raw",
+            ItemType::HeifImage => "Images",
+        })),
+        Placeholder::Filename => item
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string()),
+        Placeholder::Stem => item
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string()),
+        Placeholder::Extension => item
+            .path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_string()),
+        Placeholder::Seq => Some(sequence.to_string()),
+    };
+    value
+        .map(|value| value.replace(['/', '\\'], "_"))
+        .filter(|value| !matches!(value.trim(), "" | "." | ".."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_sort_list::metadata::Metadata;
+
+    #[test]
+    fn test_parse() {
+        assert!(
+            PathTemplate::from_str("{year}/{month:02}-{event}/{camera_model}/{filename}").is_ok()
+        );
+        assert!(PathTemplate::from_str("{ year }/{type}|fallback").is_ok());
+        assert_eq!(
+            PathTemplate::from_str("{year"),
+            Err(String::from("Missing \"}\""))
+        );
+        assert_eq!(
+            PathTemplate::from_str("year}"),
+            Err(String::from("Unexpected \"}\""))
+        );
+        assert_eq!(
+            PathTemplate::from_str("{years}"),
+            Err(String::from("Unknown placeholder {years}"))
+        );
+        assert_eq!(
+            PathTemplate::from_str("{month:x}"),
+            Err(String::from("Invalid width \"x\" of {month}"))
+        );
+        assert!(PathTemplate::from_str("").is_err());
+        assert!(PathTemplate::from_str("{year}|").is_err());
+        assert!(PathTemplate::from_str("{year}/../{month}").is_err());
+        assert!(PathTemplate::from_str("{year}\\..\\{month}").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let mut item = FileItem::dummy("dir/test.jpg", 1631461311, true);
+        let event = Event::new("Holiday", "2021-09-12", "2021-09-13");
+        let render = |template: &str, item: &FileItem, event: Option<&Event>| {
+            PathTemplate::from_str(template)
+                .unwrap()
+                .render(item, event, 1)
+        };
+
+        assert_eq!(
+            render(
+                "{year}/{month:02}-{event}/{camera_model}/{filename}",
+                &item,
+                Some(&event)
+            ),
+            PathBuf::from("2021/09-Holiday/test.jpg")
+        );
+        assert_eq!(
+            render("{year}/{day:3}_{hour}-{minute}-{second}", &item, None),
+            PathBuf::from("2021/012_15-41-51/test.jpg")
+        );
+        assert_eq!(
+            render("{event}|{type}/{stem}_{year}.{extension}", &item, None),
+            PathBuf::from("Images/test_2021.jpg")
+        );
+        assert_eq!(
+            render(
+                "{event}|{type}/{stem}_{year}.{extension}",
+                &item,
+                Some(&event)
+            ),
+            PathBuf::from("Holiday/test.jpg")
+        );
+
+        item.set_metadata(Metadata {
+            camera_make: Some(String::from("Camera Inc.")),
+            camera_model: Some(String::from("A/B 1")),
+            lens: Some(String::from("50mm F1.8")),
+            focal_length: Some(505),
+            iso: Some(400),
+            ..Metadata::default()
+        });
+        assert_eq!(
+            render("{camera_make}/{camera_model}", &item, None),
+            PathBuf::from("Camera Inc./A_B 1/test.jpg")
+        );
+        assert_eq!(
+            render("{lens}/{focal_length:03}mm_ISO{iso}", &item, None),
+            PathBuf::from("50mm F1.8/050mm_ISO400/test.jpg")
+        );
+        assert_eq!(
+            render("{codec}|{type}", &item, None),
+            PathBuf::from("Images/test.jpg")
+        );
+
+        // Values must not leave the target directory
+        item.set_metadata(Metadata {
+            camera_make: Some(String::from("..")),
+            camera_model: Some(String::from(".")),
+            ..Metadata::default()
+        });
+        assert_eq!(
+            render("{camera_make}|{year}", &item, None),
+            PathBuf::from("2021/test.jpg")
+        );
+        assert_eq!(
+            render("{camera_model}/{year}", &item, None),
+            PathBuf::from("2021/test.jpg")
+        );
+        assert_eq!(
+            render(".{camera_make}./{year}\\{month}", &item, None),
+            PathBuf::from("2021/9/test.jpg")
+        );
+    }
+
+    #[test]
+    fn test_rename() {
+        let item = FileItem::dummy("dir/test.jpg", 1631461311, true);
+        let render = |template: &str, rename_pattern: &str, sequence: usize| {
+            PathTemplate::from_str(template)
+                .unwrap()
+                .with_rename_pattern(rename_pattern)
+                .unwrap()
+                .render(&item, None, sequence)
+        };
+
+        assert_eq!(
+            render("{year}", "{date}_{time}_{seq}.{ext}", 2),
+            PathBuf::from("2021/2021-09-12_15-41-51_2.jpg")
+        );
+        assert_eq!(
+            render("{year}", "{date}_{seq:03}", 1),
+            PathBuf::from("2021/2021-09-12_001.jpg")
+        );
+        assert_eq!(
+            render(
+                "{year}/{stem}.{extension}",
+                "{camera_model}|{stem}_{seq}",
+                1
+            ),
+            PathBuf::from("2021/test_1.jpg")
+        );
+        // An empty file name is replaced by the original one
+        assert_eq!(
+            render("{year}", "{camera_model}", 1),
+            PathBuf::from("2021/test.jpg")
+        );
+        assert_eq!(render("{year}", "", 1), PathBuf::from("2021/test.jpg"));
+        assert!(PathTemplate::check_rename_pattern("{date}_{seq}").is_ok());
+        assert!(PathTemplate::check_rename_pattern("").is_ok());
+        assert!(PathTemplate::check_rename_pattern("{year}/{seq}").is_err());
+        assert!(PathTemplate::check_rename_pattern("{sequence}").is_err());
+    }
+}
@@ -7,14 +7,20 @@ use ffmpeg_next::packet::side_data::Type;
 
 use crate::item_sort_list::file_types::is_heif_image;
 
-use self::chrono::NaiveDateTime;
+use self::chrono::{FixedOffset, NaiveDateTime};
 use self::exif::{In, Tag};
 
 use super::file_types::{is_image, is_raw_image, is_video};
 use super::item_traits::{Orientation, PropertyResolver};
+use super::metadata::Metadata;
+use super::motion_photo::find_embedded_video;
+use super::timestamp::Timestamp;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+/// Maximum number of characters of a displayed metadata value
+const MAX_DETAIL_LENGTH: usize = 80;
+
 pub fn get_resolver(path: &Path) -> Box<dyn PropertyResolver> {
     if ExifResolver::supports(path) {
         Box::new(ExifResolver::new(path))
@@ -44,19 +50,17 @@ impl FileResolver {
 }
 
 impl PropertyResolver for FileResolver {
-    fn get_timestamp(&self) -> i64 {
+    fn get_timestamp(&self) -> Timestamp {
         match std::fs::metadata(&self.path) {
             Ok(metadata) => {
                 let created = metadata.created().unwrap_or_else(|_| SystemTime::now());
                 let modified = metadata.modified().unwrap_or_else(|_| SystemTime::now());
-                created
-                    .min(modified)
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as i64
-                    + chrono::Local::now().offset().local_minus_utc() as i64
+                let date_time = chrono::DateTime::<chrono::Local>::from(created.min(modified));
+                // The offset is left unknown like for EXIF data without offset, so that both are compared the
+                // same way
+                Timestamp::new(date_time.naive_local().and_utc().timestamp_millis(), None)
             }
-            Err(_) => -1,
+            Err(_) => Timestamp::from_seconds(-1),
         }
     }
 
@@ -90,10 +94,97 @@ impl ExifResolver {
     pub fn supports(path: &Path) -> bool {
         is_image(path) || is_heif_image(path)
     }
+
+    /// Gets the value of an ASCII field without trailing spaces
+    fn get_string(&self, tag: Tag) -> Option<String> {
+        let field = self.exif.as_ref()?.get_field(tag, In::PRIMARY)?;
+        if let exif::Value::Ascii(values) = &field.value {
+            let value = String::from_utf8_lossy(values.first()?).trim().to_string();
+            if !value.is_empty() {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Gets the value of an integer field
+    fn get_uint(&self, tag: Tag) -> Option<u32> {
+        self.exif
+            .as_ref()?
+            .get_field(tag, In::PRIMARY)?
+            .value
+            .get_uint(0)
+    }
+
+    /// Gets the sub second digits of the original capture time in milliseconds
+    fn get_sub_second(&self) -> Option<u32> {
+        let digits = self.get_string(Tag::SubSecTimeOriginal)?;
+        if !digits.bytes().all(|digit| digit.is_ascii_digit()) {
+            return None;
+        }
+        format!("{:0<3}", &digits[..digits.len().min(3)]).parse().ok()
+    }
+
+    /// Gets the offset of the original capture time to UTC in seconds
+    fn get_offset(&self) -> Option<i32> {
+        let offset = self.get_string(Tag::OffsetTimeOriginal)?;
+        offset
+            .parse::<FixedOffset>()
+            .ok()
+            .map(|offset| offset.local_minus_utc())
+    }
+
+    /// Gets the first value of a rational field, None if the denominator is zero
+    fn get_rational(&self, tag: Tag) -> Option<exif::Rational> {
+        let field = self.exif.as_ref()?.get_field(tag, In::PRIMARY)?;
+        match &field.value {
+            exif::Value::Rational(values) if values.first()?.denom != 0 => values.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of a rational field multiplied by ten
+    fn get_tenths(&self, tag: Tag) -> Option<u32> {
+        self.get_rational(tag)
+            .map(|value| (value.num as u64 * 10 / value.denom as u64) as u32)
+    }
+
+    /// Gets a GPS coordinate in millionths of a degree from degrees, minutes and seconds. The reference is N or E for
+    /// positive and S or W for negative values.
+    fn get_coordinate(&self, tag: Tag, reference_tag: Tag) -> Option<i32> {
+        let field = self.exif.as_ref()?.get_field(tag, In::PRIMARY)?;
+        let exif::Value::Rational(values) = &field.value else {
+            return None;
+        };
+        if values.len() < 3 || values.iter().any(|value| value.denom == 0) {
+            return None;
+        }
+        let degrees = values[0].to_f64() + values[1].to_f64() / 60.0 + values[2].to_f64() / 3600.0;
+        let sign = match self.get_string(reference_tag).as_deref() {
+            Some("S") | Some("W") => -1.0,
+            _ => 1.0,
+        };
+        Some((sign * degrees * 1_000_000.0).round() as i32)
+    }
+
+    /// Gets the exposure bias in hundredths of an EV
+    fn get_exposure_bias(&self) -> Option<i32> {
+        let field = self
+            .exif
+            .as_ref()?
+            .get_field(Tag::ExposureBiasValue, In::PRIMARY)?;
+        match &field.value {
+            exif::Value::SRational(values) if values.first()?.denom != 0 => {
+                let value = values.first()?;
+                Some((value.num as i64 * 100 / value.denom as i64) as i32)
+            }
+            _ => None,
+        }
+    }
 }
 
 impl PropertyResolver for ExifResolver {
-    fn get_timestamp(&self) -> i64 {
+    fn get_timestamp(&self) -> Timestamp {
         let file_resolver = FileResolver::new(&self.path);
         match &self.exif {
             Some(exif) => {
@@ -104,7 +195,11 @@ impl PropertyResolver for ExifResolver {
                         if let Ok(date_time) =
                             NaiveDateTime::parse_from_str(&date_time_str, "%Y-%m-%d %H:%M:%S")
                         {
-                            date_time.and_utc().timestamp()
+                            Timestamp::new(
+                                date_time.and_utc().timestamp_millis()
+                                    + self.get_sub_second().unwrap_or(0) as i64,
+                                self.get_offset(),
+                            )
                         } else {
                             file_resolver.get_timestamp()
                         }
@@ -138,6 +233,56 @@ impl PropertyResolver for ExifResolver {
             None => None,
         }
     }
+
+    fn get_metadata(&self) -> Metadata {
+        Metadata {
+            camera_make: self.get_string(Tag::Make),
+            camera_model: self.get_string(Tag::Model),
+            camera_serial: self.get_string(Tag::BodySerialNumber),
+            resolution: self
+                .get_uint(Tag::PixelXDimension)
+                .zip(self.get_uint(Tag::PixelYDimension))
+                .or_else(|| {
+                    self.get_uint(Tag::ImageWidth)
+                        .zip(self.get_uint(Tag::ImageLength))
+                }),
+            sub_second: self.get_sub_second(),
+            exposure_bias: self.get_exposure_bias(),
+            // Exposure mode 2 is auto bracket
+            auto_bracket: self.get_uint(Tag::ExposureMode) == Some(2),
+            lens: self.get_string(Tag::LensModel),
+            focal_length: self.get_tenths(Tag::FocalLength),
+            f_number: self.get_tenths(Tag::FNumber),
+            exposure_time: self
+                .get_rational(Tag::ExposureTime)
+                .map(|value| (value.num, value.denom)),
+            iso: self.get_uint(Tag::PhotographicSensitivity),
+            gps: self
+                .get_coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef)
+                .zip(self.get_coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef)),
+            duration: None,
+            codec: None,
+            embedded_video: find_embedded_video(&self.path),
+            irregular_orientation: self
+                .get_uint(Tag::Orientation)
+                .is_some_and(|orientation| ![1, 3, 6, 8].contains(&orientation)),
+        }
+    }
+
+    fn get_details(&self) -> Vec<(String, String)> {
+        let Some(exif) = &self.exif else {
+            return vec![];
+        };
+        exif.fields()
+            .filter(|field| field.ifd_num == In::PRIMARY && field.tag != Tag::MakerNote)
+            .map(|field| {
+                (
+                    field.tag.to_string(),
+                    detail_value(&field.display_value().with_unit(exif).to_string()),
+                )
+            })
+            .collect()
+    }
 }
 
 struct FFmpegResolver {
@@ -161,14 +306,15 @@ impl FFmpegResolver {
 }
 
 impl PropertyResolver for FFmpegResolver {
-    fn get_timestamp(&self) -> i64 {
+    fn get_timestamp(&self) -> Timestamp {
         let file_resolver = FileResolver::new(&self.path);
         if let Ok(context) = ffmpeg::format::input(&self.path) {
             for (k, v) in context.metadata().iter() {
+                // The creation time of videos is stored in UTC
                 if k == "creation_time"
                     && let Ok(date_time) = NaiveDateTime::parse_from_str(v, "%+")
                 {
-                    return date_time.and_utc().timestamp();
+                    return Timestamp::new(date_time.and_utc().timestamp_millis(), Some(0));
                 }
             }
         }
@@ -209,6 +355,103 @@ impl PropertyResolver for FFmpegResolver {
         }
         None
     }
+
+    fn get_metadata(&self) -> Metadata {
+        let mut metadata = Metadata::default();
+        let Ok(context) = ffmpeg::format::input(&self.path) else {
+            return metadata;
+        };
+        // The duration is given in microseconds
+        if context.duration() > 0 {
+            metadata.duration = Some(context.duration() as u64 / 1000);
+        }
+        for (k, v) in context.metadata().iter() {
+            match k {
+                "location" => metadata.gps = parse_iso6709(v),
+                "com.apple.quicktime.make" => metadata.camera_make = Some(String::from(v)),
+                "com.apple.quicktime.model" => metadata.camera_model = Some(String::from(v)),
+                _ => {}
+            }
+        }
+        if let Some(video_stream) = context.streams().best(ffmpeg::media::Type::Video) {
+            let id = video_stream.parameters().id();
+            metadata.codec = ffmpeg::codec::decoder::find(id).map(|codec| String::from(codec.name()));
+            if let Ok(context) = ffmpeg::codec::Context::from_parameters(video_stream.parameters())
+                && let Ok(decoder) = context.decoder().video()
+            {
+                metadata.resolution = Some((decoder.width(), decoder.height()));
+            }
+        }
+        metadata
+    }
+
+    fn get_details(&self) -> Vec<(String, String)> {
+        let Ok(context) = ffmpeg::format::input(&self.path) else {
+            return vec![];
+        };
+        let mut details = vec![(
+            String::from("Format"),
+            String::from(context.format().description()),
+        )];
+        if context.duration() > 0 {
+            details.push((
+                String::from("Duration"),
+                format!("{:.1} s", context.duration() as f64 / 1_000_000.0),
+            ));
+        }
+        for (k, v) in context.metadata().iter() {
+            details.push((String::from(k), detail_value(v)));
+        }
+        for stream in context.streams() {
+            let parameters = stream.parameters();
+            let codec = ffmpeg::codec::decoder::find(parameters.id())
+                .map(|codec| String::from(codec.name()))
+                .unwrap_or_else(|| format!("{:?}", parameters.id()));
+            details.push((
+                format!("Stream {}", stream.index()),
+                format!("{:?}, {}", parameters.medium(), codec),
+            ));
+            for (k, v) in stream.metadata().iter() {
+                details.push((format!("Stream {} {}", stream.index(), k), detail_value(v)));
+            }
+        }
+        details
+    }
+}
+
+/// Shortens a metadata value for display
+fn detail_value(value: &str) -> String {
+    let value = value.trim();
+    if value.chars().count() > MAX_DETAIL_LENGTH {
+        value.chars().take(MAX_DETAIL_LENGTH).collect::<String>() + "…"
+    } else {
+        String::from(value)
+    }
+}
+
+/// Parses a location in the ISO 6709 format used by videos, like +48.8577+002.2950+035.000/, and returns latitude and
+/// longitude in millionths of a degree
+fn parse_iso6709(location: &str) -> Option<(i32, i32)> {
+    let location = location.trim_end_matches('/');
+    let starts: Vec<usize> = location
+        .char_indices()
+        .filter(|(_, c)| *c == '+' || *c == '-')
+        .map(|(index, _)| index)
+        .collect();
+    if starts.len() < 2 || starts[0] != 0 {
+        return None;
+    }
+    let latitude: f64 = location[starts[0]..starts[1]].parse().ok()?;
+    let longitude: f64 = location[starts[1]..*starts.get(2).unwrap_or(&location.len())]
+        .parse()
+        .ok()?;
+    if latitude.abs() > 90.0 || longitude.abs() > 180.0 {
+        return None;
+    }
+    Some((
+        (latitude * 1_000_000.0).round() as i32,
+        (longitude * 1_000_000.0).round() as i32,
+    ))
 }
 
 struct RawResolver {
@@ -228,10 +471,18 @@ impl RawResolver {
 }
 
 impl PropertyResolver for RawResolver {
-    fn get_timestamp(&self) -> i64 {
+    fn get_timestamp(&self) -> Timestamp {
         ExifResolver::new(&self.path).get_timestamp()
     }
 
+    fn get_metadata(&self) -> Metadata {
+        ExifResolver::new(&self.path).get_metadata()
+    }
+
+    fn get_details(&self) -> Vec<(String, String)> {
+        ExifResolver::new(&self.path).get_details()
+    }
+
     fn get_orientation(&self) -> Option<Orientation> {
         match rawloader::decode_file(&self.path) {
             Ok(raw) => match raw.orientation {
@@ -251,7 +502,7 @@ mod tests {
     use super::*;
 
     fn get_timestamp_from(path: &str) -> i64 {
-        get_resolver(Path::new(path)).get_timestamp()
+        get_resolver(Path::new(path)).get_timestamp().seconds()
     }
 
     fn get_orientation_from(path: &str) -> Option<Orientation> {
@@ -259,7 +510,7 @@ mod tests {
     }
 
     fn get_file_timestamp(path: &str) -> i64 {
-        FileResolver::new(Path::new(path)).get_timestamp()
+        FileResolver::new(Path::new(path)).get_timestamp().seconds()
     }
 
     #[test]
@@ -302,6 +553,21 @@ mod tests {
             get_timestamp_from("tests/test.mp4")
         );
         assert_eq!(None, get_orientation_from("tests/test.mp4"));
+        assert!(
+            get_resolver(Path::new("tests/test.mp4"))
+                .get_metadata()
+                .resolution
+                .is_some()
+        );
+        let metadata = get_resolver(Path::new("tests/test.mp4")).get_metadata();
+        assert!(metadata.duration.is_some());
+        assert!(metadata.codec.is_some());
+        assert!(
+            get_resolver(Path::new("tests/test.mp4"))
+                .get_details()
+                .iter()
+                .any(|(name, _)| name == "Stream 0")
+        );
         assert_eq!(1640790497, get_timestamp_from("tests/test2.MP4"));
         assert_eq!(
             Some(Orientation::Landscape180),
@@ -312,10 +578,30 @@ mod tests {
             get_timestamp_from("tests/test_invalid.mp4")
         );
 
+        let details = get_resolver(Path::new("tests/test.jpg")).get_details();
+        assert!(details.iter().any(|(name, _)| name == "DateTimeOriginal"));
+        assert!(get_resolver(Path::new("LICENSE")).get_details().is_empty());
+
         assert_eq!(974638910, get_timestamp_from("tests/test.nef"));
         assert_eq!(None, get_orientation_from("tests/test.nef"));
 
         assert_eq!(-1, get_timestamp_from("not_there"));
+        assert_eq!(
+            None,
+            FileResolver::new(Path::new("LICENSE")).get_timestamp().offset
+        );
         assert_eq!(get_file_timestamp("LICENSE"), get_timestamp_from("LICENSE"));
     }
+
+    #[test]
+    fn test_parse_iso6709() {
+        assert_eq!(
+            Some((48_857_700, 2_295_000)),
+            parse_iso6709("+48.8577+002.2950+035.000/")
+        );
+        assert_eq!(Some((-33_856_800, -151_215_300)), parse_iso6709("-33.8568-151.2153/"));
+        assert_eq!(None, parse_iso6709("48.8577+002.2950"));
+        assert_eq!(None, parse_iso6709("+98.0+002.0/"));
+        assert_eq!(None, parse_iso6709(""));
+    }
 }
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+use super::file_item::FileItem;
+use super::file_types::is_sidecar;
+
+/// How the name of a sidecar file matches the name of its item, better matches come first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum NameMatch {
+    /// The sidecar is named by the complete file name of the item, like DSC_0001.JPG.xmp
+    FileName,
+    /// The sidecar has the same stem as the item, like DSC_0001.xmp
+    Stem,
+    /// GoPro low resolution videos and thumbnails are named GL010123.LRV for the video GH010123.MP4 or GX010123.MP4
+    GoPro,
+}
+
+/// Checks how the name of a sidecar file matches the name of an item, None if it does not belong to the item
+fn name_match(sidecar: &Path, item: &Path) -> Option<NameMatch> {
+    let name = |path: Option<&std::ffi::OsStr>| path.map(|name| name.to_string_lossy().to_lowercase());
+    let sidecar_stem = name(sidecar.file_stem())?;
+    let item_stem = name(item.file_stem())?;
+    if Some(&sidecar_stem) == name(item.file_name()).as_ref() {
+        Some(NameMatch::FileName)
+    } else if sidecar_stem == item_stem {
+        Some(NameMatch::Stem)
+    } else if let Some(number) = sidecar_stem.strip_prefix("gl")
+        && (item_stem.strip_prefix("gh") == Some(number)
+            || item_stem.strip_prefix("gx") == Some(number))
+    {
+        Some(NameMatch::GoPro)
+    } else {
+        None
+    }
+}
+
+/// Finds the sidecar files of all items in the directories of the items. Each sidecar file is assigned to the item
+/// whose name matches best, items which are companions of other items are only used if no other item matches.
+/// Returns the sidecar files of each item in the order of the items.
+pub fn find_sidecars(items: &[FileItem]) -> Vec<Vec<PathBuf>> {
+    let mut directories: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for (index, item) in items.iter().enumerate() {
+        if let Some(directory) = item.path.parent() {
+            directories
+                .entry(directory.to_path_buf())
+                .or_default()
+                .push(index);
+        }
+    }
+
+    let mut sidecars = vec![vec![]; items.len()];
+    for (directory, indices) in directories {
+        let listed = if directory.as_os_str().is_empty() {
+            read_dir(".")
+        } else {
+            read_dir(&directory)
+        };
+        let Ok(entries) = listed else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| directory.join(entry.file_name()))
+            .filter(|path| is_sidecar(path) && path.is_file())
+            .collect();
+        files.sort_unstable();
+        for sidecar in files {
+            let best = indices
+                .iter()
+                .filter_map(|index| {
+                    name_match(&sidecar, &items[*index].path).map(|name_match| {
+                        (name_match, items[*index].get_primary().is_some(), *index)
+                    })
+                })
+                .min();
+            if let Some((_, _, index)) = best {
+                sidecars[index].push(sidecar);
+            }
+        }
+    }
+    sidecars
+}
+
+/// Gets the target path of a sidecar file when its item is put to the given target, so that the sidecar still
+/// belongs to the item if the item is renamed
+pub fn sidecar_target(sidecar: &Path, item: &Path, target: &Path) -> PathBuf {
+    let extension = sidecar.extension().unwrap_or_default();
+    match name_match(sidecar, item) {
+        Some(NameMatch::FileName) => {
+            let mut file_name = target.file_name().unwrap_or_default().to_os_string();
+            file_name.push(".");
+            file_name.push(extension);
+            target.with_file_name(file_name)
+        }
+        Some(NameMatch::Stem) => target.with_extension(extension),
+        _ => target.with_file_name(sidecar.file_name().unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    #[test]
+    fn test_name_match() {
+        let item = Path::new("dir/DSC_0001.JPG");
+        assert_eq!(
+            name_match(Path::new("dir/dsc_0001.jpg.xmp"), item),
+            Some(NameMatch::FileName)
+        );
+        assert_eq!(
+            name_match(Path::new("dir/DSC_0001.AAE"), item),
+            Some(NameMatch::Stem)
+        );
+        assert_eq!(name_match(Path::new("dir/DSC_0002.xmp"), item), None);
+        assert_eq!(
+            name_match(Path::new("dir/GL010123.LRV"), Path::new("dir/GX010123.MP4")),
+            Some(NameMatch::GoPro)
+        );
+        assert_eq!(
+            name_match(Path::new("dir/GL010124.LRV"), Path::new("dir/GX010123.MP4")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_sidecars() {
+        let directory = Path::new("tests/sidecars");
+        remove_dir_all(directory).ok();
+        create_dir_all(directory).unwrap();
+        for file in ["DSC_0001.xmp", "DSC_0001.NEF.xmp", "GL010123.LRV", "GX010123.THM", "other.xmp"] {
+            write(directory.join(file), "").unwrap();
+        }
+
+        let mut items = vec![
+            FileItem::dummy("tests/sidecars/DSC_0001.JPG", 0, true),
+            FileItem::dummy("tests/sidecars/DSC_0001.NEF", 0, true),
+            FileItem::dummy("tests/sidecars/GX010123.MP4", 0, true),
+        ];
+        items[1].set_primary(Some(0));
+        let sidecars = find_sidecars(&items);
+        remove_dir_all(directory).ok();
+
+        assert_eq!(
+            sidecars,
+            vec![
+                vec![directory.join("DSC_0001.xmp")],
+                vec![directory.join("DSC_0001.NEF.xmp")],
+                vec![directory.join("GL010123.LRV"), directory.join("GX010123.THM")],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sidecar_target() {
+        let item = Path::new("source/DSC_0001.JPG");
+        let target = Path::new("target/2024-05-03_1.JPG");
+        assert_eq!(
+            sidecar_target(Path::new("source/DSC_0001.JPG.xmp"), item, target),
+            PathBuf::from("target/2024-05-03_1.JPG.xmp")
+        );
+        assert_eq!(
+            sidecar_target(Path::new("source/DSC_0001.AAE"), item, target),
+            PathBuf::from("target/2024-05-03_1.AAE")
+        );
+        assert_eq!(
+            sidecar_target(
+                Path::new("source/GL010123.LRV"),
+                Path::new("source/GX010123.MP4"),
+                Path::new("target/GX010123.MP4")
+            ),
+            PathBuf::from("target/GL010123.LRV")
+        );
+    }
+}
@@ -1,12 +1,20 @@
 use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
     fs::{copy, create_dir_all, metadata, remove_file, rename, File},
-    io::{Error, ErrorKind, Read},
+    io::{BufRead, BufReader, Error, ErrorKind},
+    ops::Range,
     path::{Path, PathBuf},
 };
 
-use chrono::Datelike;
+use serde::{Deserialize, Serialize};
 
-use super::{file_item, timestamp_to_string, DirectoryNames, Format, ItemList, SieveMethod};
+use super::archive_index::{hash_file, ArchiveIndex};
+use super::metadata_writer::{self, MetadataUpdate};
+use super::motion_photo::{self, MotionPhotos};
+use super::sidecars::sidecar_target;
+use super::{FileItem, ItemList, PathTemplate, SieveMethod};
 
 /// Trait to encapsulate sieve file IO operations
 pub trait SieveIO {
@@ -14,55 +22,142 @@ pub trait SieveIO {
     fn remove_file(&self, path: &Path) -> Result<(), Error>;
     fn r#move(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error>;
     fn create_dir_all(&self, path: &Path) -> Result<(), Error>;
+    /// Writes corrected metadata to a file and returns the path of the sidecar file if one was created
+    fn write_metadata(&self, path: &Path, update: &MetadataUpdate) -> Result<Option<PathBuf>, Error>;
+    fn extract(&self, src: &Path, range: Range<u64>, dest: &mut PathBuf) -> Result<(), Error>;
+    /// Copies or moves a sidecar file next to its sieved item. In contrast to items, sidecar files are not checked
+    /// against the archive index, because identical sidecar files may belong to different items.
+    fn carry_sidecar(&self, src: &Path, dest: &mut PathBuf, sieve_method: SieveMethod) -> Result<(), Error>;
 }
 
-/// Struct with implementation for std::fs implementation of SieveIO
-pub struct FileSieveIO;
-
-impl FileSieveIO {
-    fn different(&self, f1: &Path, f2: &Path) -> Result<bool, Error> {
-        if metadata(f1)?.len() == metadata(f2)?.len() {
-            let mut content1 = vec![];
-            let mut fh1 = File::open(f1)?;
-            fh1.read_to_end(&mut content1)?;
-            let mut content2 = vec![];
-            let mut fh2 = File::open(f2)?;
-            fh2.read_to_end(&mut content2)?;
-            Ok(content1 != content2)
+/// Struct with implementation for std::fs implementation of SieveIO. If an archive index is given, files which are
+/// already present anywhere in the archive are not copied or moved again.
+#[derive(Default)]
+pub struct FileSieveIO<'a> {
+    pub archive_index: Option<&'a RefCell<ArchiveIndex>>,
+}
+
+/// Returns an AlreadyExists error if the source file is found in the archive index and sets the destination to
+/// the file found in the archive
+fn check_archive(
+    src: &Path,
+    dest: &mut PathBuf,
+    archive_index: &mut ArchiveIndex,
+) -> Result<(), Error> {
+    archive_present(archive_index.find(src)?, dest)
+}
+
+/// Returns an AlreadyExists error if a file with the content to sieve was found in the archive and sets the
+/// destination to this file
+fn archive_present(existing: Option<PathBuf>, dest: &mut PathBuf) -> Result<(), Error> {
+    if let Some(existing) = existing {
+        *dest = existing;
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("File already present in archive: {}", dest.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Size of the chunks in which files are compared
+const COMPARE_CHUNK_SIZE: usize = 1 << 16;
+
+/// Checks if two files have a different content. The files are compared chunk by chunk, so that large videos are
+/// not read into memory at once.
+fn different(f1: &Path, f2: &Path) -> Result<bool, Error> {
+    if metadata(f1)?.len() != metadata(f2)?.len() {
+        return Ok(true);
+    }
+    let mut reader1 = BufReader::with_capacity(COMPARE_CHUNK_SIZE, File::open(f1)?);
+    let mut reader2 = BufReader::with_capacity(COMPARE_CHUNK_SIZE, File::open(f2)?);
+    loop {
+        let chunk1 = reader1.fill_buf()?;
+        let chunk2 = reader2.fill_buf()?;
+        let length = chunk1.len().min(chunk2.len());
+        if length == 0 {
+            return Ok(chunk1.len() != chunk2.len());
+        }
+        if chunk1[..length] != chunk2[..length] {
+            return Ok(true);
+        }
+        reader1.consume(length);
+        reader2.consume(length);
+    }
+}
+
+/// Copies a file and verifies the copy by comparing the checksums of source and destination. If the verification
+/// fails, the copy is removed again.
+fn copy_verified(src: &Path, dest: &Path) -> Result<(), Error> {
+    copy(src, dest)?;
+    if hash_file(src)? != hash_file(dest)? {
+        remove_file(dest).ok();
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Verification of copy failed: {}", dest.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks if a source file can be put to a destination. The existing_file function returns the file whose
+/// content is found at a destination or None if the destination is free. If a different file is found, the
+/// destination file name is extended by an underscore until a free or identical destination is found.
+/// If an identical file is found, an AlreadyExists error is returned.
+fn check_target(
+    src: &Path,
+    dest: &mut PathBuf,
+    existing_file: &impl Fn(&Path) -> Option<PathBuf>,
+) -> Result<(), Error> {
+    if let Some(existing) = existing_file(dest) {
+        if different(src, &existing)? {
+            let mut new_file_name = dest.file_stem().unwrap().to_os_string();
+            new_file_name.push("_.");
+            new_file_name.push(dest.extension().unwrap());
+            let parent = dest.parent().unwrap().to_path_buf();
+            dest.clear();
+            dest.push(parent);
+            dest.push(new_file_name);
+
+            check_target(src, dest, existing_file)
         } else {
-            Ok(true)
+            let e = Error::new(
+                ErrorKind::AlreadyExists,
+                format!("Destination file already exists: {}", dest.display()),
+            );
+            Err(e)
         }
+    } else {
+        Ok(())
     }
+}
 
+impl FileSieveIO<'_> {
     fn check_target(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error> {
-        if dest.exists() {
-            if self.different(src, dest)? {
-                let mut new_file_name = dest.file_stem().unwrap().to_os_string();
-                new_file_name.push("_.");
-                new_file_name.push(dest.extension().unwrap());
-                let parent = dest.parent().unwrap().to_path_buf();
-                dest.clear();
-                dest.push(parent);
-                dest.push(new_file_name);
-
-                self.check_target(src, dest)
+        if let Some(archive_index) = self.archive_index {
+            check_archive(src, dest, &mut archive_index.borrow_mut())?;
+        }
+        check_target(src, dest, &|path: &Path| {
+            if path.exists() {
+                Some(path.to_path_buf())
             } else {
-                let e = Error::new(
-                    ErrorKind::AlreadyExists,
-                    format!("Destination file already exists: {}", dest.display()),
-                );
-                Err(e)
+                None
             }
-        } else {
-            Ok(())
+        })
+    }
+
+    fn add_to_archive(&self, path: &Path) {
+        if let Some(archive_index) = self.archive_index {
+            archive_index.borrow_mut().add(path).ok();
         }
     }
 }
 
-impl SieveIO for FileSieveIO {
+impl SieveIO for FileSieveIO<'_> {
     fn copy(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error> {
         self.check_target(src, dest)?;
-        copy(src, dest)?;
+        copy(src, &dest)?;
+        self.add_to_archive(dest);
         Ok(())
     }
 
@@ -73,9 +168,14 @@ impl SieveIO for FileSieveIO {
     fn r#move(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error> {
         self.check_target(src, dest)?;
         match rename(src, dest.clone()) {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                self.add_to_archive(dest);
+                Ok(())
+            }
             Err(_) => {
-                self.copy(src, dest)?;
+                // The source is only removed if the copy is identical
+                copy_verified(src, dest)?;
+                self.add_to_archive(dest);
                 self.remove_file(src)
             }
         }
@@ -84,163 +184,852 @@ impl SieveIO for FileSieveIO {
     fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
         create_dir_all(path)
     }
+
+    fn write_metadata(&self, path: &Path, update: &MetadataUpdate) -> Result<Option<PathBuf>, Error> {
+        metadata_writer::write_metadata(path, update)
+    }
+
+    fn extract(&self, src: &Path, range: Range<u64>, dest: &mut PathBuf) -> Result<(), Error> {
+        // The part is extracted to a temporary file first, so that it can be compared with existing files
+        let mut part = dest.clone().into_os_string();
+        part.push(".part");
+        let part = PathBuf::from(part);
+        motion_photo::extract(src, range, &part)?;
+        match self
+            .check_target(&part, dest)
+            .and_then(|_| rename(&part, &dest))
+        {
+            Ok(_) => {
+                self.add_to_archive(dest);
+                Ok(())
+            }
+            Err(e) => {
+                remove_file(&part).ok();
+                Err(e)
+            }
+        }
+    }
+
+    fn carry_sidecar(&self, src: &Path, dest: &mut PathBuf, sieve_method: SieveMethod) -> Result<(), Error> {
+        let file_io = FileSieveIO::default();
+        if sieve_method == SieveMethod::Copy {
+            file_io.copy(src, dest)
+        } else {
+            file_io.r#move(src, dest)
+        }
+    }
+}
+
+/// A single file operation that a sieve process would perform
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlannedOperation {
+    /// A directory is created
+    CreateDirectory { path: PathBuf },
+    /// A file is copied, renamed indicates that the target file name was changed to avoid overwriting a different file
+    Copy {
+        source: PathBuf,
+        target: PathBuf,
+        renamed: bool,
+    },
+    /// A file is moved, renamed indicates that the target file name was changed to avoid overwriting a different file
+    Move {
+        source: PathBuf,
+        target: PathBuf,
+        renamed: bool,
+    },
+    /// A part of a file is written to a new file, like the video embedded in a motion photo
+    Extract {
+        source: PathBuf,
+        target: PathBuf,
+        renamed: bool,
+    },
+    /// A file is deleted
+    Delete { path: PathBuf },
+    /// A file is skipped, because an identical file already exists in the target
+    Skip { source: PathBuf, existing: PathBuf },
+    /// Corrected metadata is written to a file, or to the given sidecar file if the file can't be modified in place
+    WriteMetadata {
+        path: PathBuf,
+        sidecar: Option<PathBuf>,
+    },
+}
+
+impl Display for PlannedOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let renamed_str = |renamed: &bool| if *renamed { " (renamed)" } else { "" };
+        match self {
+            PlannedOperation::CreateDirectory { path } => {
+                write!(f, "Create directory {}", path.display())
+            }
+            PlannedOperation::Copy {
+                source,
+                target,
+                renamed,
+            } => write!(
+                f,
+                "Copy {} -> {}{}",
+                source.display(),
+                target.display(),
+                renamed_str(renamed)
+            ),
+            PlannedOperation::Move {
+                source,
+                target,
+                renamed,
+            } => write!(
+                f,
+                "Move {} -> {}{}",
+                source.display(),
+                target.display(),
+                renamed_str(renamed)
+            ),
+            PlannedOperation::Extract {
+                source,
+                target,
+                renamed,
+            } => write!(
+                f,
+                "Extract {} -> {}{}",
+                source.display(),
+                target.display(),
+                renamed_str(renamed)
+            ),
+            PlannedOperation::Delete { path } => write!(f, "Delete {}", path.display()),
+            PlannedOperation::Skip { source, existing } => write!(
+                f,
+                "Skip {}, identical to {}",
+                source.display(),
+                existing.display()
+            ),
+            PlannedOperation::WriteMetadata { path, sidecar } => match sidecar {
+                Some(sidecar) => write!(
+                    f,
+                    "Write metadata of {} to {}",
+                    path.display(),
+                    sidecar.display()
+                ),
+                None => write!(f, "Write metadata to {}", path.display()),
+            },
+        }
+    }
+}
+
+/// The list of operations a sieve process would perform
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SievePlan {
+    /// Planned operations in the order of execution
+    pub operations: Vec<PlannedOperation>,
+}
+
+/// Implementation of SieveIO that does not touch the file system, but records all operations in a sieve plan.
+/// Target checks take both the file system and the previously planned operations into account, so the plan
+/// contains the same target file names as a real sieve run.
+#[derive(Default)]
+pub struct DryRunSieveIO {
+    plan: RefCell<SievePlan>,
+    /// Copy of the archive index, files found there are planned to be skipped
+    archive_index: Option<RefCell<ArchiveIndex>>,
+    /// Planned target files mapped to the source files that would be put there
+    planned_targets: RefCell<HashMap<PathBuf, PathBuf>>,
+    planned_directories: RefCell<HashSet<PathBuf>>,
+}
+
+impl DryRunSieveIO {
+    /// Creates a dry run IO object which also skips files already present in the archive
+    pub fn with_archive_index(archive_index: ArchiveIndex) -> Self {
+        Self {
+            archive_index: Some(RefCell::new(archive_index)),
+            ..Default::default()
+        }
+    }
+
+    /// Consumes the IO object and returns the recorded plan
+    pub fn into_plan(self) -> SievePlan {
+        self.plan.into_inner()
+    }
+
+    /// Check the target like the file system IO would do, but also consider planned target files. The archive index
+    /// is only checked if check_index is set.
+    fn plan_transfer(&self, src: &Path, dest: &mut PathBuf, check_index: bool) -> Result<bool, Error> {
+        let original_dest = dest.clone();
+        let mut result = match &self.archive_index {
+            Some(archive_index) if check_index => {
+                check_archive(src, dest, &mut archive_index.borrow_mut())
+            }
+            _ => Ok(()),
+        };
+        if result.is_ok() {
+            result = self.check_planned_target(src, dest);
+        }
+        match result {
+            Ok(_) => {
+                self.planned_targets
+                    .borrow_mut()
+                    .insert(dest.clone(), src.to_path_buf());
+                Ok(*dest != original_dest)
+            }
+            Err(e) => {
+                if e.kind() == ErrorKind::AlreadyExists {
+                    self.push(PlannedOperation::Skip {
+                        source: src.to_path_buf(),
+                        existing: dest.clone(),
+                    });
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Check the target like the file system IO would do considering both existing and planned target files
+    fn check_planned_target(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error> {
+        check_target(src, dest, &|path: &Path| {
+            if let Some(planned_source) = self.planned_targets.borrow().get(path) {
+                Some(planned_source.clone())
+            } else if path.exists() {
+                Some(path.to_path_buf())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn push(&self, operation: PlannedOperation) {
+        self.plan.borrow_mut().operations.push(operation);
+    }
+}
+
+impl SieveIO for DryRunSieveIO {
+    fn copy(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error> {
+        let renamed = self.plan_transfer(src, dest, true)?;
+        self.push(PlannedOperation::Copy {
+            source: src.to_path_buf(),
+            target: dest.clone(),
+            renamed,
+        });
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), Error> {
+        self.push(PlannedOperation::Delete {
+            path: path.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn r#move(&self, src: &Path, dest: &mut PathBuf) -> Result<(), Error> {
+        let renamed = self.plan_transfer(src, dest, true)?;
+        self.push(PlannedOperation::Move {
+            source: src.to_path_buf(),
+            target: dest.clone(),
+            renamed,
+        });
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<(), Error> {
+        if self
+            .planned_directories
+            .borrow_mut()
+            .insert(path.to_path_buf())
+        {
+            self.push(PlannedOperation::CreateDirectory {
+                path: path.to_path_buf(),
+            });
+        }
+        Ok(())
+    }
+
+    fn write_metadata(&self, path: &Path, update: &MetadataUpdate) -> Result<Option<PathBuf>, Error> {
+        // The file is usually a planned target which does not exist yet, so the decision is based on its source
+        let source = self
+            .planned_targets
+            .borrow()
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| path.to_path_buf());
+        let sidecar = if metadata_writer::needs_sidecar(&source, update)? {
+            Some(metadata_writer::sidecar_path(path))
+        } else {
+            None
+        };
+        if let Some(sidecar) = &sidecar {
+            if sidecar.exists() || self.planned_targets.borrow().contains_key(sidecar) {
+                return Err(metadata_writer::sidecar_exists_error(sidecar));
+            }
+            self.planned_targets
+                .borrow_mut()
+                .insert(sidecar.clone(), source);
+        }
+        if !update.is_empty() {
+            self.push(PlannedOperation::WriteMetadata {
+                path: path.to_path_buf(),
+                sidecar: sidecar.clone(),
+            });
+        }
+        Ok(sidecar)
+    }
+
+    fn extract(&self, src: &Path, range: Range<u64>, dest: &mut PathBuf) -> Result<(), Error> {
+        // The source may be a planned target file which does not exist yet
+        let src = self
+            .planned_targets
+            .borrow()
+            .get(src)
+            .cloned()
+            .unwrap_or_else(|| src.to_path_buf());
+        let original_dest = dest.clone();
+        // Like the file system IO, the extracted part is looked up in the archive index
+        let existing = match &self.archive_index {
+            Some(archive_index) => archive_index.borrow_mut().find_part(&src, range)?,
+            None => None,
+        };
+        if let Err(e) =
+            archive_present(existing, dest).and_then(|_| self.check_planned_target(&src, dest))
+        {
+            if e.kind() == ErrorKind::AlreadyExists {
+                self.push(PlannedOperation::Skip {
+                    source: src,
+                    existing: dest.clone(),
+                });
+            }
+            return Err(e);
+        }
+        self.planned_targets
+            .borrow_mut()
+            .insert(dest.clone(), src.clone());
+        self.push(PlannedOperation::Extract {
+            source: src,
+            target: dest.clone(),
+            renamed: *dest != original_dest,
+        });
+        Ok(())
+    }
+
+    fn carry_sidecar(&self, src: &Path, dest: &mut PathBuf, sieve_method: SieveMethod) -> Result<(), Error> {
+        let renamed = self.plan_transfer(src, dest, false)?;
+        let (source, target) = (src.to_path_buf(), dest.clone());
+        self.push(if sieve_method == SieveMethod::Copy {
+            PlannedOperation::Copy {
+                source,
+                target,
+                renamed,
+            }
+        } else {
+            PlannedOperation::Move {
+                source,
+                target,
+                renamed,
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Statistics of a sieve process
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SieveStatistics {
+    /// Number of copied files including renamed ones
+    pub copied: usize,
+    /// Number of moved files including renamed ones
+    pub moved: usize,
+    /// Number of files that were copied or moved to a different file name
+    pub renamed: usize,
+    /// Number of files skipped because an identical file already existed
+    pub skipped: usize,
+    /// Number of deleted files
+    pub deleted: usize,
+    /// Number of failed operations
+    pub failed: usize,
+}
+
+/// Event reported during a sieve process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SieveEvent {
+    /// The sieve process started and will process the given number of files
+    Started { total: usize },
+    /// A file was copied
+    Copied { source: PathBuf, target: PathBuf },
+    /// A file was moved
+    Moved { source: PathBuf, target: PathBuf },
+    /// A file was copied or moved to a different file name, because a different file with the same name existed
+    Renamed { source: PathBuf, target: PathBuf },
+    /// A file was skipped, because an identical file already existed
+    Skipped { source: PathBuf, duplicate: PathBuf },
+    /// A file was deleted
+    Deleted { path: PathBuf },
+    /// An operation failed
+    Failed {
+        path: PathBuf,
+        kind: ErrorKind,
+        message: String,
+    },
+    /// The sieve process finished
+    Finished { statistics: SieveStatistics },
+}
+
+impl SieveEvent {
+    /// Creates a failed event from an IO error
+    pub fn failed(operation: &str, path: &Path, error: &Error) -> Self {
+        SieveEvent::Failed {
+            path: path.to_path_buf(),
+            kind: error.kind(),
+            message: format!("{} failed: {}", operation, error),
+        }
+    }
+}
+
+impl Display for SieveEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SieveEvent::Started { total } => write!(f, "Sieving {} files", total),
+            SieveEvent::Copied { source, target } => {
+                write!(f, "Copied {} -> {}", source.display(), target.display())
+            }
+            SieveEvent::Moved { source, target } => {
+                write!(f, "Moved {} -> {}", source.display(), target.display())
+            }
+            SieveEvent::Renamed { source, target } => {
+                write!(f, "{} -> {} (renamed)", source.display(), target.display())
+            }
+            SieveEvent::Skipped { source, duplicate } => write!(
+                f,
+                "Skipped {}, identical to {}",
+                source.display(),
+                duplicate.display()
+            ),
+            SieveEvent::Deleted { path } => write!(f, "Deleted {}", path.display()),
+            SieveEvent::Failed { path, message, .. } => {
+                write!(f, "Error: {}: {}", path.display(), message)
+            }
+            SieveEvent::Finished { statistics } => write!(
+                f,
+                "Done: {} copied, {} moved, {} renamed, {} skipped, {} deleted, {} failed",
+                statistics.copied,
+                statistics.moved,
+                statistics.renamed,
+                statistics.skipped,
+                statistics.deleted,
+                statistics.failed
+            ),
+        }
+    }
 }
 
 /// Sieves an item list taking the take_over flag into account to a new directory.
-/// The progress is reported by calling a callback function with the file that is currently processed.
+/// The progress is reported by calling a callback function with an event for every processed file.
+/// Motion photos are sieved as they are, with their video extracted or as still images only depending on
+/// motion_photos. Without the video, the video of a live photo is handled like a discarded item.
 pub fn sieve<T>(
     item_list: &ItemList,
     path: &Path,
     sieve_method: SieveMethod,
-    sieve_directory_names: DirectoryNames,
+    path_template: &PathTemplate,
+    motion_photos: MotionPhotos,
     sieve_io: &T,
-    progress_callback: impl Fn(String),
+    progress_callback: impl Fn(SieveEvent),
 ) where
     T: SieveIO,
 {
+    let mut statistics = SieveStatistics::default();
+    let take_over = |item: &FileItem| {
+        item.get_take_over()
+            && !(motion_photos == MotionPhotos::StillOnly
+                && item.is_video()
+                && item
+                    .get_primary()
+                    .is_some_and(|primary| item_list.items[primary].is_live_photo()))
+    };
+    let total = item_list
+        .items
+        .iter()
+        .filter(|item| match sieve_method {
+            SieveMethod::Copy | SieveMethod::Move => take_over(item),
+            SieveMethod::MoveAndDelete => true,
+            SieveMethod::Delete => !item.get_take_over(),
+        })
+        .map(|item| 1 + item.get_sidecars().len() + extract_video(item, motion_photos) as usize)
+        .sum();
+    progress_callback(SieveEvent::Started { total });
+
     if sieve_method != SieveMethod::Delete {
-        prepare_path(path, sieve_io);
+        prepare_path(path, sieve_io, &mut statistics, &progress_callback);
+        let targets = get_targets(item_list, path, path_template);
 
-        for item in &item_list.items {
-            if item.get_take_over() {
-                let sub_path: PathBuf = get_sub_path(item_list, item, &sieve_directory_names)
-                    .iter()
-                    .collect();
-                let full_path = path.join(sub_path);
-                prepare_path(&full_path, sieve_io);
-                let source = &item.path;
-                let mut target = full_path.join(source.file_name().unwrap());
-
-                if sieve_method == SieveMethod::Copy {
-                    match sieve_io.copy(source, &mut target) {
-                        Ok(_) => (),
-                        Err(e) => progress_callback(format!("Error copying {}: {}", item, e)),
-                    }
-                } else {
-                    match sieve_io.r#move(source, &mut target) {
-                        Ok(_) => (),
-                        Err(e) => progress_callback(format!("Error moving {}: {}", item, e)),
+        for (index, item) in item_list.items.iter().enumerate() {
+            if take_over(item) {
+                if is_companion(item_list, index) {
+                    // Companions are sieved together with their primary item
+                    continue;
+                }
+                let target = sieve_item(
+                    item,
+                    targets[index].clone(),
+                    item_list,
+                    sieve_method,
+                    motion_photos,
+                    sieve_io,
+                    &mut statistics,
+                    &progress_callback,
+                );
+                // Companions get the final name of their primary item, even if it was renamed to avoid a collision
+                for companion in item.get_companions() {
+                    let companion = &item_list.items[*companion];
+                    if take_over(companion) {
+                        let extension = companion.path.extension().unwrap_or_default();
+                        sieve_item(
+                            companion,
+                            target.with_extension(extension),
+                            item_list,
+                            sieve_method,
+                            motion_photos,
+                            sieve_io,
+                            &mut statistics,
+                            &progress_callback,
+                        );
                     }
-                };
-                progress_callback(format!("{:?} -> {:?}", source, target));
-            } else if sieve_method == SieveMethod::MoveAndDelete {
-                let source = &item.path;
-                progress_callback(format!("Delete {:?}", source));
-                match sieve_io.remove_file(source) {
-                    Ok(_) => (),
-                    Err(e) => progress_callback(format!("Error deleting {}: {}", item, e)),
                 }
+            } else if sieve_method == SieveMethod::MoveAndDelete {
+                delete_item(item, sieve_io, &mut statistics, &progress_callback);
             }
         }
     } else {
         for item in &item_list.items {
             if !item.get_take_over() {
-                let source = &item.path;
-                progress_callback(format!("Delete {:?}", source));
-                match sieve_io.remove_file(source) {
-                    Ok(_) => (),
-                    Err(e) => progress_callback(format!("Error deleting {:?}: {}", item, e)),
-                }
+                delete_item(item, sieve_io, &mut statistics, &progress_callback);
             }
         }
     }
 
-    progress_callback(String::from("Done"));
+    progress_callback(SieveEvent::Finished { statistics });
 }
 
-/// Gets the sub path of a file item taking the file item's timestamp and possible events into account.
-/// If a fileitem is part of an event, its sub path is the event's span and name.
-/// If it is not part of an event, its sub path is the file item's timestamp in the given format.
-fn get_sub_path(
+/// Copies or moves a single item to its target together with its sidecar files and reports the result. Returns the
+/// path the item was put to, which differs from the original target if the item was renamed or skipped.
+#[allow(clippy::too_many_arguments)]
+fn sieve_item<T>(
+    item: &FileItem,
+    original_target: PathBuf,
     item_list: &ItemList,
-    item: &file_item::FileItem,
-    directory_names: &DirectoryNames,
-) -> Vec<String> {
-    // TODO: This is a bit ugly.
-
-    let mut directories = Vec::<String>::new();
-    let event = item_list.get_event(item);
-    if let Some(event) = event {
-        if *directory_names == DirectoryNames::YearAndMonthInSubdirectory {
-            directories.push(event.start_date.format("%Y").to_string());
-            if event.start_date != event.end_date {
-                directories.push(format!(
-                    "{} - {} {}",
-                    event.start_date.format("%m-%d"),
-                    event
-                        .end_date
-                        .format(if event.start_date.year() != event.end_date.year() {
-                            "%Y-%m-%d"
-                        } else {
-                            "%m-%d"
-                        }),
-                    event.name
-                ));
+    sieve_method: SieveMethod,
+    motion_photos: MotionPhotos,
+    sieve_io: &T,
+    statistics: &mut SieveStatistics,
+    progress_callback: &impl Fn(SieveEvent),
+) -> PathBuf
+where
+    T: SieveIO,
+{
+    if let Some(directory) = original_target.parent() {
+        prepare_path(directory, sieve_io, statistics, progress_callback);
+    }
+    let source = &item.path;
+    let mut target = original_target.clone();
+
+    let embedded_video = item.get_metadata().embedded_video;
+    let (operation, result) = match embedded_video {
+        Some(offset) if motion_photos == MotionPhotos::StillOnly => (
+            "Extract",
+            sieve_io
+                .extract(source, 0..offset, &mut target)
+                .and_then(|_| match sieve_method {
+                    SieveMethod::Copy => Ok(()),
+                    _ => sieve_io.remove_file(source),
+                }),
+        ),
+        _ if sieve_method == SieveMethod::Copy => ("Copy", sieve_io.copy(source, &mut target)),
+        _ => ("Move", sieve_io.r#move(source, &mut target)),
+    };
+    let event = match result {
+        Ok(_) => {
+            if sieve_method == SieveMethod::Copy {
+                statistics.copied += 1;
             } else {
-                directories.push(format!(
-                    "{} {}",
-                    event.start_date.format("%m-%d"),
-                    event.name
-                ));
-            }
-        } else if event.start_date != event.end_date {
-            directories.push(format!(
-                "{} - {} {}",
-                event.start_date.format("%Y-%m-%d"),
-                event.end_date.format("%Y-%m-%d"),
-                event.name
-            ));
-        } else {
-            directories.push(format!(
-                "{} {}",
-                event.start_date.format("%Y-%m-%d"),
-                event.name
-            ));
+                statistics.moved += 1;
+            }
+            carry_sidecars(item, &target, sieve_method, sieve_io, statistics, progress_callback);
+            if let Some(offset) = embedded_video
+                && extract_video(item, motion_photos)
+            {
+                save_video(offset, &target, sieve_io, statistics, progress_callback);
+            }
+            write_metadata(item_list, item, &target, sieve_io, statistics, progress_callback);
+            if target != original_target {
+                statistics.renamed += 1;
+                SieveEvent::Renamed {
+                    source: source.clone(),
+                    target: target.clone(),
+                }
+            } else if sieve_method == SieveMethod::Copy {
+                SieveEvent::Copied {
+                    source: source.clone(),
+                    target: target.clone(),
+                }
+            } else {
+                SieveEvent::Moved {
+                    source: source.clone(),
+                    target: target.clone(),
+                }
+            }
         }
-    } else {
-        let format = match directory_names {
-            DirectoryNames::YearAndMonth => Format::YearAndMonth,
-            DirectoryNames::Year => Format::Year,
-            DirectoryNames::YearMonthAndDay => Format::Date,
-            DirectoryNames::YearAndQuarter => Format::YearAndQuarter,
-            DirectoryNames::YearAndMonthInSubdirectory => Format::Year,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            statistics.skipped += 1;
+            SieveEvent::Skipped {
+                source: source.clone(),
+                duplicate: target.clone(),
+            }
+        }
+        Err(e) => {
+            statistics.failed += 1;
+            SieveEvent::failed(operation, source, &e)
+        }
+    };
+    progress_callback(event);
+    target
+}
+
+/// Checks if the video embedded in a motion photo is saved as separate file
+fn extract_video(item: &FileItem, motion_photos: MotionPhotos) -> bool {
+    motion_photos == MotionPhotos::ExtractVideo && item.get_metadata().embedded_video.is_some()
+}
+
+/// Checks if an item is a companion which is sieved together with its primary item
+fn is_companion(item_list: &ItemList, index: usize) -> bool {
+    item_list.items[index]
+        .get_primary()
+        .is_some_and(|primary| item_list.items[primary].get_take_over())
+}
+
+/// Gets the target paths of all items, items which are not taken over get an empty path. Items are numbered in the
+/// order of their timestamps and paths, so that the sequence number of the path template makes every target unique.
+/// Companions of items which are taken over get no target either, they are put next to the sieved primary item.
+fn get_targets(item_list: &ItemList, path: &Path, path_template: &PathTemplate) -> Vec<PathBuf> {
+    let mut order: Vec<usize> = (0..item_list.items.len())
+        .filter(|index| item_list.items[*index].get_take_over() && !is_companion(item_list, *index))
+        .collect();
+    order.sort_by(|a, b| {
+        let (a, b) = (&item_list.items[*a], &item_list.items[*b]);
+        a.get_capture_time()
+            .cmp(&b.get_capture_time())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+
+    let mut targets = vec![PathBuf::new(); item_list.items.len()];
+    let mut used_targets = HashSet::new();
+    // Items with the same target for the first sequence number continue with the next number that was not tried yet,
+    // so that the lower numbers are not rendered again for every item
+    let mut next_sequences: HashMap<PathBuf, usize> = HashMap::new();
+    for index in order {
+        let item = &item_list.items[index];
+        let event = item_list.get_event(item);
+        let first_target = path.join(path_template.render(item, event, 1));
+        let next_sequence = next_sequences.entry(first_target.clone()).or_insert(1);
+        let mut sequence = *next_sequence;
+        let mut target = if sequence == 1 {
+            first_target
+        } else {
+            path.join(path_template.render(item, event, sequence))
         };
-        directories.push(timestamp_to_string(item.get_timestamp(), format));
-        if *directory_names == DirectoryNames::YearAndMonthInSubdirectory {
-            directories.push(timestamp_to_string(item.get_timestamp(), Format::Month))
+        while used_targets.contains(&target) {
+            let next_target = path.join(path_template.render(item, event, sequence + 1));
+            if next_target == target {
+                // The sequence number is not part of this target, the collision is resolved by the sieve IO
+                break;
+            }
+            sequence += 1;
+            target = next_target;
         }
+        *next_sequence = sequence + 1;
+        used_targets.insert(target.clone());
+        targets[index] = target;
     }
+    targets
+}
 
-    directories
+/// Writes the corrected capture time and the orientation to the metadata of a sieved image if enabled
+fn write_metadata<T>(
+    item_list: &ItemList,
+    item: &FileItem,
+    target: &Path,
+    sieve_io: &T,
+    statistics: &mut SieveStatistics,
+    progress_callback: &impl Fn(SieveEvent),
+) where
+    T: SieveIO,
+{
+    if !item_list.write_metadata {
+        return;
+    }
+    let update = MetadataUpdate::from_item(item);
+    if !update.is_empty()
+        && let Err(e) = sieve_io.write_metadata(target, &update)
+    {
+        statistics.failed += 1;
+        progress_callback(SieveEvent::failed("Write metadata", target, &e));
+    }
 }
 
-/// Prepares the path by creating it if it does not exist
-fn prepare_path<T>(path: &Path, sieve_io: &T)
+/// Deletes a file and reports the result
+fn delete<T>(
+    path: &Path,
+    sieve_io: &T,
+    statistics: &mut SieveStatistics,
+    progress_callback: &impl Fn(SieveEvent),
+) -> bool
 where
     T: SieveIO,
 {
-    if !path.exists() {
-        match sieve_io.create_dir_all(path) {
-            Ok(_) => (),
-            Err(e) => println!("Error creating path {}: {}", e, path.display()),
+    match sieve_io.remove_file(path) {
+        Ok(_) => {
+            statistics.deleted += 1;
+            progress_callback(SieveEvent::Deleted {
+                path: path.to_path_buf(),
+            });
+            true
+        }
+        Err(e) => {
+            statistics.failed += 1;
+            progress_callback(SieveEvent::failed("Delete", path, &e));
+            false
+        }
+    }
+}
+
+/// Saves the video embedded in a sieved motion photo as separate file next to the image
+fn save_video<T>(
+    offset: u64,
+    target: &Path,
+    sieve_io: &T,
+    statistics: &mut SieveStatistics,
+    progress_callback: &impl Fn(SieveEvent),
+) where
+    T: SieveIO,
+{
+    let mut video_target = target.with_extension("mp4");
+    let event = match sieve_io.extract(target, offset..u64::MAX, &mut video_target) {
+        Ok(_) => {
+            statistics.copied += 1;
+            SieveEvent::Copied {
+                source: target.to_path_buf(),
+                target: video_target,
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            statistics.skipped += 1;
+            SieveEvent::Skipped {
+                source: target.to_path_buf(),
+                duplicate: video_target,
+            }
+        }
+        Err(e) => {
+            statistics.failed += 1;
+            SieveEvent::failed("Extract", target, &e)
+        }
+    };
+    progress_callback(event);
+}
+
+/// Deletes an item and, if this succeeded, its sidecar files
+fn delete_item<T>(
+    item: &FileItem,
+    sieve_io: &T,
+    statistics: &mut SieveStatistics,
+    progress_callback: &impl Fn(SieveEvent),
+) where
+    T: SieveIO,
+{
+    if delete(&item.path, sieve_io, statistics, progress_callback) {
+        for sidecar in item.get_sidecars() {
+            delete(sidecar, sieve_io, statistics, progress_callback);
         }
     }
 }
 
+/// Copies or moves the sidecar files of an item next to the target of the item
+fn carry_sidecars<T>(
+    item: &FileItem,
+    target: &Path,
+    sieve_method: SieveMethod,
+    sieve_io: &T,
+    statistics: &mut SieveStatistics,
+    progress_callback: &impl Fn(SieveEvent),
+) where
+    T: SieveIO,
+{
+    for sidecar in item.get_sidecars() {
+        let mut sidecar_target = sidecar_target(sidecar, &item.path, target);
+        let event = match sieve_io.carry_sidecar(sidecar, &mut sidecar_target, sieve_method) {
+            Ok(_) if sieve_method == SieveMethod::Copy => {
+                statistics.copied += 1;
+                SieveEvent::Copied {
+                    source: sidecar.clone(),
+                    target: sidecar_target,
+                }
+            }
+            Ok(_) => {
+                statistics.moved += 1;
+                SieveEvent::Moved {
+                    source: sidecar.clone(),
+                    target: sidecar_target,
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                statistics.skipped += 1;
+                SieveEvent::Skipped {
+                    source: sidecar.clone(),
+                    duplicate: sidecar_target,
+                }
+            }
+            Err(e) => {
+                statistics.failed += 1;
+                let operation = if sieve_method == SieveMethod::Copy {
+                    "Copy"
+                } else {
+                    "Move"
+                };
+                SieveEvent::failed(operation, sidecar, &e)
+            }
+        };
+        progress_callback(event);
+    }
+}
+
+/// Prepares the path by creating it if it does not exist
+fn prepare_path<T>(
+    path: &Path,
+    sieve_io: &T,
+    statistics: &mut SieveStatistics,
+    progress_callback: &impl Fn(SieveEvent),
+) where
+    T: SieveIO,
+{
+    if !path.exists()
+        && let Err(e) = sieve_io.create_dir_all(path)
+    {
+        statistics.failed += 1;
+        progress_callback(SieveEvent::failed("Create directory", path, &e));
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::item_sort_list::sieve::SieveIO;
-    use crate::item_sort_list::{sieve::get_sub_path, Event, FileItem, ItemList};
+    use crate::item_sort_list::{DirectoryNames, Event, FileItem, ItemList, Metadata};
     use num_traits::FromPrimitive;
     use std::cell::RefCell;
     use std::path::PathBuf;
 
+    fn year_and_month() -> PathTemplate {
+        PathTemplate::new(&DirectoryNames::YearAndMonth, "").unwrap()
+    }
+
     struct TestSieveIO {
         pub copies: RefCell<Vec<(PathBuf, PathBuf)>>,
         pub renames: RefCell<Vec<(PathBuf, PathBuf)>>,
         pub removes: RefCell<Vec<PathBuf>>,
         pub creates: RefCell<Vec<PathBuf>>,
+        pub metadata: RefCell<Vec<(PathBuf, MetadataUpdate)>>,
+        pub extracts: RefCell<Vec<(PathBuf, Range<u64>, PathBuf)>>,
     }
 
     impl TestSieveIO {
@@ -250,6 +1039,8 @@ mod test {
                 renames: RefCell::new(vec![]),
                 removes: RefCell::new(vec![]),
                 creates: RefCell::new(vec![]),
+                metadata: RefCell::new(vec![]),
+                extracts: RefCell::new(vec![]),
             }
         }
 
@@ -258,6 +1049,8 @@ mod test {
             self.renames.get_mut().clear();
             self.removes.get_mut().clear();
             self.creates.get_mut().clear();
+            self.metadata.get_mut().clear();
+            self.extracts.get_mut().clear();
         }
     }
 
@@ -285,10 +1078,41 @@ mod test {
             self.creates.borrow_mut().push(path.to_path_buf());
             Ok(())
         }
+
+        fn write_metadata(
+            &self,
+            path: &Path,
+            update: &MetadataUpdate,
+        ) -> Result<Option<PathBuf>, Error> {
+            self.metadata
+                .borrow_mut()
+                .push((path.to_path_buf(), update.clone()));
+            Ok(None)
+        }
+
+        fn extract(&self, src: &Path, range: Range<u64>, dest: &mut PathBuf) -> Result<(), Error> {
+            self.extracts
+                .borrow_mut()
+                .push((src.to_path_buf(), range, dest.to_path_buf()));
+            Ok(())
+        }
+
+        fn carry_sidecar(
+            &self,
+            src: &Path,
+            dest: &mut PathBuf,
+            sieve_method: SieveMethod,
+        ) -> Result<(), Error> {
+            if sieve_method == SieveMethod::Copy {
+                self.copy(src, dest)
+            } else {
+                self.r#move(src, dest)
+            }
+        }
     }
 
     #[test]
-    fn test_get_sub_path() {
+    fn test_builtin_templates() {
         use chrono::NaiveDate;
         use chrono::NaiveDateTime;
 
@@ -312,6 +1136,8 @@ mod test {
                 },
             ],
             path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
         };
         let test_cases = [
             (
@@ -374,19 +1200,24 @@ mod test {
 
         for (input, results) in test_cases {
             for (i, result) in results.into_iter().enumerate() {
-                let sub_path: String = get_sub_path(
-                    &item_list,
-                    &FileItem::dummy(
-                        "test.jpg",
-                        NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M")
-                            .unwrap()
-                            .and_utc()
-                            .timestamp(),
-                        false,
-                    ),
-                    &FromPrimitive::from_usize(i).unwrap(),
-                )
-                .join("");
+                let item = FileItem::dummy(
+                    "test.jpg",
+                    NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M")
+                        .unwrap()
+                        .and_utc()
+                        .timestamp(),
+                    false,
+                );
+                let path_template =
+                    PathTemplate::new(&FromPrimitive::from_usize(i).unwrap(), "").unwrap();
+                let target = path_template.render(&item, item_list.get_event(&item), 1);
+                assert_eq!(target.file_name().unwrap(), "test.jpg");
+                let sub_path: String = target
+                    .parent()
+                    .unwrap()
+                    .iter()
+                    .map(|directory| directory.to_string_lossy())
+                    .collect();
                 assert_eq!(sub_path, result);
             }
         }
@@ -401,6 +1232,8 @@ mod test {
             ],
             events: vec![],
             path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
         };
         let mut sieve_io = TestSieveIO::new();
 
@@ -408,9 +1241,10 @@ mod test {
             &item_list,
             Path::new("target"),
             SieveMethod::Delete,
-            DirectoryNames::YearAndMonth,
+            &year_and_month(),
+            MotionPhotos::Keep,
             &sieve_io,
-            |_: String| {},
+            |_| {},
         );
         assert_eq!(sieve_io.copies.borrow().len(), 0);
         assert_eq!(sieve_io.creates.borrow().len(), 0);
@@ -426,9 +1260,10 @@ mod test {
             &item_list,
             Path::new("target"),
             SieveMethod::Copy,
-            DirectoryNames::YearAndMonth,
+            &year_and_month(),
+            MotionPhotos::Keep,
             &sieve_io,
-            |_: String| {},
+            |_| {},
         );
         assert_eq!(sieve_io.copies.borrow().len(), 1);
         assert_eq!(
@@ -452,9 +1287,10 @@ mod test {
             &item_list,
             Path::new("target"),
             SieveMethod::Move,
-            DirectoryNames::YearAndMonth,
+            &year_and_month(),
+            MotionPhotos::Keep,
             &sieve_io,
-            |_: String| {},
+            |_| {},
         );
         assert_eq!(sieve_io.copies.borrow().len(), 0);
         assert_eq!(sieve_io.creates.borrow().len(), 1);
@@ -478,9 +1314,10 @@ mod test {
             &item_list,
             Path::new("target"),
             SieveMethod::MoveAndDelete,
-            DirectoryNames::YearAndMonth,
+            &year_and_month(),
+            MotionPhotos::Keep,
             &sieve_io,
-            |_: String| {},
+            |_| {},
         );
         assert_eq!(sieve_io.copies.borrow().len(), 0);
         assert_eq!(sieve_io.creates.borrow().len(), 1);
@@ -517,16 +1354,19 @@ mod test {
             ],
             events: vec![],
             path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
         };
-        let file_io = FileSieveIO {};
+        let file_io = FileSieveIO::default();
 
         sieve(
             &item_list,
             Path::new("tests/target"),
             SieveMethod::Copy,
-            DirectoryNames::YearAndMonth,
+            &year_and_month(),
+            MotionPhotos::Keep,
             &file_io,
-            |_: String| {},
+            |_| {},
         );
 
         assert!(Path::new("tests/target/1970-01/test.jpg").exists());
@@ -536,4 +1376,715 @@ mod test {
         assert!(Path::new("tests/target/1970-01/test3.jpg").exists());
         assert!(Path::new("tests/target/1970-01/test3_.jpg").exists());
     }
+
+    #[test]
+    fn test_different() {
+        let test = Path::new("tests/test.jpg");
+        assert!(!different(test, Path::new("tests/subdir/test.jpg")).unwrap());
+        assert!(different(test, Path::new("tests/test_invalid_date.jpg")).unwrap());
+        assert!(different(test, Path::new("tests/test2.JPG")).unwrap());
+        assert!(different(test, Path::new("tests/missing.jpg")).is_err());
+        let test3 = Path::new("tests/test3.jpg");
+        assert!(different(test3, Path::new("tests/subdir/test3.jpg")).unwrap());
+    }
+
+    #[test]
+    fn test_copy_verified() {
+        let target = Path::new("tests/verified_target");
+        std::fs::remove_dir_all(target).ok();
+        create_dir_all(target).unwrap();
+        let test = Path::new("tests/test.jpg");
+        let copied = copy_verified(test, &target.join("test.jpg"));
+        let copy_different = different(test, &target.join("test.jpg"));
+        let missing = Path::new("tests/missing.jpg");
+        let missing_copied = copy_verified(missing, &target.join("missing.jpg"));
+        std::fs::remove_dir_all(target).ok();
+
+        assert!(copied.is_ok());
+        assert!(!copy_different.unwrap());
+        assert!(missing_copied.is_err());
+    }
+
+    #[test]
+    fn test_archive_index() {
+        let target = Path::new("tests/archive_target");
+        std::fs::remove_dir_all(target).ok();
+        create_dir_all(target.join("2001")).unwrap();
+        copy("tests/test.jpg", target.join("2001/archived.jpg")).unwrap();
+
+        let item_list = ItemList {
+            items: vec![
+                FileItem::dummy("tests/test.jpg", 0, true),
+                FileItem::dummy("tests/test2.JPG", 0, true),
+                FileItem::dummy("tests/subdir/test2.JPG", 0, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        let mut archive_index = ArchiveIndex::default();
+        archive_index.update(target);
+
+        let dry_run_io = DryRunSieveIO::with_archive_index(archive_index.clone());
+        sieve(
+            &item_list,
+            target,
+            SieveMethod::Copy,
+            &year_and_month(),
+            MotionPhotos::Keep,
+            &dry_run_io,
+            |_| {},
+        );
+        let plan = dry_run_io.into_plan();
+        let planned_directory_exists = target.join("1970-01").exists();
+
+        let archive_index = RefCell::new(archive_index);
+        let file_io = FileSieveIO {
+            archive_index: Some(&archive_index),
+        };
+        let events = RefCell::new(vec![]);
+        sieve(
+            &item_list,
+            target,
+            SieveMethod::Copy,
+            &year_and_month(),
+            MotionPhotos::Keep,
+            &file_io,
+            |event| events.borrow_mut().push(event),
+        );
+        let sieved = (
+            target.join("1970-01/test.jpg").exists(),
+            target.join("1970-01/test2.JPG").exists(),
+        );
+        std::fs::remove_dir_all(target).ok();
+
+        assert_eq!(
+            plan.operations[1],
+            PlannedOperation::Skip {
+                source: PathBuf::from("tests/test.jpg"),
+                existing: target.join("2001/archived.jpg")
+            }
+        );
+        assert!(!planned_directory_exists);
+        assert_eq!(
+            events.borrow()[1],
+            SieveEvent::Skipped {
+                source: PathBuf::from("tests/test.jpg"),
+                duplicate: target.join("2001/archived.jpg")
+            }
+        );
+        assert_eq!(sieved, (false, true));
+        assert_eq!(archive_index.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_write_metadata() {
+        let mut item_list = ItemList {
+            items: vec![
+                FileItem::dummy("test/test1.jpg", 0, true),
+                FileItem::dummy("test/test2.jpg", 0, true),
+                FileItem::dummy("test/test3.mp4", 0, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        item_list.items[0].set_clock_offset(60);
+        item_list.items[1].set_metadata(Metadata {
+            irregular_orientation: true,
+            ..Metadata::default()
+        });
+        item_list.items[2].set_clock_offset(60);
+        let mut sieve_io = TestSieveIO::new();
+
+        sieve(
+            &item_list,
+            Path::new("target"),
+            SieveMethod::Copy,
+            &year_and_month(),
+            MotionPhotos::Keep,
+            &sieve_io,
+            |_| {},
+        );
+        assert!(sieve_io.metadata.borrow().is_empty());
+
+        // Videos are not modified
+        item_list.write_metadata = true;
+        sieve_io.reset();
+        sieve(
+            &item_list,
+            Path::new("target"),
+            SieveMethod::Move,
+            &year_and_month(),
+            MotionPhotos::Keep,
+            &sieve_io,
+            |_| {},
+        );
+        let metadata = sieve_io.metadata.borrow();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata[0].0, PathBuf::from("target/1970-01/test1.jpg"));
+        assert_eq!(
+            metadata[0].1.date_time,
+            chrono::DateTime::from_timestamp(60, 0).map(|date_time| date_time.naive_utc())
+        );
+        assert_eq!(metadata[0].1.orientation, None);
+        assert_eq!(metadata[1].0, PathBuf::from("target/1970-01/test2.jpg"));
+        assert_eq!(metadata[1].1.date_time, None);
+        assert_eq!(
+            metadata[1].1.orientation,
+            Some(crate::item_sort_list::Orientation::Landscape)
+        );
+    }
+
+    #[test]
+    fn test_companions() {
+        let mut item_list = ItemList {
+            items: vec![
+                FileItem::dummy("dir/DSC_0001.JPG", 0, true),
+                FileItem::dummy("dir/DSC_0001.NEF", 0, true),
+                FileItem::dummy("dir/a.jpg", 0, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        item_list.find_companions();
+
+        let path_template = year_and_month()
+            .with_rename_pattern("{date}_{seq}")
+            .unwrap();
+        assert_eq!(
+            get_targets(&item_list, Path::new("target"), &path_template),
+            vec![
+                PathBuf::from("target/1970-01/1970-01-01_1.JPG"),
+                PathBuf::new(),
+                PathBuf::from("target/1970-01/1970-01-01_2.jpg"),
+            ]
+        );
+
+        let sieve_io = TestSieveIO::new();
+        sieve(
+            &item_list,
+            Path::new("target"),
+            SieveMethod::Copy,
+            &path_template,
+            MotionPhotos::Keep,
+            &sieve_io,
+            |_| {},
+        );
+        assert_eq!(
+            sieve_io.copies.borrow()[1],
+            (
+                PathBuf::from("dir/DSC_0001.NEF"),
+                PathBuf::from("target/1970-01/1970-01-01_1.NEF")
+            )
+        );
+    }
+
+    #[test]
+    fn test_renamed_companions() {
+        let source = Path::new("tests/test_renamed_companions");
+        let target = Path::new("tests/test_renamed_companions_target");
+        std::fs::remove_dir_all(source).ok();
+        std::fs::remove_dir_all(target).ok();
+        std::fs::create_dir_all(source).unwrap();
+        std::fs::create_dir_all(target.join("1970-01")).unwrap();
+        std::fs::copy("tests/test.jpg", source.join("DSC_0001.JPG")).unwrap();
+        std::fs::write(source.join("DSC_0001.NEF"), b"raw").unwrap();
+        std::fs::write(target.join("1970-01/DSC_0001.JPG"), b"other").unwrap();
+        let mut item_list = ItemList {
+            items: vec![
+                FileItem::dummy("tests/test_renamed_companions/DSC_0001.JPG", 0, true),
+                FileItem::dummy("tests/test_renamed_companions/DSC_0001.NEF", 0, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        item_list.find_companions();
+
+        sieve(
+            &item_list,
+            target,
+            SieveMethod::Copy,
+            &year_and_month(),
+            MotionPhotos::Keep,
+            &FileSieveIO::default(),
+            |_| {},
+        );
+        // The companion follows the primary item renamed to avoid overwriting a different file
+        let renamed_image = target.join("1970-01/DSC_0001_.JPG").exists();
+        let renamed_raw = target.join("1970-01/DSC_0001_.NEF").exists();
+        let raw = target.join("1970-01/DSC_0001.NEF").exists();
+        std::fs::remove_dir_all(source).ok();
+        std::fs::remove_dir_all(target).ok();
+
+        assert!(renamed_image);
+        assert!(renamed_raw);
+        assert!(!raw);
+    }
+
+    #[test]
+    fn test_sidecars() {
+        let mut item_list = ItemList {
+            items: vec![
+                FileItem::dummy("dir/DSC_0001.JPG", 0, true),
+                FileItem::dummy("dir/DSC_0002.JPG", 0, false),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        item_list.items[0].set_sidecars(vec![
+            PathBuf::from("dir/DSC_0001.JPG.xmp"),
+            PathBuf::from("dir/DSC_0001.AAE"),
+        ]);
+        item_list.items[1].set_sidecars(vec![PathBuf::from("dir/DSC_0002.xmp")]);
+        let path_template = year_and_month()
+            .with_rename_pattern("{date}_{seq}")
+            .unwrap();
+
+        let mut sieve_io = TestSieveIO::new();
+        sieve(
+            &item_list,
+            Path::new("target"),
+            SieveMethod::Copy,
+            &path_template,
+            MotionPhotos::Keep,
+            &sieve_io,
+            |_| {},
+        );
+        assert_eq!(
+            *sieve_io.copies.borrow(),
+            vec![
+                (
+                    PathBuf::from("dir/DSC_0001.JPG"),
+                    PathBuf::from("target/1970-01/1970-01-01_1.JPG")
+                ),
+                (
+                    PathBuf::from("dir/DSC_0001.JPG.xmp"),
+                    PathBuf::from("target/1970-01/1970-01-01_1.JPG.xmp")
+                ),
+                (
+                    PathBuf::from("dir/DSC_0001.AAE"),
+                    PathBuf::from("target/1970-01/1970-01-01_1.AAE")
+                ),
+            ]
+        );
+
+        sieve_io.reset();
+        sieve(
+            &item_list,
+            Path::new("target"),
+            SieveMethod::Delete,
+            &path_template,
+            MotionPhotos::Keep,
+            &sieve_io,
+            |_| {},
+        );
+        assert_eq!(
+            *sieve_io.removes.borrow(),
+            vec![
+                PathBuf::from("dir/DSC_0002.JPG"),
+                PathBuf::from("dir/DSC_0002.xmp")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identical_sidecars() {
+        let source = Path::new("tests/test_identical_sidecars");
+        let target = Path::new("tests/test_identical_sidecars_target");
+        std::fs::remove_dir_all(source).ok();
+        std::fs::remove_dir_all(target).ok();
+        create_dir_all(source).unwrap();
+        copy("tests/test.jpg", source.join("a.jpg")).unwrap();
+        copy("tests/test2.JPG", source.join("b.jpg")).unwrap();
+        std::fs::write(source.join("a.xmp"), b"<x:xmpmeta/>").unwrap();
+        std::fs::write(source.join("b.xmp"), b"<x:xmpmeta/>").unwrap();
+        let mut item_list = ItemList {
+            items: vec![
+                FileItem::dummy("tests/test_identical_sidecars/a.jpg", 0, true),
+                FileItem::dummy("tests/test_identical_sidecars/b.jpg", 0, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        item_list.items[0].set_sidecars(vec![source.join("a.xmp")]);
+        item_list.items[1].set_sidecars(vec![source.join("b.xmp")]);
+        let archive_index = RefCell::new(ArchiveIndex::default());
+        let file_io = FileSieveIO {
+            archive_index: Some(&archive_index),
+        };
+        let statistics = RefCell::new(SieveStatistics::default());
+
+        sieve(
+            &item_list,
+            target,
+            SieveMethod::Copy,
+            &year_and_month(),
+            MotionPhotos::Keep,
+            &file_io,
+            |event| {
+                if let SieveEvent::Finished { statistics: result } = event {
+                    *statistics.borrow_mut() = result;
+                }
+            },
+        );
+        // Identical sidecar files of different items are both copied
+        let sidecars = [
+            target.join("1970-01/a.xmp").exists(),
+            target.join("1970-01/b.xmp").exists(),
+        ];
+        std::fs::remove_dir_all(source).ok();
+        std::fs::remove_dir_all(target).ok();
+
+        assert_eq!(sidecars, [true, true]);
+        assert_eq!(statistics.borrow().copied, 4);
+        assert_eq!(statistics.borrow().failed, 0);
+        assert_eq!(archive_index.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_motion_photos() {
+        let mut item_list = ItemList {
+            items: vec![
+                FileItem::dummy("dir/IMG_0002.HEIC", 0, true),
+                FileItem::dummy("dir/IMG_0002.MOV", 0, true),
+                FileItem::dummy("dir/PXL_0001.MP.jpg", 0, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        item_list.items[2].set_metadata(Metadata {
+            embedded_video: Some(1000),
+            ..Metadata::default()
+        });
+        item_list.find_companions();
+        let sieve_with = |sieve_method: SieveMethod, motion_photos: MotionPhotos| {
+            let sieve_io = TestSieveIO::new();
+            sieve(
+                &item_list,
+                Path::new("target"),
+                sieve_method,
+                &year_and_month(),
+                motion_photos,
+                &sieve_io,
+                |_| {},
+            );
+            sieve_io
+        };
+
+        let sieve_io = sieve_with(SieveMethod::Copy, MotionPhotos::ExtractVideo);
+        assert_eq!(sieve_io.copies.borrow().len(), 3);
+        assert_eq!(
+            *sieve_io.extracts.borrow(),
+            vec![(
+                PathBuf::from("target/1970-01/PXL_0001.MP.jpg"),
+                1000..u64::MAX,
+                PathBuf::from("target/1970-01/PXL_0001.MP.mp4")
+            )]
+        );
+
+        let sieve_io = sieve_with(SieveMethod::Move, MotionPhotos::StillOnly);
+        assert_eq!(
+            *sieve_io.renames.borrow(),
+            vec![(
+                PathBuf::from("dir/IMG_0002.HEIC"),
+                PathBuf::from("target/1970-01/IMG_0002.HEIC")
+            )]
+        );
+        assert_eq!(
+            *sieve_io.extracts.borrow(),
+            vec![(
+                PathBuf::from("dir/PXL_0001.MP.jpg"),
+                0..1000,
+                PathBuf::from("target/1970-01/PXL_0001.MP.jpg")
+            )]
+        );
+        assert_eq!(
+            *sieve_io.removes.borrow(),
+            vec![PathBuf::from("dir/PXL_0001.MP.jpg")]
+        );
+
+        let sieve_io = sieve_with(SieveMethod::MoveAndDelete, MotionPhotos::StillOnly);
+        assert_eq!(
+            *sieve_io.removes.borrow(),
+            vec![
+                PathBuf::from("dir/IMG_0002.MOV"),
+                PathBuf::from("dir/PXL_0001.MP.jpg")
+            ]
+        );
+
+        // Only the videos of live photos are dropped
+        item_list.items[0].set_live_photo(false);
+        let sieve_io = TestSieveIO::new();
+        sieve(
+            &item_list,
+            Path::new("target"),
+            SieveMethod::Copy,
+            &year_and_month(),
+            MotionPhotos::StillOnly,
+            &sieve_io,
+            |_| {},
+        );
+        assert!(sieve_io
+            .copies
+            .borrow()
+            .iter()
+            .any(|(source, _)| source == Path::new("dir/IMG_0002.MOV")));
+    }
+
+    #[test]
+    fn test_rename() {
+        let item_list = ItemList {
+            items: vec![
+                FileItem::dummy("tests/test3.jpg", 0, true),
+                FileItem::dummy("tests/test.jpg", 0, true),
+                FileItem::dummy("tests/test2.JPG", 86400, true),
+                FileItem::dummy("tests/test_no_exif.jpg", 0, false),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        let dry_run_io = DryRunSieveIO::default();
+
+        sieve(
+            &item_list,
+            Path::new("tests/rename_target"),
+            SieveMethod::Copy,
+            &year_and_month()
+                .with_rename_pattern("{date}_{seq}")
+                .unwrap(),
+            MotionPhotos::Keep,
+            &dry_run_io,
+            |_| {},
+        );
+
+        let targets: Vec<PathBuf> = dry_run_io
+            .into_plan()
+            .operations
+            .into_iter()
+            .filter_map(|operation| match operation {
+                PlannedOperation::Copy { target, .. } => Some(target),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                PathBuf::from("tests/rename_target/1970-01/1970-01-01_2.jpg"),
+                PathBuf::from("tests/rename_target/1970-01/1970-01-01_1.jpg"),
+                PathBuf::from("tests/rename_target/1970-01/1970-01-02_1.JPG"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let item_list = ItemList {
+            items: vec![
+                FileItem::dummy("tests/test.jpg", 0, true),
+                FileItem::dummy("tests/test2.JPG", 0, true),
+                FileItem::dummy("tests/subdir/test.jpg", 0, true),
+                FileItem::dummy("tests/subdir/test2.JPG", 0, true),
+                FileItem::dummy("tests/test3.jpg", 0, false),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        let dry_run_io = DryRunSieveIO::default();
+        let events = RefCell::new(vec![]);
+
+        sieve(
+            &item_list,
+            Path::new("tests/dry_run_target"),
+            SieveMethod::MoveAndDelete,
+            &year_and_month(),
+            MotionPhotos::Keep,
+            &dry_run_io,
+            |event| events.borrow_mut().push(event),
+        );
+
+        let plan = dry_run_io.into_plan();
+        assert_eq!(
+            plan.operations,
+            vec![
+                PlannedOperation::CreateDirectory {
+                    path: PathBuf::from("tests/dry_run_target")
+                },
+                PlannedOperation::CreateDirectory {
+                    path: PathBuf::from("tests/dry_run_target/1970-01")
+                },
+                PlannedOperation::Move {
+                    source: PathBuf::from("tests/test.jpg"),
+                    target: PathBuf::from("tests/dry_run_target/1970-01/test.jpg"),
+                    renamed: false
+                },
+                PlannedOperation::Move {
+                    source: PathBuf::from("tests/test2.JPG"),
+                    target: PathBuf::from("tests/dry_run_target/1970-01/test2.JPG"),
+                    renamed: false
+                },
+                PlannedOperation::Skip {
+                    source: PathBuf::from("tests/subdir/test.jpg"),
+                    existing: PathBuf::from("tests/dry_run_target/1970-01/test.jpg")
+                },
+                PlannedOperation::Move {
+                    source: PathBuf::from("tests/subdir/test2.JPG"),
+                    target: PathBuf::from("tests/dry_run_target/1970-01/test2_.JPG"),
+                    renamed: true
+                },
+                PlannedOperation::Delete {
+                    path: PathBuf::from("tests/test3.jpg")
+                },
+            ]
+        );
+        let events = events.into_inner();
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[0], SieveEvent::Started { total: 5 });
+        assert_eq!(
+            events[3],
+            SieveEvent::Skipped {
+                source: PathBuf::from("tests/subdir/test.jpg"),
+                duplicate: PathBuf::from("tests/dry_run_target/1970-01/test.jpg")
+            }
+        );
+        assert_eq!(
+            events[4],
+            SieveEvent::Renamed {
+                source: PathBuf::from("tests/subdir/test2.JPG"),
+                target: PathBuf::from("tests/dry_run_target/1970-01/test2_.JPG")
+            }
+        );
+        assert_eq!(
+            events[6],
+            SieveEvent::Finished {
+                statistics: SieveStatistics {
+                    copied: 0,
+                    moved: 3,
+                    renamed: 1,
+                    skipped: 1,
+                    deleted: 1,
+                    failed: 0
+                }
+            }
+        );
+        assert!(!Path::new("tests/dry_run_target").exists());
+        assert!(Path::new("tests/test.jpg").exists());
+        assert!(Path::new("tests/test3.jpg").exists());
+    }
+
+    #[test]
+    fn test_dry_run_extract() {
+        let dir = std::env::temp_dir().join("image_sieve_test_dry_run_extract");
+        let archive = dir.join("archive");
+        std::fs::create_dir_all(archive.join("2001")).unwrap();
+        std::fs::copy("tests/test.mp4", archive.join("2001/video.mp4")).unwrap();
+        let image = std::fs::read("tests/test.jpg").unwrap();
+        let mut data = image.clone();
+        data.extend(std::fs::read("tests/test.mp4").unwrap());
+        let motion_photo = dir.join("PXL_0001.MP.jpg");
+        std::fs::write(&motion_photo, data).unwrap();
+        let mut item_list = ItemList {
+            items: vec![FileItem::dummy(motion_photo.to_str().unwrap(), 0, true)],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        };
+        item_list.items[0].set_metadata(Metadata {
+            embedded_video: Some(image.len() as u64),
+            ..Metadata::default()
+        });
+        let mut archive_index = ArchiveIndex::default();
+        archive_index.update(&archive);
+        let dry_run_io = DryRunSieveIO::with_archive_index(archive_index);
+
+        sieve(
+            &item_list,
+            &archive,
+            SieveMethod::Copy,
+            &year_and_month(),
+            MotionPhotos::ExtractVideo,
+            &dry_run_io,
+            |_| {},
+        );
+        let plan = dry_run_io.into_plan();
+        std::fs::remove_dir_all(&dir).ok();
+
+        // The embedded video is already in the archive
+        assert!(plan.operations.contains(&PlannedOperation::Skip {
+            source: motion_photo,
+            existing: archive.join("2001/video.mp4")
+        }));
+        assert!(!plan
+            .operations
+            .iter()
+            .any(|operation| matches!(operation, PlannedOperation::Extract { .. })));
+    }
+
+    #[test]
+    fn test_dry_run_metadata() {
+        let mut item_list = ItemList {
+            items: vec![
+                FileItem::dummy("tests/test.jpg", 0, true),
+                FileItem::dummy("tests/test.png", 0, true),
+                FileItem::dummy("tests/test.nef", 0, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: true,
+        };
+        for item in &mut item_list.items {
+            item.set_clock_offset(60);
+        }
+        let dry_run_io = DryRunSieveIO::default();
+        let events = RefCell::new(vec![]);
+
+        sieve(
+            &item_list,
+            Path::new("tests/dry_run_metadata"),
+            SieveMethod::Copy,
+            &year_and_month(),
+            MotionPhotos::Keep,
+            &dry_run_io,
+            |event| events.borrow_mut().push(event),
+        );
+
+        let plan = dry_run_io.into_plan();
+        let target = Path::new("tests/dry_run_metadata/1970-01");
+        assert!(plan.operations.contains(&PlannedOperation::WriteMetadata {
+            path: target.join("test.jpg"),
+            sidecar: None
+        }));
+        assert!(plan.operations.contains(&PlannedOperation::WriteMetadata {
+            path: target.join("test.png"),
+            sidecar: Some(target.join("test.xmp"))
+        }));
+        // The sidecar file of the raw file would overwrite the one planned for the PNG file
+        assert!(!plan.operations.iter().any(|operation| matches!(
+            operation,
+            PlannedOperation::WriteMetadata { path, .. } if *path == target.join("test.nef")
+        )));
+        assert!(events.into_inner().iter().any(|event| matches!(
+            event,
+            SieveEvent::Failed { kind: ErrorKind::AlreadyExists, .. }
+        )));
+        assert!(!Path::new("tests/dry_run_metadata").exists());
+    }
 }
@@ -0,0 +1,132 @@
+use super::file_item::FileItem;
+
+/// Maximum similarity score of two items
+pub const MAX_SCORE: u32 = 100;
+
+/// Weight of the timestamp difference in the similarity score
+const TIMESTAMP_WEIGHT: u64 = 40;
+/// Weight of the hash distance in the similarity score
+const HASH_WEIGHT: u64 = 40;
+/// Weight of both items being taken with the same camera in the similarity score
+const CAMERA_WEIGHT: u64 = 10;
+/// Weight of both items having the same resolution in the similarity score
+const RESOLUTION_WEIGHT: u64 = 10;
+
+/// Configuration of the similarity scoring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimilarityConfig {
+    /// Maximum timestamp difference in seconds of similar items, None if timestamps are not compared
+    pub timestamp_max_diff: Option<i64>,
+    /// Hash distance of similar items needs to be below this value, None if hashes are not compared
+    pub hash_max_diff: Option<u32>,
+    /// Minimum score of two items to be similar
+    pub threshold: u32,
+}
+
+/// Similar item with the score of its similarity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Similar {
+    /// Index of the similar item in the list of file items
+    pub index: usize,
+    /// Similarity score between the threshold and the maximum score
+    pub score: u32,
+}
+
+impl SimilarityConfig {
+    /// Gets the similarity score of two items between 0 and the maximum score. The timestamp difference and the hash
+    /// distance contribute at least half of their weight if they are within their maximum and up to the full weight
+    /// if they are equal. Same camera and same resolution add their full weight. The score is relative to the sum
+    /// of the weights of the compared properties, so that it does not depend on which of them are enabled.
+    pub fn score(&self, item: &FileItem, other: &FileItem) -> u32 {
+        let mut score = 0;
+        let mut max_score = CAMERA_WEIGHT + RESOLUTION_WEIGHT;
+        if let Some(max_diff) = self.timestamp_max_diff {
+            max_score += TIMESTAMP_WEIGHT;
+            let diff = item
+                .get_capture_time()
+                .utc_millis()
+                .abs_diff(other.get_capture_time().utc_millis());
+            let range = (max_diff.max(0) as u64 + 1) * 1000;
+            score += partial_score(TIMESTAMP_WEIGHT, diff, range);
+        }
+        if let Some(max_diff) = self.hash_max_diff {
+            max_score += HASH_WEIGHT;
+            // Videos are only compared to videos, since their hash is calculated from several frames
+            if item.is_video() == other.is_video() {
+                let distance = item.get_hash_distance(other);
+                score += partial_score(HASH_WEIGHT, distance as u64, max_diff as u64);
+            }
+        }
+        let metadata = item.get_metadata();
+        let other_metadata = other.get_metadata();
+        if metadata.camera_model.is_some()
+            && metadata.camera_make == other_metadata.camera_make
+            && metadata.camera_model == other_metadata.camera_model
+        {
+            score += CAMERA_WEIGHT;
+        }
+        if metadata.resolution.is_some() && metadata.resolution == other_metadata.resolution {
+            score += RESOLUTION_WEIGHT;
+        }
+        (score * MAX_SCORE as u64 / max_score) as u32
+    }
+}
+
+/// Gets the part of a weight for a difference, which is 0 if the difference is not below the range and decreases
+/// linearly from the full weight to half of the weight otherwise
+fn partial_score(weight: u64, diff: u64, range: u64) -> u64 {
+    if diff < range {
+        weight * (2 * range - diff) / (2 * range)
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_sort_list::file_item::HashType;
+    use crate::item_sort_list::metadata::Metadata;
+
+    fn item(path: &str, timestamp: i64, hash: &[u8], camera_model: Option<&str>) -> FileItem {
+        let mut item = FileItem::dummy(path, timestamp, true);
+        item.set_encoded_hash(&HashType::from_bytes(hash).unwrap().to_base64());
+        item.set_metadata(Metadata {
+            camera_make: None,
+            camera_model: camera_model.map(String::from),
+            resolution: Some((4000, 3000)),
+            ..Metadata::default()
+        });
+        item
+    }
+
+    #[test]
+    fn test_score() {
+        let config = SimilarityConfig {
+            timestamp_max_diff: Some(5),
+            hash_max_diff: Some(4),
+            threshold: 30,
+        };
+        let first = item("test.jpg", 0, &[0, 0], Some("A"));
+        assert_eq!(config.score(&first, &first), MAX_SCORE);
+
+        // Timestamp at the maximum difference and no hash within the maximum distance
+        let second = item("test.jpg", 5, &[0xF, 0], Some("B"));
+        assert_eq!(config.score(&first, &second), 10 + 23);
+        // Timestamp too far away, but hash within the maximum distance and same camera
+        let third = item("test.jpg", 100, &[1, 0], Some("A"));
+        assert_eq!(config.score(&first, &third), 35 + 10 + 10);
+        // Videos are not compared to images
+        let video = item("test.mp4", 100, &[0, 0], Some("A"));
+        assert_eq!(config.score(&first, &video), 10 + 10);
+
+        // Only timestamps
+        let config = SimilarityConfig {
+            timestamp_max_diff: Some(5),
+            hash_max_diff: None,
+            threshold: 30,
+        };
+        assert_eq!(config.score(&first, &second), 55);
+        assert_eq!(config.score(&first, &third), 33);
+    }
+}
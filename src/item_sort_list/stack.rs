@@ -0,0 +1,163 @@
+use super::file_item::FileItem;
+
+/// Maximum time in milliseconds between two images of a burst sequence
+const MAX_BURST_GAP_MS: i64 = 1000;
+/// Maximum time in milliseconds between two images of an exposure bracket
+const MAX_BRACKET_GAP_MS: i64 = 2000;
+
+/// Kind of a sequence of images taken by the camera in one go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackKind {
+    /// Images taken in continuous shooting mode
+    Burst,
+    /// Images of the same scene taken with different exposures, for example for HDR
+    Bracket,
+}
+
+/// Sequence of images which is shown as one item and is kept or discarded together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stack {
+    /// Kind of the sequence
+    pub kind: StackKind,
+    /// Indices of the images in the list of file items in the order they were taken
+    pub items: Vec<usize>,
+}
+
+impl Stack {
+    /// Gets the index of the first image of the stack which represents the stack
+    pub fn head(&self) -> usize {
+        self.items[0]
+    }
+}
+
+/// Finds burst and bracket sequences in a list of file items. Images are part of the same sequence if they were
+/// taken with the same camera shortly after each other and either have sub second timestamps (burst) or were
+/// taken with a different exposure bias or in auto bracket mode (bracket). Companions of other items are not stacked,
+/// they follow their primary item.
+pub fn find_stacks(items: &[FileItem]) -> Vec<Stack> {
+    let mut indices: Vec<usize> = (0..items.len())
+        .filter(|index| !items[*index].is_video() && items[*index].get_primary().is_none())
+        .collect();
+    indices.sort_by_key(|index| items[*index].get_capture_time());
+
+    let mut stacks = vec![];
+    let mut current: Option<Stack> = None;
+    let mut previous: Option<usize> = None;
+    for index in indices {
+        let kind = previous.and_then(|previous| sequence_kind(&items[previous], &items[index]));
+        match (kind, current.as_mut()) {
+            (Some(kind), Some(stack)) => {
+                if kind == StackKind::Bracket {
+                    stack.kind = StackKind::Bracket;
+                }
+                stack.items.push(index);
+            }
+            (Some(kind), None) => {
+                current = Some(Stack {
+                    kind,
+                    items: vec![previous.unwrap(), index],
+                })
+            }
+            (None, _) => stacks.extend(current.take()),
+        }
+        previous = Some(index);
+    }
+    stacks.extend(current);
+    stacks
+}
+
+/// Checks if an item was taken in the same sequence as the item taken before it and gets the kind of the sequence
+fn sequence_kind(previous: &FileItem, item: &FileItem) -> Option<StackKind> {
+    let (previous_metadata, metadata) = (previous.get_metadata(), item.get_metadata());
+    if metadata.camera_model.is_none()
+        || metadata.camera_make != previous_metadata.camera_make
+        || metadata.camera_model != previous_metadata.camera_model
+    {
+        return None;
+    }
+    let gap = item.get_capture_time().utc_millis() - previous.get_capture_time().utc_millis();
+    let bracket = (metadata.auto_bracket && previous_metadata.auto_bracket)
+        || (metadata.exposure_bias.is_some()
+            && previous_metadata.exposure_bias.is_some()
+            && metadata.exposure_bias != previous_metadata.exposure_bias);
+    let burst = metadata.sub_second.is_some() && previous_metadata.sub_second.is_some();
+    if bracket && gap <= MAX_BRACKET_GAP_MS {
+        Some(StackKind::Bracket)
+    } else if burst && gap <= MAX_BURST_GAP_MS {
+        Some(StackKind::Burst)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_sort_list::Timestamp;
+    use crate::item_sort_list::metadata::Metadata;
+
+    fn item(
+        timestamp: i64,
+        sub_second: Option<u32>,
+        exposure_bias: Option<i32>,
+        camera_model: &str,
+    ) -> FileItem {
+        let mut item = FileItem::dummy("test.jpg", timestamp, true);
+        item.set_capture_time(Timestamp::new(
+            timestamp * 1000 + sub_second.unwrap_or(0) as i64,
+            None,
+        ));
+        item.set_metadata(Metadata {
+            camera_model: Some(String::from(camera_model)),
+            sub_second,
+            exposure_bias,
+            ..Metadata::default()
+        });
+        item
+    }
+
+    #[test]
+    fn test_find_stacks() {
+        let items = vec![
+            // Burst
+            item(10, Some(100), Some(0), "A"),
+            item(10, Some(400), Some(0), "A"),
+            item(11, Some(200), Some(0), "A"),
+            // Too late for a burst
+            item(13, Some(0), Some(0), "A"),
+            // Bracket
+            item(20, None, Some(-200), "A"),
+            item(21, None, Some(0), "A"),
+            item(22, None, Some(200), "A"),
+            // Different camera
+            item(22, None, Some(-200), "B"),
+            // No sub seconds
+            item(30, None, Some(0), "A"),
+            item(30, None, Some(0), "A"),
+        ];
+        let stacks = find_stacks(&items);
+        assert_eq!(
+            stacks,
+            vec![
+                Stack {
+                    kind: StackKind::Burst,
+                    items: vec![0, 1, 2]
+                },
+                Stack {
+                    kind: StackKind::Bracket,
+                    items: vec![4, 5, 6]
+                }
+            ]
+        );
+        assert_eq!(stacks[1].head(), 4);
+
+        // Videos and images without camera are never stacked
+        let mut items = vec![
+            item(10, Some(100), Some(0), "A"),
+            item(10, Some(200), Some(0), "A"),
+        ];
+        items[1] = FileItem::dummy("test.mp4", 10, true);
+        items.push(FileItem::dummy("test.jpg", 10, true));
+        assert!(find_stacks(&items).is_empty());
+    }
+}
@@ -1,4 +1,6 @@
-use chrono::Datelike;
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 
 #[derive(Display, PartialEq, Eq)]
@@ -7,25 +9,111 @@ pub enum Format {
     Date,
     #[strum(serialize = "%Y-%m-%d %H:%M:%S")]
     DateTime,
-    #[strum(serialize = "%Y")]
-    Year,
-    #[strum(serialize = "%Y-%m")]
-    YearAndMonth,
-    #[strum(serialize = "%Y")]
-    YearAndQuarter,
-    #[strum(serialize = "%m")]
-    Month,
 }
 
 pub fn timestamp_to_string(timestamp: i64, fmt: Format) -> String {
     let d = chrono::DateTime::from_timestamp(timestamp, 0);
     if let Some(d) = d {
-        if fmt == Format::YearAndQuarter {
-            d.format("%Y-Q").to_string() + &format!("{}", (d.date_naive().month() - 1) / 3 + 1)
-        } else {
-            d.format(&fmt.to_string()).to_string()
-        }
+        d.format(&fmt.to_string()).to_string()
     } else {
         String::from("???")
     }
 }
+
+/// Capture time of a file item with millisecond precision. The time is the local time at the place the file was
+/// captured together with the offset of this local time to UTC if it is known.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Timestamp {
+    /// Local time in milliseconds since 1970-01-01 00:00:00
+    pub millis: i64,
+    /// Offset of the local time to UTC in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i32>,
+}
+
+impl Timestamp {
+    /// Creates a timestamp from the local time in milliseconds and an optional offset to UTC in seconds
+    pub fn new(millis: i64, offset: Option<i32>) -> Self {
+        Self { millis, offset }
+    }
+
+    /// Creates a timestamp from the local time in seconds without a known offset to UTC
+    pub fn from_seconds(seconds: i64) -> Self {
+        Self::new(seconds * 1000, None)
+    }
+
+    /// Gets the local time in whole seconds
+    pub fn seconds(&self) -> i64 {
+        self.millis.div_euclid(1000)
+    }
+
+    /// Gets the time in milliseconds in UTC. If the offset is not known, the local time is used.
+    pub fn utc_millis(&self) -> i64 {
+        self.millis - self.offset.unwrap_or(0) as i64 * 1000
+    }
+}
+
+impl PartialEq for Timestamp {
+    /// Timestamps are equal if they are ordered the same, an unknown offset is the same as UTC
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Timestamp {}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timestamp {
+    /// Timestamps are ordered by their time in UTC, so that files captured in different time zones are ordered
+    /// correctly
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.utc_millis()
+            .cmp(&other.utc_millis())
+            .then(self.millis.cmp(&other.millis))
+    }
+}
+
+/// Deserializes a timestamp, also from project files which stored the local time in whole seconds
+pub fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Timestamp, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredTimestamp {
+        Seconds(i64),
+        Timestamp(Timestamp),
+    }
+
+    Ok(match StoredTimestamp::deserialize(deserializer)? {
+        StoredTimestamp::Seconds(seconds) => Timestamp::from_seconds(seconds),
+        StoredTimestamp::Timestamp(timestamp) => timestamp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        let timestamp = Timestamp::new(10_500, Some(3600));
+        assert_eq!(timestamp.seconds(), 10);
+        assert_eq!(timestamp.utc_millis(), 10_500 - 3_600_000);
+        assert_eq!(Timestamp::new(-500, None).seconds(), -1);
+        assert_eq!(Timestamp::from_seconds(-1).seconds(), -1);
+
+        // Taken later in local time, but earlier in UTC
+        let earlier = Timestamp::new(3_600_000, Some(7200));
+        let later = Timestamp::new(0, Some(0));
+        assert!(earlier < later);
+        assert!(Timestamp::new(0, None) < Timestamp::new(1, None));
+        assert_eq!(Timestamp::new(0, None), Timestamp::new(0, Some(0)));
+        assert_ne!(Timestamp::new(0, None), Timestamp::new(0, Some(3600)));
+    }
+}
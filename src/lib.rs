@@ -11,6 +11,7 @@
 
 //! Image_sieve crate providing a GUI based tool to sort out images based on similarity, categorize them according
 //! to their creation date and archive them in a target folder.
+pub mod cli;
 mod controller;
 mod item_sort_list;
 pub mod main_window;
@@ -7,7 +7,7 @@ use std::io::Write;
 use std::{panic, fs::File};
 use backtrace::Backtrace;
 
-use image_sieve::main_window;
+use image_sieve::{cli, main_window};
 
 fn main() {
     let prev = panic::take_hook();
@@ -23,6 +23,13 @@ fn main() {
         
         prev(panic_info);
     }));
+
+    // Any command line argument runs image_sieve without GUI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let main_window = main_window::MainWindow::new();
 
     main_window.run();
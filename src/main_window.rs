@@ -3,9 +3,10 @@
 extern crate nfde;
 extern crate slint;
 
-use slint::{Model, ModelRc, SharedString};
+use slint::{Model, SharedString};
 use std::cell::RefCell;
 use std::fmt::Debug;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
@@ -14,10 +15,15 @@ use nfde::*;
 
 use crate::controller::events_controller::EventsController;
 use crate::controller::items_controller::ItemsController;
-use crate::item_sort_list::ItemList;
+use crate::item_sort_list::{
+    ItemList, PathTemplate, PlannedOperation, SieveEvent, SieveJournal, SieveMethod,
+    SieveStatistics,
+};
 use crate::misc::images::get_empty_image;
-use crate::persistence::json::{get_project_filename, get_settings_filename, JsonPersistence, self};
-use crate::persistence::model_to_enum::model_to_enum;
+use crate::persistence::json::{
+    get_archive_index_filename, get_journal_filename, get_project_filename, get_settings_filename,
+    load_archive_index, load_latest_journal, JsonPersistence, self,
+};
 use crate::persistence::settings::Settings;
 use crate::synchronize::Synchronizer;
 
@@ -122,9 +128,23 @@ impl MainWindow {
                 .get_similar_items_model()
                 .into(),
         );
+        main_window.window.set_details_model(
+            main_window
+                .items_controller
+                .borrow()
+                .get_details_model()
+                .into(),
+        );
         main_window
             .window
             .set_events_model(main_window.events_controller.borrow().get_model().into());
+        main_window.window.set_camera_clocks_model(
+            main_window
+                .events_controller
+                .borrow()
+                .get_camera_clocks_model()
+                .into(),
+        );
         main_window
             .window
             .set_sieve_result_model(main_window.sieve_result_model.clone().into());
@@ -168,6 +188,23 @@ impl MainWindow {
                 items_controller
                     .borrow_mut()
                     .selected_list_item(i as usize, window_weak.clone());
+                if window_weak.unwrap().get_details_visible() {
+                    items_controller
+                        .borrow_mut()
+                        .fill_details(window_weak.clone());
+                }
+            }
+        });
+
+        self.window.on_show_details({
+            // Details of the current image shall be shown
+            let items_controller = self.items_controller.clone();
+            let window_weak = self.window.as_weak();
+
+            move || {
+                items_controller
+                    .borrow_mut()
+                    .fill_details(window_weak.clone());
             }
         });
 
@@ -186,6 +223,36 @@ impl MainWindow {
             }
         });
 
+        self.window.on_preview_sieve({
+            // Preview pressed - show the planned operations of the selected action
+            let window_weak = self.window.as_weak();
+            let item_list = self.item_list.clone();
+            let sieve_result_model = self.sieve_result_model.clone();
+
+            move || {
+                preview_sieve(
+                    &item_list.lock().unwrap(),
+                    window_weak.clone(),
+                    sieve_result_model.clone(),
+                );
+            }
+        });
+
+        self.window.on_undo_sieve({
+            // Undo pressed - revert the last sieve process
+            let window_weak = self.window.as_weak();
+            let item_list = self.item_list.clone();
+            let sieve_result_model = self.sieve_result_model.clone();
+
+            move || {
+                undo_sieve(
+                    &item_list.lock().unwrap(),
+                    window_weak.clone(),
+                    sieve_result_model.clone(),
+                );
+            }
+        });
+
         self.window.on_set_take_over({
             // Image was clicked, toggle take over state
             let items_controller = self.items_controller.clone();
@@ -196,6 +263,25 @@ impl MainWindow {
             }
         });
 
+        self.window.on_set_date({
+            // Date of an image was corrected manually, the items are sorted again like after changing a clock offset
+            let items_controller = self.items_controller.clone();
+            let item_list = self.item_list.clone();
+            let window_weak = self.window.as_weak();
+
+            move |i: i32, date: SharedString| -> SharedString {
+                let result = items_controller.borrow_mut().set_date(i, date.as_str());
+                if result.is_empty() {
+                    {
+                        let item_list = item_list.lock().unwrap();
+                        JsonPersistence::save(&get_project_filename(&item_list.path), &item_list.clone());
+                    }
+                    window_weak.unwrap().invoke_synchronization_finished();
+                }
+                result
+            }
+        });
+
         self.window.on_browse_source({
             // Browse source was clicked, select new path
             let events_controller = self.events_controller.clone();
@@ -254,6 +340,39 @@ impl MainWindow {
             }
         });
 
+        self.window.on_browse_quarantine({
+            // Quarantine path was changed
+            let window_weak = self.window.as_weak();
+
+            move || {
+                let nfd = Nfd::new().unwrap();
+                let mut builder = nfd.pick_folder();
+                builder.default_path(&Path::new(&window_weak.unwrap().get_quarantine_directory().to_string().as_str())).ok();
+                if let DialogResult::Ok(folder) = builder.show()
+                {
+                    window_weak
+                        .unwrap()
+                        .set_quarantine_directory(SharedString::from(folder.as_os_str().to_str().unwrap()));
+                }
+            }
+        });
+
+        self.window
+            .on_check_template(|template: SharedString| -> SharedString {
+                // Custom template was edited, return an error message if it is invalid
+                SharedString::from(template.parse::<PathTemplate>().err().unwrap_or_default())
+            });
+
+        self.window
+            .on_check_rename_pattern(|rename_pattern: SharedString| -> SharedString {
+                // Rename pattern was edited, return an error message if it is invalid
+                SharedString::from(
+                    PathTemplate::check_rename_pattern(&rename_pattern)
+                        .err()
+                        .unwrap_or_default(),
+                )
+            });
+
         self.window.on_synchronization_finished({
             // First step of synchronization (browsing for files) finished
             let window_weak = self.window.as_weak();
@@ -270,6 +389,7 @@ impl MainWindow {
 
                 // Now fill the events model
                 events_controller.borrow_mut().synchronize();
+                window.set_write_metadata(events_controller.borrow().get_write_metadata());
 
                 // Update the selection variables
                 if num_items > 0 {
@@ -298,6 +418,7 @@ impl MainWindow {
                 window.set_loading(false);
 
                 // And tell the synchronizer to calculate similarities now
+                window.set_similarities_progress(SharedString::new());
                 synchronizer.calculate_similarities(Settings::from_window(&window));
             }
         });
@@ -362,6 +483,38 @@ impl MainWindow {
             }
         });
 
+        self.window.on_set_clock_offset({
+            // Clock offset of a camera was changed, the items are sorted again by the corrected timestamps, so the
+            // list is filled again and the similarities are calculated again
+            let events_controller = self.events_controller.clone();
+            let item_list = self.item_list.clone();
+            let window_weak = self.window.as_weak();
+
+            move |index: i32, offset: SharedString| -> SharedString {
+                let result = events_controller
+                    .borrow_mut()
+                    .set_clock_offset(index, &offset);
+                if result.is_empty() {
+                    {
+                        let item_list = item_list.lock().unwrap();
+                        JsonPersistence::save(&get_project_filename(&item_list.path), &item_list.clone());
+                    }
+                    window_weak.unwrap().invoke_synchronization_finished();
+                }
+                result
+            }
+        });
+
+        self.window.on_set_write_metadata({
+            let events_controller = self.events_controller.clone();
+
+            move |write_metadata: bool| {
+                events_controller
+                    .borrow_mut()
+                    .set_write_metadata(write_metadata);
+            }
+        });
+
         self.window.on_remove_event({
             // Event was removed
             let events_controller = self.events_controller.clone();
@@ -397,14 +550,35 @@ impl MainWindow {
                 let window = window_weak.unwrap();
                 // Synchronize in a background thread
                 window.set_calculating_similarities(true);
+                window.set_similarities_progress(SharedString::new());
                 synchronizer.calculate_similarities(Settings::from_window(&window));
             }
         });
 
+        self.window.on_keep_best_shots({
+            // Keep the best shot of each group of similar items in a background thread
+            let window_weak = self.window.as_weak();
+            let synchronizer = self.synchronizer.clone();
+
+            move || {
+                let window = window_weak.unwrap();
+                window.set_calculating_similarities(true);
+                window.set_similarities_progress(SharedString::new());
+                synchronizer.keep_best_shots();
+            }
+        });
+
         self.window.on_cancel_loading({
             let synchronizer = self.synchronizer.clone();
             move || {
-                synchronizer.stop();
+                synchronizer.cancel();
+            }
+        });
+
+        self.window.on_cancel_similarities({
+            let synchronizer = self.synchronizer.clone();
+            move || {
+                synchronizer.cancel();
             }
         });
 
@@ -439,65 +613,234 @@ pub fn sieve(
     sieve_result_model: Rc<slint::VecModel<SieveResult>>,
 ) {
     let item_list_copy = item_list.to_owned();
-    let target_path = window_weak.unwrap().get_target_directory().to_string();
-    let methods: ModelRc<SharedString> = window_weak
-        .unwrap()
-        .global::<SieveComboValues>()
-        .get_methods();
-    let sieve_method = model_to_enum(&methods, &window_weak.unwrap().get_sieve_method());
-    let directory_names: ModelRc<SharedString> = window_weak
-        .unwrap()
-        .global::<SieveComboValues>()
-        .get_directory_names();
-    let sieve_directory_names = model_to_enum(
-        &directory_names,
-        &window_weak.unwrap().get_sieve_directory_names(),
+    let Some((target_path, sieve_method, path_template)) =
+        get_sieve_parameters(&window_weak, &sieve_result_model)
+    else {
+        return;
+    };
+    let settings = Settings::from_window(&window_weak.unwrap());
+    let deletion = settings.deletion();
+    let motion_photos = settings.motion_photos;
+    reset_sieve_results(
+        &sieve_result_model,
+        format!(
+            "Sieving using {:?} method to {} with template {}",
+            sieve_method, target_path, path_template
+        ),
     );
-    for _ in 0..sieve_result_model.row_count() {
-        sieve_result_model.remove(0);
-    }
-    sieve_result_model.push(SieveResult {
-        result: SharedString::from(format!(
-            "Sieving using {:?} method to {} with directories {:?}",
-            sieve_method, target_path, sieve_directory_names
-        )),
-        color: SharedString::from("black"),
-    });
 
     thread::spawn(move || {
-        let progress_callback = |progress: String| {
-            let window_weak_copy = window_weak.clone();
-            window_weak_copy
-                .upgrade_in_event_loop(move |handle| {
-                    if progress == "Done" {
-                        handle.set_sieve_running(false);
-                    }
-                    let sieve_result_model = handle.get_sieve_result_model();
-                    let sieve_result_model = sieve_result_model
-                        .as_any()
-                        .downcast_ref::<slint::VecModel<SieveResult>>()
-                        .unwrap();
-                    let color = if progress == "Done" {
-                        SharedString::from("green")
-                    } else if progress.starts_with("Error") {
-                        SharedString::from("red")
-                    } else {
-                        SharedString::from("black")
-                    };
-                    let sieve_result = SieveResult {
-                        result: SharedString::from(progress),
-                        color,
-                    };
-                    sieve_result_model.push(sieve_result);
-                })
+        let journal_filename = get_journal_filename(
+            &item_list_copy.journal_directory(Path::new(&target_path), &sieve_method),
+        );
+        // Only the last sieve can be undone, so finally delete the files staged by the previous one
+        let previous_journal: Option<SieveJournal> = JsonPersistence::load(&journal_filename);
+        if let Some(previous_journal) = previous_journal {
+            previous_journal.purge();
+        }
+        let mut archive_index = load_archive_index(Path::new(&target_path));
+        let journal = item_list_copy.sieve(
+            Path::new(&target_path),
+            sieve_method,
+            &path_template,
+            motion_photos,
+            &deletion,
+            &mut archive_index,
+            |event: SieveEvent| report_sieve_event(&window_weak, event),
+        );
+        JsonPersistence::save(&journal_filename, &journal);
+        if !target_path.is_empty() {
+            JsonPersistence::save(
+                &get_archive_index_filename(Path::new(&target_path)),
+                &archive_index,
+            );
+        }
+    });
+}
+
+/// Undoes the last sieve process in a background thread
+pub fn undo_sieve(
+    item_list: &ItemList,
+    window_weak: slint::Weak<ImageSieve>,
+    sieve_result_model: Rc<slint::VecModel<SieveResult>>,
+) {
+    let source_path = item_list.path.clone();
+    let target_path = PathBuf::from(window_weak.unwrap().get_target_directory().to_string());
+    reset_sieve_results(&sieve_result_model, String::from("Undoing last sieve"));
+
+    thread::spawn(
+        move || match load_latest_journal(&[target_path.clone(), source_path]) {
+            Some((journal_filename, journal)) => {
+                let remaining =
+                    journal.undo(|event: SieveEvent| report_sieve_event(&window_weak, event));
+                if remaining.is_empty() {
+                    std::fs::remove_file(journal_filename).ok();
+                } else {
+                    JsonPersistence::save(&journal_filename, &remaining);
+                }
+            }
+            None => {
+                report_sieve_event(
+                    &window_weak,
+                    SieveEvent::Failed {
+                        path: target_path,
+                        kind: ErrorKind::NotFound,
+                        message: String::from("No sieve to undo found"),
+                    },
+                );
+                report_sieve_event(
+                    &window_weak,
+                    SieveEvent::Finished {
+                        statistics: SieveStatistics::default(),
+                    },
+                );
+            }
+        },
+    );
+}
+
+/// Adds an event of a sieve process to the sieve result model
+fn report_sieve_event(window_weak: &slint::Weak<ImageSieve>, event: SieveEvent) {
+    window_weak
+        .upgrade_in_event_loop(move |handle| {
+            let color = match event {
+                SieveEvent::Finished { .. } => {
+                    handle.set_sieve_running(false);
+                    SharedString::from("green")
+                }
+                SieveEvent::Failed { .. } => SharedString::from("red"),
+                SieveEvent::Skipped { .. } => SharedString::from("gray"),
+                _ => SharedString::from("black"),
+            };
+            let sieve_result_model = handle.get_sieve_result_model();
+            let sieve_result_model = sieve_result_model
+                .as_any()
+                .downcast_ref::<slint::VecModel<SieveResult>>()
                 .unwrap();
-        };
-        item_list_copy.sieve(
+            let sieve_result = SieveResult {
+                result: SharedString::from(event.to_string()),
+                color,
+            };
+            sieve_result_model.push(sieve_result);
+        })
+        .unwrap();
+}
+
+/// Performs a dry run of the sieve process in a background thread and shows the planned operations
+pub fn preview_sieve(
+    item_list: &ItemList,
+    window_weak: slint::Weak<ImageSieve>,
+    sieve_result_model: Rc<slint::VecModel<SieveResult>>,
+) {
+    let item_list_copy = item_list.to_owned();
+    let Some((target_path, sieve_method, path_template)) =
+        get_sieve_parameters(&window_weak, &sieve_result_model)
+    else {
+        return;
+    };
+    let motion_photos = Settings::from_window(&window_weak.unwrap()).motion_photos;
+    reset_sieve_results(
+        &sieve_result_model,
+        format!(
+            "Preview of sieving using {:?} method to {} with template {}",
+            sieve_method, target_path, path_template
+        ),
+    );
+
+    thread::spawn(move || {
+        let plan = item_list_copy.plan_sieve(
             Path::new(&target_path),
             sieve_method,
-            sieve_directory_names,
-            progress_callback,
+            &path_template,
+            motion_photos,
+            &load_archive_index(Path::new(&target_path)),
         );
+        window_weak
+            .upgrade_in_event_loop(move |handle| {
+                let sieve_result_model = handle.get_sieve_result_model();
+                let sieve_result_model = sieve_result_model
+                    .as_any()
+                    .downcast_ref::<slint::VecModel<SieveResult>>()
+                    .unwrap();
+                for operation in &plan.operations {
+                    let color = match operation {
+                        PlannedOperation::Skip { .. } => SharedString::from("gray"),
+                        _ => SharedString::from("black"),
+                    };
+                    sieve_result_model.push(SieveResult {
+                        result: SharedString::from(operation.to_string()),
+                        color,
+                    });
+                }
+                sieve_result_model.push(SieveResult {
+                    result: SharedString::from(format!(
+                        "Preview done, {} operations planned, no files were changed",
+                        plan.operations.len()
+                    )),
+                    color: SharedString::from("green"),
+                });
+            })
+            .unwrap();
+    });
+}
+
+/// Gets the target path, the sieve method and the path template selected in the window. If the custom template is
+/// invalid or the deletion policy is not supported on this platform, the error is reported as sieve result and None
+/// is returned.
+fn get_sieve_parameters(
+    window_weak: &slint::Weak<ImageSieve>,
+    sieve_result_model: &slint::VecModel<SieveResult>,
+) -> Option<(String, SieveMethod, PathTemplate)> {
+    let settings = Settings::from_window(&window_weak.unwrap());
+    let (header, path, kind, message) = match settings.check_deletion_policy() {
+        Err(message) => (
+            "Unsupported deletion policy",
+            settings.target_directory,
+            ErrorKind::Unsupported,
+            message,
+        ),
+        Ok(_) => match settings.path_template() {
+            Ok(path_template) => {
+                return Some((
+                    settings.target_directory,
+                    settings.sieve_method,
+                    path_template,
+                ));
+            }
+            Err(message) => (
+                "Invalid template",
+                settings.sieve_template,
+                ErrorKind::InvalidInput,
+                message,
+            ),
+        },
+    };
+    reset_sieve_results(sieve_result_model, String::from(header));
+    report_sieve_event(
+        window_weak,
+        SieveEvent::Failed {
+            path: PathBuf::from(path),
+            kind,
+            message,
+        },
+    );
+    report_sieve_event(
+        window_weak,
+        SieveEvent::Finished {
+            statistics: SieveStatistics::default(),
+        },
+    );
+    None
+}
+
+/// Clears the sieve result model and adds a header line
+fn reset_sieve_results(sieve_result_model: &slint::VecModel<SieveResult>, header: String) {
+    for _ in 0..sieve_result_model.row_count() {
+        sieve_result_model.remove(0);
+    }
+    sieve_result_model.push(SieveResult {
+        result: SharedString::from(header),
+        color: SharedString::from("black"),
     });
 }
 
@@ -12,7 +12,12 @@ pub type ImageBuffer = image::ImageBuffer<image::Rgba<u8>, Vec<u8>>;
 /// Get an image buffer from a FileItem with a width and height constraint. If the image contains
 /// an orientation indication, it is rotated accordingly.
 pub fn get_image_buffer(item: &FileItem, max_width: u32, max_height: u32) -> ImageBuffer {
-    let image_buffer = match item.get_item_type() {
+    load_image_buffer(item, max_width, max_height).unwrap_or_else(|| ImageBuffer::new(1, 1))
+}
+
+/// Load an image buffer from a FileItem like get_image_buffer, but None is returned if the image cannot be decoded.
+pub fn load_image_buffer(item: &FileItem, max_width: u32, max_height: u32) -> Option<ImageBuffer> {
+    match item.get_item_type() {
         ItemType::Image => {
             load_image_and_rotate(&item.path, get_rotation(item), max_width, max_height)
         }
@@ -23,9 +28,7 @@ pub fn get_image_buffer(item: &FileItem, max_width: u32, max_height: u32) -> Ima
             load_heif_image_and_rotate(&item.path, get_rotation(item), max_width, max_height)
         }
         _ => None,
-    };
-
-    image_buffer.unwrap_or_else(|| ImageBuffer::new(1, 1))
+    }
 }
 
 /// Return the rotation in degrees from a file item
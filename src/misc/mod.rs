@@ -1,5 +1,6 @@
 pub mod image_cache;
 pub mod images;
 mod lru_map;
+pub mod quality;
 mod resize;
-mod video_to_image;
+pub mod video_to_image;
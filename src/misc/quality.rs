@@ -0,0 +1,157 @@
+use super::images::{ImageBuffer, load_image_buffer};
+use crate::item_sort_list::FileItem;
+
+/// Maximum width and height of the image the quality is calculated from
+const QUALITY_IMAGE_SIZE: u32 = 1024;
+/// Brightness up to which a pixel counts as clipped shadow
+const SHADOW_CLIPPING: u8 = 2;
+/// Brightness from which on a pixel counts as clipped highlight
+const HIGHLIGHT_CLIPPING: u8 = 253;
+
+/// Weight of the sharpness in the quality score
+const SHARPNESS_WEIGHT: f64 = 0.4;
+/// Weight of the exposure in the quality score
+const EXPOSURE_WEIGHT: f64 = 0.3;
+/// Weight of the resolution in the quality score
+const RESOLUTION_WEIGHT: f64 = 0.2;
+/// Weight of the file size in the quality score
+const FILE_SIZE_WEIGHT: f64 = 0.1;
+
+/// Quality measures of an image used to suggest the best image of a group of similar images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// Variance of the Laplacian of the brightness, sharper images have a higher value
+    pub sharpness: f64,
+    /// Fraction of pixels with clipped shadows or highlights
+    pub clipping: f64,
+    /// Number of pixels of the image in full size
+    pub pixels: u64,
+    /// File size in bytes
+    pub file_size: u64,
+}
+
+impl Quality {
+    /// Calculates the quality measures from a decoded image, the resolution of the image in full size and its file
+    /// size
+    pub fn from_buffer(buffer: &ImageBuffer, resolution: (u32, u32), file_size: u64) -> Self {
+        let (width, height) = (buffer.width() as usize, buffer.height() as usize);
+        let brightness: Vec<u8> = buffer
+            .pixels()
+            .map(|pixel| {
+                ((pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000)
+                    as u8
+            })
+            .collect();
+
+        let clipped = brightness
+            .iter()
+            .filter(|value| **value <= SHADOW_CLIPPING || **value >= HIGHLIGHT_CLIPPING)
+            .count();
+
+        // Apply the Laplacian kernel to all inner pixels
+        let (mut sum, mut square_sum, mut count) = (0.0, 0.0, 0.0);
+        for y in 1..height.saturating_sub(1) {
+            for x in 1..width.saturating_sub(1) {
+                let at = |x: usize, y: usize| brightness[y * width + x] as f64;
+                let laplacian =
+                    4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1);
+                sum += laplacian;
+                square_sum += laplacian * laplacian;
+                count += 1.0;
+            }
+        }
+        let sharpness = if count > 0.0 {
+            square_sum / count - (sum / count).powi(2)
+        } else {
+            0.0
+        };
+
+        Self {
+            sharpness,
+            clipping: clipped as f64 / brightness.len().max(1) as f64,
+            pixels: resolution.0 as u64 * resolution.1 as u64,
+            file_size,
+        }
+    }
+}
+
+/// Calculates the quality of an image, None is returned for videos and images that cannot be decoded
+pub fn calculate_quality(item: &FileItem) -> Option<Quality> {
+    let buffer = load_image_buffer(item, QUALITY_IMAGE_SIZE, QUALITY_IMAGE_SIZE)?;
+    let resolution = item
+        .get_metadata()
+        .resolution
+        .or_else(|| image::image_dimensions(&item.path).ok())
+        .unwrap_or(buffer.dimensions());
+    Some(Quality::from_buffer(&buffer, resolution, item.get_size()))
+}
+
+/// Gets the position of the best image in a list of qualities. Since the sharpness, resolution and file size have no
+/// absolute scale, they are compared relative to the maximum value in the list.
+pub fn best_quality(qualities: &[Quality]) -> Option<usize> {
+    let max_sharpness = qualities
+        .iter()
+        .map(|quality| quality.sharpness)
+        .fold(0.0, f64::max);
+    let max_pixels = qualities.iter().map(|quality| quality.pixels).max()?;
+    let max_file_size = qualities.iter().map(|quality| quality.file_size).max()?;
+    let relative = |value: f64, max: f64| if max > 0.0 { value / max } else { 1.0 };
+    let score = |quality: &Quality| {
+        SHARPNESS_WEIGHT * relative(quality.sharpness, max_sharpness)
+            + EXPOSURE_WEIGHT * (1.0 - quality.clipping)
+            + RESOLUTION_WEIGHT * relative(quality.pixels as f64, max_pixels as f64)
+            + FILE_SIZE_WEIGHT * relative(quality.file_size as f64, max_file_size as f64)
+    };
+    qualities
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| score(a).total_cmp(&score(b)))
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(brightness: u8) -> ImageBuffer {
+        ImageBuffer::from_fn(16, 16, |x, y| {
+            let value = if (x + y) % 2 == 0 { brightness } else { 128 };
+            image::Rgba([value, value, value, 255])
+        })
+    }
+
+    #[test]
+    fn test_from_buffer() {
+        let flat = Quality::from_buffer(&checkerboard(128), (16, 16), 100);
+        assert_eq!(flat.sharpness, 0.0);
+        assert_eq!(flat.clipping, 0.0);
+        assert_eq!(flat.pixels, 256);
+
+        let sharp = Quality::from_buffer(&checkerboard(160), (16, 16), 100);
+        assert!(sharp.sharpness > flat.sharpness);
+        assert_eq!(sharp.clipping, 0.0);
+
+        let clipped = Quality::from_buffer(&checkerboard(255), (16, 16), 100);
+        assert_eq!(clipped.clipping, 0.5);
+    }
+
+    #[test]
+    fn test_best_quality() {
+        assert_eq!(best_quality(&[]), None);
+        let qualities = [
+            Quality::from_buffer(&checkerboard(128), (16, 16), 100),
+            Quality::from_buffer(&checkerboard(160), (16, 16), 100),
+            Quality::from_buffer(&checkerboard(160), (8, 8), 50),
+        ];
+        assert_eq!(best_quality(&qualities), Some(1));
+        assert_eq!(best_quality(&qualities[..1]), Some(0));
+    }
+
+    #[test]
+    fn test_calculate_quality() {
+        let quality = calculate_quality(&FileItem::dummy("tests/test.jpg", 0, true)).unwrap();
+        assert!(quality.sharpness > 0.0);
+        assert!(quality.pixels > 0);
+        assert!(calculate_quality(&FileItem::dummy("tests/test.mp4", 0, true)).is_none());
+    }
+}
@@ -16,6 +16,12 @@ pub fn get_image_buffer(item: &FileItem, max_width: u32, max_height: u32) -> Ima
     create_image_from_video(item, max_width, max_height).unwrap_or_else(|_| get_alternative_image())
 }
 
+/// Construct an image for a video by combining 9 frames sampled from the video. In contrast to get_image_buffer, None
+/// is returned if the video cannot be decoded.
+pub fn get_frames_image(item: &FileItem, max_width: u32, max_height: u32) -> Option<ImageBuffer> {
+    create_image_from_video(item, max_width, max_height).ok()
+}
+
 /// Get the alternative image of a video camera
 fn get_alternative_image() -> ImageBuffer {
     crate::misc::images::image_from_buffer(VIDEO_PNG).unwrap()
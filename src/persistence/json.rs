@@ -4,7 +4,7 @@ use std::{
 };
 
 use super::settings::Settings;
-use crate::item_sort_list::ItemList;
+use crate::item_sort_list::{ArchiveIndex, ItemList, SieveJournal, SievePlan};
 use home;
 
 /// Name of the global settings file
@@ -13,6 +13,12 @@ const SETTINGS_FILE: &str = "image_sieve_settings.json";
 /// Name of the project settings file
 const ITEM_LIST_FILE: &str = "image_sieve.json";
 
+/// Name of the journal file of the last sieve process
+const JOURNAL_FILE: &str = "image_sieve_journal.json";
+
+/// Name of the file where the content hashes of a sieve target directory are cached
+const ARCHIVE_INDEX_FILE: &str = "image_sieve_index.json";
+
 /// Name of the trace file
 const TRACE_FILE: &str = "trace.txt";
 
@@ -31,6 +37,40 @@ pub fn get_project_filename(path: &Path) -> PathBuf {
     Path::new(path).to_path_buf().join(ITEM_LIST_FILE)
 }
 
+/// Get the directory and filename where the journal of the last sieve process is stored
+pub fn get_journal_filename(path: &Path) -> PathBuf {
+    path.join(JOURNAL_FILE)
+}
+
+/// Load the most recent sieve journal found in one of the given directories together with its filename
+pub fn load_latest_journal(directories: &[PathBuf]) -> Option<(PathBuf, SieveJournal)> {
+    directories
+        .iter()
+        .map(|directory| get_journal_filename(directory))
+        .filter_map(|file_name| {
+            let journal: Option<SieveJournal> = JsonPersistence::load(&file_name);
+            journal.map(|journal| (file_name, journal))
+        })
+        .max_by_key(|(_, journal)| journal.timestamp)
+}
+
+/// Get the directory and filename where the archive index of a sieve target directory is stored
+pub fn get_archive_index_filename(path: &Path) -> PathBuf {
+    path.join(ARCHIVE_INDEX_FILE)
+}
+
+/// Load the archive index of a sieve target directory and synchronize it with the files found there
+pub fn load_archive_index(path: &Path) -> ArchiveIndex {
+    if path.as_os_str().is_empty() {
+        return ArchiveIndex::default();
+    }
+    let archive_index: Option<ArchiveIndex> =
+        JsonPersistence::load(&get_archive_index_filename(path));
+    let mut archive_index = archive_index.unwrap_or_default();
+    archive_index.update(path);
+    archive_index
+}
+
 fn get_and_create_home_dir() -> PathBuf {
     let home = home::home_dir();
     if let Some(home) = home {
@@ -89,12 +129,51 @@ impl JsonPersistence for ItemList {
     }
 }
 
+impl JsonPersistence for SievePlan {
+    fn load(file_name: &Path) -> Option<SievePlan> {
+        let plan = fs::read_to_string(file_name).unwrap_or_default();
+        serde_json::from_str::<SievePlan>(&plan).ok()
+    }
+
+    fn save(file_name: &Path, plan: &SievePlan) {
+        let plan = serde_json::to_string_pretty(plan).unwrap_or_default();
+        fs::write(file_name, plan).ok();
+    }
+}
+
+impl JsonPersistence for SieveJournal {
+    fn load(file_name: &Path) -> Option<SieveJournal> {
+        let journal = fs::read_to_string(file_name).unwrap_or_default();
+        serde_json::from_str::<SieveJournal>(&journal).ok()
+    }
+
+    fn save(file_name: &Path, journal: &SieveJournal) {
+        let journal = serde_json::to_string_pretty(journal).unwrap_or_default();
+        fs::write(file_name, journal).ok();
+    }
+}
+
+impl JsonPersistence for ArchiveIndex {
+    fn load(file_name: &Path) -> Option<ArchiveIndex> {
+        let archive_index = fs::read_to_string(file_name).unwrap_or_default();
+        serde_json::from_str::<ArchiveIndex>(&archive_index).ok()
+    }
+
+    fn save(file_name: &Path, archive_index: &ArchiveIndex) {
+        let archive_index = serde_json::to_string(archive_index).unwrap_or_default();
+        fs::write(file_name, archive_index).ok();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::item_sort_list::ClockOffset;
     use crate::item_sort_list::Event;
     use crate::item_sort_list::FileItem;
-    use crate::item_sort_list::{DirectoryNames, SieveMethod};
+    use crate::item_sort_list::{
+        DeletionPolicy, DirectoryNames, HashAlgorithm, MotionPhotos, PlannedOperation, SieveMethod,
+    };
     use chrono::NaiveDate;
     use img_hash::ImageHash;
 
@@ -106,6 +185,34 @@ mod tests {
         assert!(project_filename_str.contains("test"));
         assert!(project_filename_str.contains(ITEM_LIST_FILE));
         assert!(!get_trace_filename().as_os_str().is_empty());
+        assert!(get_journal_filename(Path::new("test")).ends_with(JOURNAL_FILE));
+    }
+
+    #[test]
+    fn test_load_latest_journal() {
+        let directories = [
+            PathBuf::from("tests/journal_old"),
+            PathBuf::from("tests/journal_new"),
+            PathBuf::from("tests/journal_none"),
+        ];
+        for (timestamp, directory) in directories[..2].iter().enumerate() {
+            fs::create_dir_all(directory).unwrap();
+            let journal = SieveJournal {
+                timestamp: timestamp as i64,
+                ..Default::default()
+            };
+            JsonPersistence::save(&get_journal_filename(directory), &journal);
+        }
+
+        let latest = load_latest_journal(&directories);
+        let none = load_latest_journal(&directories[2..]);
+        fs::remove_dir_all(&directories[0]).ok();
+        fs::remove_dir_all(&directories[1]).ok();
+
+        let (file_name, journal) = latest.unwrap();
+        assert_eq!(file_name, get_journal_filename(&directories[1]));
+        assert_eq!(journal.timestamp, 1);
+        assert!(none.is_none());
     }
 
     #[test]
@@ -121,6 +228,11 @@ mod tests {
                 end_date: NaiveDate::from_ymd_opt(2021, 9, 14).unwrap(),
             }],
             path: PathBuf::from("test"),
+            clock_offsets: vec![ClockOffset {
+                camera: String::from("Canon EOS 5D"),
+                seconds: -3600,
+            }],
+            write_metadata: true,
         };
         let hash = ImageHash::<Vec<u8>>::from_bytes(&[0x64, 0x65, 0x66, 0x67])
             .unwrap()
@@ -133,11 +245,38 @@ mod tests {
         assert_eq!(loaded_item_list.path, item_list.path);
         assert_eq!(loaded_item_list.events, item_list.events);
         assert_eq!(loaded_item_list.items, item_list.items);
+        assert_eq!(loaded_item_list.clock_offsets, item_list.clock_offsets);
+        assert!(loaded_item_list.write_metadata);
 
         let loaded_item_list: Option<ItemList> = JsonPersistence::load(Path::new("invalid.json"));
         assert!(loaded_item_list.is_none());
     }
 
+    #[test]
+    fn test_load_save_sieve_plan() {
+        let plan = SievePlan {
+            operations: vec![
+                PlannedOperation::CreateDirectory {
+                    path: PathBuf::from("target/2021-09"),
+                },
+                PlannedOperation::Copy {
+                    source: PathBuf::from("test/test1.jpg"),
+                    target: PathBuf::from("target/2021-09/test1.jpg"),
+                    renamed: false,
+                },
+                PlannedOperation::Delete {
+                    path: PathBuf::from("test/test2.jpg"),
+                },
+            ],
+        };
+
+        JsonPersistence::save(Path::new("test_plan.json"), &plan);
+
+        let loaded_plan: Option<SievePlan> = JsonPersistence::load(Path::new("test_plan.json"));
+        fs::remove_file("test_plan.json").ok();
+        assert_eq!(loaded_plan, Some(plan));
+    }
+
     #[test]
     fn test_load_save_settings() {
         let mut settings = Settings::new();
@@ -148,8 +287,16 @@ mod tests {
         settings.timestamp_max_diff += 1;
         settings.use_hash = !settings.use_hash;
         settings.hash_max_diff = 12;
-        settings.sieve_directory_names = Some(DirectoryNames::YearAndQuarter);
+        settings.hash_algorithm = HashAlgorithm::Blockhash;
+        settings.hash_size = 32;
+        settings.sieve_directory_names = Some(DirectoryNames::Custom);
+        settings.sieve_template = String::from("{year}/{event}");
+        settings.rename_pattern = String::from("{date}_{time}_{seq}.{ext}");
         settings.dark_mode = String::from("On");
+        settings.deletion_policy = DeletionPolicy::Quarantine;
+        settings.quarantine_directory = String::from("quarantine");
+        settings.quarantine_retention_days = 7;
+        settings.motion_photos = MotionPhotos::ExtractVideo;
 
         JsonPersistence::save(Path::new("test.json"), &settings);
 
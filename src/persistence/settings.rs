@@ -1,7 +1,11 @@
-use crate::item_sort_list::{DirectoryNames, SieveMethod};
-use crate::main_window::{ImageSieve, SieveComboValues};
+use crate::item_sort_list::{
+    Deletion, DeletionPolicy, DirectoryNames, HashAlgorithm, HashConfig, MotionPhotos, PathTemplate,
+    SieveMethod, SimilarityConfig,
+};
+use crate::main_window::{ImageSieve, SettingsComboValues, SieveComboValues};
 use serde::{Deserialize, Serialize};
 use slint::{ComponentHandle, ModelRc, PhysicalPosition, SharedString};
+use std::path::PathBuf;
 
 use super::model_to_enum::{enum_to_model, model_to_enum};
 
@@ -14,7 +18,17 @@ pub struct Settings {
     pub timestamp_max_diff: i64,
     pub use_hash: bool,
     pub hash_max_diff: u32,
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    #[serde(default = "hash_size_default")]
+    pub hash_size: u32,
+    #[serde(default = "similarity_threshold_default")]
+    pub similarity_threshold: u32,
     pub sieve_directory_names: Option<DirectoryNames>,
+    #[serde(default)]
+    pub sieve_template: String,
+    #[serde(default)]
+    pub rename_pattern: String,
     pub dark_mode: String,
     #[serde(default)]
     pub is_maximized: bool,
@@ -24,12 +38,32 @@ pub struct Settings {
     pub size: (u32, u32),
     #[serde(default)]
     pub last_selected_image: String,
+    #[serde(default)]
+    pub deletion_policy: DeletionPolicy,
+    #[serde(default)]
+    pub quarantine_directory: String,
+    #[serde(default = "quarantine_retention_days_default")]
+    pub quarantine_retention_days: u32,
+    #[serde(default)]
+    pub motion_photos: MotionPhotos,
 }
 
 fn size_default() -> (u32, u32) {
     (800, 600)
 }
 
+fn quarantine_retention_days_default() -> u32 {
+    30
+}
+
+fn hash_size_default() -> u32 {
+    HashConfig::default().size
+}
+
+fn similarity_threshold_default() -> u32 {
+    30
+}
+
 impl Settings {
     pub fn new() -> Self {
         Self {
@@ -40,19 +74,88 @@ impl Settings {
             timestamp_max_diff: 5,
             use_hash: false,
             hash_max_diff: 14,
+            hash_algorithm: HashAlgorithm::DoubleGradient,
+            hash_size: hash_size_default(),
+            similarity_threshold: similarity_threshold_default(),
             sieve_directory_names: Some(DirectoryNames::YearAndMonth),
+            sieve_template: String::new(),
+            rename_pattern: String::new(),
             dark_mode: String::from("Automatic"),
             is_maximized: false,
             position: {(0, 0)},
             size: {(800, 600)},
             last_selected_image: String::new(),
+            deletion_policy: DeletionPolicy::Delete,
+            quarantine_directory: String::new(),
+            quarantine_retention_days: quarantine_retention_days_default(),
+            motion_photos: MotionPhotos::Keep,
+        }
+    }
+
+    /// Gets the deletion policy together with its quarantine settings
+    pub fn deletion(&self) -> Deletion {
+        Deletion {
+            policy: self.deletion_policy.clone(),
+            quarantine_directory: PathBuf::from(&self.quarantine_directory),
+            retention_days: self.quarantine_retention_days,
         }
     }
 
+    /// Checks if the deletion policy can be used on this platform. The policy is only checked if the sieve method
+    /// deletes files.
+    pub fn check_deletion_policy(&self) -> Result<(), String> {
+        match self.sieve_method {
+            SieveMethod::MoveAndDelete | SieveMethod::Delete => self
+                .deletion_policy
+                .check_supported()
+                .map_err(|error| error.to_string()),
+            SieveMethod::Copy | SieveMethod::Move => Ok(()),
+        }
+    }
+
+    /// Gets the configuration of the image similarity hashes
+    pub fn hash_config(&self) -> HashConfig {
+        HashConfig {
+            algorithm: self.hash_algorithm,
+            size: self.hash_size,
+        }
+    }
+
+    /// Gets the configuration of the similarity scoring with the maximum hash distance scaled to the hash
+    /// configuration
+    pub fn similarity_config(&self) -> SimilarityConfig {
+        SimilarityConfig {
+            timestamp_max_diff: self.use_timestamps.then_some(self.timestamp_max_diff),
+            hash_max_diff: self
+                .use_hash
+                .then(|| self.hash_config().scale_distance(self.hash_max_diff)),
+            threshold: self.similarity_threshold,
+        }
+    }
+
+    /// Gets the template for the paths in the sieve target directory including the rename pattern, an error is
+    /// returned if the custom template or the rename pattern is invalid
+    pub fn path_template(&self) -> Result<PathTemplate, String> {
+        PathTemplate::new(
+            self.sieve_directory_names
+                .as_ref()
+                .unwrap_or(&DirectoryNames::YearAndMonth),
+            &self.sieve_template,
+        )?
+        .with_rename_pattern(&self.rename_pattern)
+        .map_err(|error| format!("Invalid rename pattern: {}", error))
+    }
+
     pub fn from_window(window: &ImageSieve) -> Self {
         let methods: ModelRc<SharedString> = window.global::<SieveComboValues>().get_methods();
         let directory_names: ModelRc<SharedString> =
             window.global::<SieveComboValues>().get_directory_names();
+        let deletion_policies: ModelRc<SharedString> =
+            window.global::<SieveComboValues>().get_deletion_policies();
+        let motion_photos: ModelRc<SharedString> =
+            window.global::<SieveComboValues>().get_motion_photos();
+        let hash_algorithms: ModelRc<SharedString> =
+            window.global::<SettingsComboValues>().get_hash_algorithms();
         Settings {
             source_directory: window.get_source_directory().to_string(),
             target_directory: window.get_target_directory().to_string(),
@@ -62,10 +165,21 @@ impl Settings {
                 .unwrap_or(5),
             use_hash: window.get_use_similarity(),
             hash_max_diff: convert_sensitivity_to_u32(&window.get_similarity_sensitivity()),
+            hash_algorithm: model_to_enum(&hash_algorithms, &window.get_hash_algorithm()),
+            hash_size: window
+                .get_hash_size()
+                .parse::<u32>()
+                .unwrap_or(hash_size_default()),
+            similarity_threshold: window
+                .get_similarity_threshold()
+                .parse::<u32>()
+                .unwrap_or(similarity_threshold_default()),
             sieve_directory_names: Some(model_to_enum(
                 &directory_names,
                 &window.get_sieve_directory_names(),
             )),
+            sieve_template: window.get_sieve_template().to_string(),
+            rename_pattern: window.get_rename_pattern().to_string(),
             dark_mode: window.get_dark_mode().to_string(),
             is_maximized: window.window().is_maximized(),
             position: {
@@ -77,6 +191,13 @@ impl Settings {
                 (size.width, size.height)
             },
             last_selected_image: String::new(),
+            deletion_policy: model_to_enum(&deletion_policies, &window.get_deletion_policy()),
+            quarantine_directory: window.get_quarantine_directory().to_string(),
+            quarantine_retention_days: window
+                .get_quarantine_retention_days()
+                .parse::<u32>()
+                .unwrap_or(quarantine_retention_days_default()),
+            motion_photos: model_to_enum(&motion_photos, &window.get_motion_photos()),
         }
     }
 
@@ -91,6 +212,11 @@ impl Settings {
         window.set_similarity_sensitivity(SharedString::from(convert_u32_to_sensitivity(
             self.hash_max_diff,
         )));
+        let hash_algorithms: ModelRc<SharedString> =
+            window.global::<SettingsComboValues>().get_hash_algorithms();
+        window.set_hash_algorithm(enum_to_model(&hash_algorithms, &self.hash_algorithm));
+        window.set_hash_size(SharedString::from(self.hash_size.to_string()));
+        window.set_similarity_threshold(SharedString::from(self.similarity_threshold.to_string()));
         let directory_names: ModelRc<SharedString> =
             window.global::<SieveComboValues>().get_directory_names();
         let directory_name = self
@@ -98,6 +224,29 @@ impl Settings {
             .as_ref()
             .unwrap_or(&DirectoryNames::YearAndMonth);
         window.set_sieve_directory_names(enum_to_model(&directory_names, directory_name));
+        window.set_sieve_template(SharedString::from(self.sieve_template.clone()));
+        window.set_sieve_template_error(SharedString::from(
+            self.sieve_template
+                .parse::<PathTemplate>()
+                .err()
+                .unwrap_or_default(),
+        ));
+        window.set_rename_pattern(SharedString::from(self.rename_pattern.clone()));
+        window.set_rename_pattern_error(SharedString::from(
+            PathTemplate::check_rename_pattern(&self.rename_pattern)
+                .err()
+                .unwrap_or_default(),
+        ));
+        let deletion_policies: ModelRc<SharedString> =
+            window.global::<SieveComboValues>().get_deletion_policies();
+        window.set_deletion_policy(enum_to_model(&deletion_policies, &self.deletion_policy));
+        window.set_quarantine_directory(SharedString::from(self.quarantine_directory.clone()));
+        window.set_quarantine_retention_days(SharedString::from(
+            self.quarantine_retention_days.to_string(),
+        ));
+        let motion_photos: ModelRc<SharedString> =
+            window.global::<SieveComboValues>().get_motion_photos();
+        window.set_motion_photos(enum_to_model(&motion_photos, &self.motion_photos));
         window.set_dark_mode(SharedString::from(self.dark_mode.clone()));
         window.window().set_maximized(self.is_maximized);
         window.window().set_position(PhysicalPosition {
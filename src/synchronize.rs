@@ -1,34 +1,41 @@
-use crate::item_sort_list::ItemList;
+use crate::item_sort_list::{FileItem, HashConfig, ItemList, STAGING_DIRECTORY, SimilarityConfig};
 use crate::persistence::settings::Settings;
 use image_23::GenericImageView;
-use img_hash::HashAlg;
-use img_hash::Hasher;
-use img_hash::HasherConfig;
 use img_hash::ImageHash;
 use slint::ComponentHandle;
 use slint::SharedString;
 use walkdir::WalkDir;
 
 use crate::main_window::ImageSieve;
+use crate::misc::quality::{self, Quality};
+use crate::misc::{images, video_to_image};
 use crate::persistence::json::get_project_filename;
 use crate::persistence::json::JsonPersistence;
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use std::sync::Mutex;
 
+/// Maximum width and height of the image or the combined frames of a video a hash is calculated from
+const HASH_IMAGE_SIZE: u32 = 480;
+
 /// Combined path and settings used to send changes to the synchronize thread.
 enum Command {
     Stop,
     Scan(PathBuf),
     Similarities(Settings),
+    KeepBestShots,
 }
 
 /// Synchronize the item list with the state of the file system and calculate similarities in a background thread.
 pub struct Synchronizer {
     channel: Sender<Command>,
+    /// Flag to cancel the command that is currently processed
+    cancel: Arc<AtomicBool>,
 }
 
 impl Synchronizer {
@@ -36,13 +43,15 @@ impl Synchronizer {
     /// set the resulting states in the ImageSieve window
     pub fn new(item_list: Arc<Mutex<ItemList>>, image_sieve: &ImageSieve) -> Self {
         let (channel, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
         std::thread::spawn({
             let handle_weak = image_sieve.as_weak();
+            let cancel = cancel.clone();
             move || {
-                synchronize_run(item_list, &receiver, handle_weak);
+                synchronize_run(item_list, &receiver, &cancel, handle_weak);
             }
         });
-        Self { channel }
+        Self { channel, cancel }
     }
 
     /// Perform synchronization of the item list with a given path in a background thread.
@@ -56,8 +65,19 @@ impl Synchronizer {
         self.channel.send(Command::Similarities(settings)).ok();
     }
 
-    /// Stop the current synchronization process
+    /// Keep the best shot of each group of similar items and discard the others in a background thread.
+    pub fn keep_best_shots(&self) {
+        self.channel.send(Command::KeepBestShots).ok();
+    }
+
+    /// Cancel the current synchronization process, commands sent afterwards are still processed
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Stop the current synchronization process and the synchronization thread
     pub fn stop(&self) {
+        self.cancel();
         self.channel.send(Command::Stop).ok();
     }
 }
@@ -77,11 +97,15 @@ impl Drop for Synchronizer {
 fn synchronize_run(
     item_list: Arc<Mutex<ItemList>>,
     receiver: &Receiver<Command>,
+    cancel: &AtomicBool,
     image_sieve: slint::Weak<ImageSieve>,
 ) {
     for command in receiver {
-        // In any case, reset similarities first
-        {
+        // A cancel request only applies to the command that was processed when it was made
+        cancel.store(false, Ordering::Relaxed);
+
+        // Reset similarities first unless they are used by the command
+        if !matches!(command, Command::KeepBestShots) {
             let mut item_list_loc = item_list.lock().unwrap();
             for item in &mut item_list_loc.items {
                 item.reset_similars();
@@ -91,7 +115,16 @@ fn synchronize_run(
         match command {
             Command::Stop => break,
             Command::Scan(path) => {
-                if scan_files(&path, item_list.clone(), &image_sieve, receiver).is_err() {
+                let result = {
+                    let mut item_list_loc = item_list.lock().unwrap();
+                    scan_files(
+                        &path,
+                        &mut item_list_loc,
+                        |progress| report_progress(&image_sieve, progress),
+                        || check_abort(cancel),
+                    )
+                };
+                if result.is_err() {
                     let mut item_list_loc = item_list.lock().unwrap();
                     item_list_loc.items.clear();
                 }
@@ -114,11 +147,26 @@ fn synchronize_run(
 
                 // Then, if enabled, find similars based on hashes. This takes some time.
                 if settings.use_hash {
-                    calculate_similar_hashes(item_list.clone(), &settings);
+                    calculate_similar_hashes(
+                        item_list.clone(),
+                        &settings,
+                        |progress| report_similarities_progress(&image_sieve, progress),
+                        || check_abort(cancel),
+                    )
+                    .ok();
                     // Finally, update the GUI again with the new found similarities
                     similarities_calculated(&image_sieve, true);
                 }
             }
+            Command::KeepBestShots => {
+                keep_best_shots(
+                    item_list.clone(),
+                    |progress| report_similarities_progress(&image_sieve, progress),
+                    || check_abort(cancel),
+                )
+                .ok();
+                similarities_calculated(&image_sieve, true);
+            }
         };
     }
 }
@@ -135,96 +183,144 @@ fn similarities_calculated(image_sieve: &slint::Weak<ImageSieve>, finished: bool
         .unwrap();
 }
 
-/// Scan files in a path, update the item list with those found files and update the GUI models with the new data
-fn scan_files(
+/// Scan files in a path and update the item list with those found files. The progress is reported by calling a
+/// callback function and the scan is aborted as soon as the abort check returns an error.
+pub fn scan_files(
     path: &Path,
-    item_list: Arc<Mutex<ItemList>>,
-    image_sieve: &slint::Weak<ImageSieve>,
-    receiver: &Receiver<Command>,
+    item_list: &mut ItemList,
+    progress_callback: impl Fn(String),
+    check_abort: impl Fn() -> Result<(), ()>,
 ) -> Result<(), ()> {
-    let mut item_list_loc = item_list.lock().unwrap();
-
-    item_list_loc.items.clear();
+    item_list.items.clear();
 
-    report_progress(image_sieve, String::from("Checking existing project..."));
-    check_abort(receiver)?;
+    progress_callback(String::from("Checking existing project..."));
+    check_abort()?;
     // Check if folder already contains an item list
     let loaded_item_list: Option<ItemList> = JsonPersistence::load(&get_project_filename(path));
     if let Some(loaded_item_list) = loaded_item_list {
-        item_list_loc.clone_from(&loaded_item_list);
-        item_list_loc.events.sort_unstable();
+        item_list.clone_from(&loaded_item_list);
+        item_list.events.sort_unstable();
     }
 
-    if !item_list_loc.items.is_empty() {
-        report_progress(image_sieve, String::from("Checking existing files..."));
-        check_abort(receiver)?;
+    if !item_list.items.is_empty() {
+        progress_callback(String::from("Checking existing files..."));
+        check_abort()?;
         // First, drain missing files
-        item_list_loc.drain_missing();
+        item_list.drain_missing();
     }
 
     // Now, walk dirs and synchronize each
-    for (file_counter, entry) in WalkDir::new(path).into_iter().flatten().enumerate() {
+    // Files that were deleted by a sieve process are kept in the staging directory and are skipped
+    for (file_counter, entry) in WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != STAGING_DIRECTORY)
+        .flatten()
+        .enumerate()
+    {
         if file_counter % 100 == 0 {
-            report_progress(image_sieve, format!("Searching {}", entry.path().display()));
+            progress_callback(format!("Searching {}", entry.path().display()));
         }
-        check_abort(receiver)?;
-        item_list_loc.check_and_add(entry.path());
+        check_abort()?;
+        item_list.check_and_add(entry.path());
     }
 
-    item_list_loc.finish_synchronizing(path);
+    item_list.finish_synchronizing(path);
     Ok(())
 }
 
-/// Check if an abort command was received
-fn check_abort(receiver: &Receiver<Command>) -> Result<(), ()> {
-    let command = receiver.try_recv();
-    if let Ok(Command::Stop) = command {
+/// Check if the current command was cancelled
+fn check_abort(cancel: &AtomicBool) -> Result<(), ()> {
+    if cancel.load(Ordering::Relaxed) {
         Err(())
     } else {
         Ok(())
     }
 }
 
-/// Extract the timestamp from all items in the item list and find similar items based on a maximum difference.
-/// Afterwards, the GUI is updated with the new found similarities.
+/// Find similar items based on the timestamps only, since the hashes are not yet calculated. Afterwards, the GUI is
+/// updated with the new found similarities.
 fn calculate_similar_timestamps(item_list: Arc<Mutex<ItemList>>, settings: &Settings) {
     {
         let mut item_list_loc = item_list.lock().unwrap();
-        item_list_loc.find_similar(settings.timestamp_max_diff);
+        item_list_loc.find_similar(&SimilarityConfig {
+            hash_max_diff: None,
+            ..settings.similarity_config()
+        });
     }
 }
 
-/// Calculate the similarity hashes of images in the item list and check for hashes with a given maximum distance. Does not update the GUI
-fn calculate_similar_hashes(item_list: Arc<Mutex<ItemList>>, settings: &Settings) {
-    // Collect file names which need to be hashed (those that are images and have no stored hash yet)
-    let mut image_file_names: Vec<PathBuf> = Vec::new();
+/// Calculate the similarity hashes of images in the item list and find similar items by scoring their timestamps,
+/// hashes and metadata. The hashes are calculated by a pool of worker threads, the progress is reported for every
+/// file by calling a callback function and the calculation is aborted as soon as the abort check returns an error.
+/// Hashes calculated up to then are kept. Does not update the GUI
+pub fn calculate_similar_hashes(
+    item_list: Arc<Mutex<ItemList>>,
+    settings: &Settings,
+    progress_callback: impl Fn(String),
+    check_abort: impl Fn() -> Result<(), ()>,
+) -> Result<(), ()> {
+    // Collect items which need to be hashed (those that have no stored hash yet or one calculated with a different
+    // configuration)
+    let hash_config = settings.hash_config();
+    let mut hash_items: Vec<FileItem> = Vec::new();
     {
-        let item_list_loc = item_list.lock().unwrap();
-        for item in &item_list_loc.items {
-            if (item.is_image() || item.is_raw_image()) && !item.has_hash() {
-                image_file_names.push(item.path.clone());
+        let mut item_list_loc = item_list.lock().unwrap();
+        for item in &mut item_list_loc.items {
+            item.invalidate_hash(&hash_config);
+            if !item.has_hash() {
+                hash_items.push(item.clone());
             }
         }
     }
 
-    // Now calculate the hashes
+    // Now calculate the hashes, each worker takes the next item until all are done or the calculation is aborted
     let mut hashes: HashMap<PathBuf, ImageHash<Vec<u8>>> = HashMap::new();
-    for image_file_name in image_file_names {
-        if let Ok(image) = image_23::open(&image_file_name) {
-            // The hash size is dependent on the image orientation to increase the result quality
-            let (hash_width, hash_height) = if image.width() > image.height() {
-                (16, 8)
-            } else {
-                (8, 16)
-            };
-            // We are using the double gradient algorithm
-            let hasher: Hasher<Vec<u8>> = HasherConfig::with_bytes_type()
-                .hash_size(hash_width, hash_height)
-                .hash_alg(HashAlg::DoubleGradient)
-                .to_hasher();
-            hashes.insert(image_file_name, hasher.hash_image(&image));
+    let next_index = AtomicUsize::new(0);
+    let aborted = AtomicBool::new(false);
+    let workers = std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+        .min(hash_items.len());
+    let result = std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (hash_items, hash_config, next_index, aborted) =
+                (&hash_items, &hash_config, &next_index, &aborted);
+            scope.spawn(move || {
+                while !aborted.load(Ordering::Relaxed) {
+                    let index = next_index.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = hash_items.get(index) else {
+                        break;
+                    };
+                    if sender
+                        .send((&item.path, calculate_hash(item, &hash_config)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
         }
-    }
+        drop(sender);
+
+        for (counter, (path, hash)) in receiver.iter().enumerate() {
+            progress_callback(format!(
+                "{}/{} {}",
+                counter + 1,
+                hash_items.len(),
+                path.display()
+            ));
+            if let Some(hash) = hash {
+                hashes.insert(path.clone(), hash);
+            }
+            if check_abort().is_err() {
+                aborted.store(true, Ordering::Relaxed);
+                return Err(());
+            }
+        }
+        Ok(())
+    });
 
     // Update the items with the new calculated hashes and update the similarities
     {
@@ -232,11 +328,70 @@ fn calculate_similar_hashes(item_list: Arc<Mutex<ItemList>>, settings: &Settings
         for item in &mut item_list_loc.items {
             let hash = hashes.remove(&item.path);
             if let Some(hash) = hash {
-                item.set_hash(hash);
+                item.set_hash(hash, hash_config);
             }
         }
-        item_list_loc.find_similar_hashes(settings.hash_max_diff);
+        if result.is_ok() {
+            item_list_loc.find_similar(&settings.similarity_config());
+        }
+    }
+    result
+}
+
+/// Calculate the quality of all images in each group of similar items, keep the best image of each group and discard
+/// the other images. Videos are left unchanged. The progress is reported for every group by calling a callback
+/// function and the process is aborted as soon as the abort check returns an error. Returns the number of groups
+/// the best image was selected in.
+pub fn keep_best_shots(
+    item_list: Arc<Mutex<ItemList>>,
+    progress_callback: impl Fn(String),
+    check_abort: impl Fn() -> Result<(), ()>,
+) -> Result<usize, ()> {
+    let groups = item_list.lock().unwrap().similar_groups();
+    let mut selected = 0;
+    for (counter, group) in groups.iter().enumerate() {
+        progress_callback(format!("Selecting best shots {}/{}", counter + 1, groups.len()));
+        check_abort()?;
+        let items: Vec<(usize, FileItem)> = {
+            let item_list_loc = item_list.lock().unwrap();
+            group
+                .iter()
+                .map(|index| (*index, item_list_loc.items[*index].clone()))
+                .collect()
+        };
+        let (indices, qualities): (Vec<usize>, Vec<Quality>) = items
+            .iter()
+            .filter_map(|(index, item)| Some((*index, quality::calculate_quality(item)?)))
+            .unzip();
+        if indices.len() > 1
+            && let Some(best) = quality::best_quality(&qualities)
+        {
+            item_list
+                .lock()
+                .unwrap()
+                .keep_best(&indices, indices[best]);
+            selected += 1;
+        }
     }
+    Ok(selected)
+}
+
+/// Calculate the similarity hash of an image or a video using a hash configuration, None is returned if the file
+/// cannot be decoded. Images of all supported formats are decoded the same way as for displaying them, the hash of a
+/// video is calculated from an image combining frames sampled over its whole duration.
+fn calculate_hash(item: &FileItem, hash_config: &HashConfig) -> Option<ImageHash<Vec<u8>>> {
+    let buffer = if item.is_video() {
+        video_to_image::get_frames_image(item, HASH_IMAGE_SIZE, HASH_IMAGE_SIZE)
+    } else {
+        images::load_image_buffer(item, HASH_IMAGE_SIZE, HASH_IMAGE_SIZE)
+    }?;
+    let image = image_23::DynamicImage::ImageRgba8(image_23::RgbaImage::from_raw(
+        buffer.width(),
+        buffer.height(),
+        buffer.into_raw(),
+    )?);
+    let hasher = hash_config.hasher(image.width() > image.height());
+    Some(hasher.hash_image(&image))
 }
 
 /// Report a progress string back to the main window
@@ -250,3 +405,187 @@ fn report_progress(image_sieve: &slint::Weak<ImageSieve>, progress: String) {
         })
         .unwrap();
 }
+
+/// Report the progress of the similarity calculation back to the main window
+fn report_similarities_progress(image_sieve: &slint::Weak<ImageSieve>, progress: String) {
+    image_sieve
+        .clone()
+        .upgrade_in_event_loop({
+            move |h| {
+                h.set_similarities_progress(SharedString::from(progress));
+            }
+        })
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_sort_list::{HashAlgorithm, MAX_SCORE, Similar};
+    use std::cell::Cell;
+
+    fn test_item_list() -> Arc<Mutex<ItemList>> {
+        Arc::new(Mutex::new(ItemList {
+            items: vec![
+                FileItem::dummy("tests/test.jpg", 0, true),
+                FileItem::dummy("tests/test2.JPG", 0, true),
+                FileItem::dummy("tests/subdir/test.jpg", 0, true),
+                FileItem::dummy("tests/missing.jpg", 0, true),
+            ],
+            events: vec![],
+            path: PathBuf::from(""),
+            clock_offsets: vec![],
+            write_metadata: false,
+        }))
+    }
+
+    #[test]
+    fn test_calculate_similar_hashes() {
+        let item_list = test_item_list();
+        let progress = Cell::new(0);
+        let mut settings = Settings::new();
+        settings.use_hash = true;
+        settings.hash_max_diff = 1;
+        settings.use_timestamps = false;
+
+        let result = calculate_similar_hashes(
+            item_list.clone(),
+            &settings,
+            |_| progress.set(progress.get() + 1),
+            || Ok(()),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(progress.get(), 4);
+        let item_list = item_list.lock().unwrap();
+        assert!(item_list.items[0].has_hash());
+        assert!(item_list.items[1].has_hash());
+        assert!(item_list.items[2].has_hash());
+        assert!(!item_list.items[3].has_hash());
+        assert!(
+            item_list.items[0]
+                .get_similars()
+                .iter()
+                .any(|similar| similar.index == 2)
+        );
+        assert!(
+            item_list.items[2]
+                .get_similars()
+                .iter()
+                .any(|similar| similar.index == 0)
+        );
+        assert!(item_list.items[3].get_similars().is_empty());
+    }
+
+    #[test]
+    fn test_hash_config_changed() {
+        let item_list = test_item_list();
+        let progress = Cell::new(0);
+        let mut settings = Settings::new();
+        let mut calculate = |settings: &Settings| {
+            progress.set(0);
+            calculate_similar_hashes(
+                item_list.clone(),
+                settings,
+                |_| progress.set(progress.get() + 1),
+                || Ok(()),
+            )
+            .unwrap();
+            progress.get()
+        };
+
+        assert_eq!(calculate(&settings), 4);
+        // Only the missing file is tried again
+        assert_eq!(calculate(&settings), 1);
+        settings.hash_algorithm = HashAlgorithm::Dct;
+        assert_eq!(calculate(&settings), 4);
+        settings.hash_size = 8;
+        assert_eq!(calculate(&settings), 4);
+        assert_eq!(calculate(&settings), 1);
+    }
+
+    fn hash(path: &str) -> Option<ImageHash<Vec<u8>>> {
+        calculate_hash(&FileItem::dummy(path, 0, true), &HashConfig::default())
+    }
+
+    #[test]
+    fn test_image_hash() {
+        assert!(hash("tests/test.jpg").is_some());
+        assert!(hash("tests/test.jxl").is_some());
+        assert!(hash("tests/test.nef").is_some());
+        assert!(hash("tests/test_invalid.jpg").is_none());
+    }
+
+    #[test]
+    fn test_heif_hash() {
+        assert!(hash("tests/test.heif").is_some());
+    }
+
+    #[test]
+    fn test_video_hash() {
+        assert!(hash("tests/test.mp4").is_some());
+        assert_eq!(hash("tests/test.mp4"), hash("tests/test.mp4"));
+        assert!(hash("tests/test_invalid.mp4").is_none());
+    }
+
+    #[test]
+    fn test_keep_best_shots() {
+        let item_list = test_item_list();
+        {
+            let mut item_list = item_list.lock().unwrap();
+            let similar = |index| Similar {
+                index,
+                score: MAX_SCORE,
+            };
+            item_list.items[0].set_similars(vec![similar(1), similar(3)]);
+            item_list.items[1].set_similars(vec![similar(0)]);
+            item_list.items[3].set_similars(vec![similar(0)]);
+        }
+
+        assert_eq!(keep_best_shots(item_list.clone(), |_| {}, || Err(())), Err(()));
+        let result = keep_best_shots(item_list.clone(), |_| {}, || Ok(()));
+
+        assert_eq!(result, Ok(1));
+        let item_list = item_list.lock().unwrap();
+        assert_ne!(
+            item_list.items[0].get_take_over(),
+            item_list.items[1].get_take_over()
+        );
+        assert!(item_list.items[2].get_take_over());
+        // The missing file cannot be rated and is kept
+        assert!(item_list.items[3].get_take_over());
+    }
+
+    #[test]
+    fn test_check_abort() {
+        let cancel = AtomicBool::new(false);
+        assert!(check_abort(&cancel).is_ok());
+        cancel.store(true, Ordering::Relaxed);
+        // The flag stays set, so every later check of the same command fails as well
+        assert!(check_abort(&cancel).is_err());
+        assert!(check_abort(&cancel).is_err());
+    }
+
+    #[test]
+    fn test_abort_similar_hashes() {
+        let item_list = test_item_list();
+        let progress = Cell::new(0);
+
+        let result = calculate_similar_hashes(
+            item_list.clone(),
+            &Settings::new(),
+            |_| progress.set(progress.get() + 1),
+            || Err(()),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(progress.get(), 1);
+        let item_list = item_list.lock().unwrap();
+        assert!(
+            item_list
+                .items
+                .iter()
+                .all(|item| item.get_similars().is_empty())
+        );
+    }
+}